@@ -1,5 +1,9 @@
+use std::borrow::Borrow;
+use std::collections::HashSet;
 use std::fmt;
-use std::collections::HashMap;
+use std::sync::Arc;
+use indexmap::IndexMap;
+use crate::error::ErdError;
 
 pub const OPT_COLOR: &str = "color";
 pub const OPT_LABEL: &str = "label";
@@ -7,71 +11,763 @@ pub const OPT_SIZE: &str = "size";
 pub const OPT_FONT: &str = "font";
 pub const OPT_BACKGROUND_COLOR: &str = "bgcolor";
 pub const OPT_BORDER_COLOR: &str = "border-color";
+pub const OPT_HEAD_PORT: &str = "head-port";
+pub const OPT_TAIL_PORT: &str = "tail-port";
 pub const OPT_BORDER: &str = "border";
+pub const OPT_TYPE: &str = "type";
+pub const OPT_UNIQUE: &str = "unique";
+pub const OPT_NULLABLE: &str = "nullable";
+pub const OPT_PRIMARY_KEY: &str = "pk";
+pub const OPT_COLUMNS: &str = "columns";
+pub const OPT_DESCRIPTION: &str = "description";
+pub const OPT_IDENTIFYING: &str = "identifying";
+pub const OPT_ATTRIBUTES: &str = "attributes";
+pub const OPT_VALUES: &str = "values";
+pub const OPT_ATTACHED: &str = "attached";
+pub const OPT_REFERENCES: &str = "references";
+pub const OPT_TEXT_ALIGNMENT: &str = "text-alignment";
+pub const OPT_NOTATION: &str = "notation";
+pub const OPT_STYLE: &str = "style";
+pub const OPT_GROUP: &str = "group";
+pub const OPT_URL: &str = "url";
+pub const OPT_RANKDIR: &str = "rankdir";
+pub const OPT_SPLINES: &str = "splines";
+pub const OPT_LAYOUT: &str = "layout";
+pub const OPT_MINLEN: &str = "minlen";
+pub const OPT_PENWIDTH: &str = "penwidth";
+
+/// Every option key, as written canonically.
+const OPTION_KEYS: &[&str] = &[
+    OPT_COLOR, OPT_LABEL, OPT_SIZE, OPT_FONT, OPT_BACKGROUND_COLOR, OPT_BORDER_COLOR,
+    OPT_HEAD_PORT, OPT_TAIL_PORT, OPT_BORDER, OPT_TYPE, OPT_UNIQUE, OPT_PRIMARY_KEY,
+    OPT_COLUMNS, OPT_DESCRIPTION, OPT_IDENTIFYING, OPT_ATTRIBUTES, OPT_VALUES,
+    OPT_ATTACHED, OPT_REFERENCES, OPT_TEXT_ALIGNMENT, OPT_NOTATION, OPT_STYLE,
+    OPT_MINLEN, OPT_PENWIDTH, OPT_GROUP, OPT_RANKDIR, OPT_SPLINES,
+    OPT_LAYOUT, OPT_NULLABLE, OPT_URL,
+];
+
+/// Other names for option keys, such as those used by Graphviz, normalised
+/// as by [`canonical_key`].
+const OPTION_ALIASES: &[(&str, &str)] = &[
+    ("backgroundcolor", OPT_BACKGROUND_COLOR),
+    ("fontname", OPT_FONT),
+    ("fontsize", OPT_SIZE),
+    ("fontcolor", OPT_COLOR),
+    ("primarykey", OPT_PRIMARY_KEY),
+    ("href", OPT_URL),
+];
+
+/// Returns the canonical spelling of an option key, ignoring case, `-` and
+/// `_`, so `bgColor`, `background-color` and `BGCOLOR` are all `bgcolor`.
+/// Unknown keys are returned as given.
+pub fn canonical_key(key: &str) -> &str {
+    if OPTION_KEYS.contains(&key) {
+        return key;
+    }
+    let normalised: String = key.chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect();
+    OPTION_KEYS.iter()
+        .map(|k| (*k, *k))
+        .chain(OPTION_ALIASES.iter().copied())
+        .find(|(alias, _)| alias.chars().filter(|c| *c != '-').eq(normalised.chars()))
+        .map(|(_, k)| k)
+        .unwrap_or(key)
+}
+
+/// Option key/value pairs, kept in the order they were declared.
+pub type OptionMap = IndexMap<String, String>;
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Erd {
     pub entities: Vec<Entity>,
     pub relationships: Vec<Relation>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub nary_relationships: Vec<NaryRelation>,
+    /// Enumerated types declared with `enum`, in declaration order.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub enums: Vec<Enum>,
+    /// Annotations declared with `note`, in declaration order.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub notes: Vec<Note>,
     pub title_options: TitleOptions,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub graph_options: GraphOptions,
+    /// Paths given by `include` declarations, when parsed without resolving
+    /// them. Resolved includes are replaced by the contents of the files.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub includes: Vec<String>,
+    /// Variables given by `set` declarations, with their values. References
+    /// to them in option values have already been replaced.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub variables: IndexMap<String, String>,
+    /// Options with unknown keys, which were skipped rather than failing
+    /// because the input was parsed leniently.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub ignored_options: Vec<IgnoredOption>,
+}
+
+/// An option with an unknown key, skipped when parsing leniently.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IgnoredOption {
+    /// Kind of element the option was given on, e.g. `entity`.
+    pub scope: &'static str,
+    pub key: String,
+    /// Location of the declaration the option was given on, or of the
+    /// directive it was given in.
+    pub span: Span,
+}
+
+impl Erd {
+    /// Returns the entity with the given name.
+    pub fn entity(&self, name: &str) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.name == name)
+    }
+
+    /// Makes names with the same text share one copy, so that memory use
+    /// doesn't grow with how often each is repeated. Parsing does this
+    /// already; it's only needed after building or modifying a diagram by
+    /// other means.
+    pub fn intern(&mut self) {
+        let mut names = Interner::default();
+        for e in &mut self.entities {
+            names.intern(&mut e.name);
+            for a in &mut e.attribs {
+                names.intern(&mut a.field);
+            }
+        }
+        for r in &mut self.relationships {
+            names.intern(&mut r.entity1);
+            names.intern(&mut r.entity2);
+            for a in &mut r.attribs {
+                names.intern(&mut a.field);
+            }
+        }
+        for r in &mut self.nary_relationships {
+            for e in &mut r.entities {
+                names.intern(e);
+            }
+        }
+    }
+
+    /// Returns the entity with the given name, ignoring case.
+    pub fn entity_ignore_case(&self, name: &str) -> Option<&Entity> {
+        self.entities.iter().find(|e| eq_ignore_case(&e.name, name))
+    }
+
+    /// Returns the entity with the given name, for modification.
+    pub fn entity_mut(&mut self, name: &str) -> Option<&mut Entity> {
+        self.entities.iter_mut().find(|e| e.name == name)
+    }
+
+    /// Returns the relationship with the given name.
+    pub fn relationship(&self, name: &str) -> Option<&Relation> {
+        self.relationships.iter().find(|r| r.name.as_deref() == Some(name))
+    }
+
+    /// Returns the enum with the given name.
+    pub fn enumeration(&self, name: &str) -> Option<&Enum> {
+        self.enums.iter().find(|e| e.name == name)
+    }
+
+    /// Returns relationships implied by attributes referring to another
+    /// entity's field, from the referred to entity to the referring one,
+    /// attached at the two fields' ports. Entities already joined by a
+    /// declared relationship, and references to undeclared entities, are
+    /// skipped.
+    pub fn implied_relationships(&self) -> Vec<Relation> {
+        let mut implied = Vec::new();
+        for e in &self.entities {
+            for a in &e.attribs {
+                let target = match &a.references {
+                    Some(r) if self.entity(&r.entity).is_some() => r,
+                    _ => continue,
+                };
+                let declared = self.relationships_for(&e.name)
+                    .any(|r| r.entity1 == target.entity || r.entity2 == target.entity);
+                if declared {
+                    continue;
+                }
+                implied.push(Relation {
+                    name: None,
+                    entity1: target.entity.as_str().into(),
+                    entity2: e.name.clone(),
+                    // Rows whose key is null don't refer to anything.
+                    card1: if a.nullable { Cardinality::ZeroOne } else { Cardinality::One },
+                    card2: if a.unique { Cardinality::ZeroOne } else { Cardinality::ZeroPlus },
+                    kind: RelationKind::Association,
+                    identifying: e.is_primary_key(a),
+                    attribs: Vec::new(),
+                    options: RelationshipOptions {
+                        tail_port: Some(target.field.clone()),
+                        head_port: Some(a.field.to_string()),
+                        ..Default::default()
+                    },
+                    span: a.span,
+                });
+            }
+        }
+        implied
+    }
+
+    /// Returns relationships which have the named entity at either end.
+    pub fn relationships_for<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Relation> + 'a {
+        self.relationships.iter().filter(move |r| r.entity1 == name || r.entity2 == name)
+    }
+
+    /// Returns relationships which have the named entity at either end,
+    /// ignoring case.
+    pub fn relationships_for_ignore_case<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Relation> + 'a {
+        self.relationships.iter()
+            .filter(move |r| eq_ignore_case(&r.entity1, name) || eq_ignore_case(&r.entity2, name))
+    }
+}
+
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars().flat_map(char::to_lowercase).eq(b.chars().flat_map(char::to_lowercase))
+}
+
+/// Location of a declaration in the source text.
+///
+/// Spans are metadata: any two spans compare equal, so nodes compare by what
+/// they declare rather than where. Nodes not produced by the parser have a
+/// default span, with a line of 0.
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    /// Byte offset of the start of the declaration.
+    pub start: usize,
+    /// Byte offset just past the end of the declaration.
+    pub end: usize,
+    /// Line the declaration starts on, from 1.
+    pub line: usize,
+    /// Byte column the declaration starts at, from 1.
+    pub column: usize,
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Span) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+/// Name of an entity or attribute.
+///
+/// Names are shared rather than copied when cloned, and a parsed diagram
+/// holds a single copy of each distinct name however often it's used (see
+/// [`Erd::intern`]). A name derefs to `str` and compares equal to strings
+/// with the same text.
+#[derive(Clone, Default, PartialOrd, Ord)]
+pub struct Name(Arc<str>);
+
+impl Name {
+    pub fn new(name: &str) -> Self {
+        Self(Arc::from(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::ops::Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Name {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Eq for Name {}
+
+impl std::hash::Hash for Name {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Name {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Name {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<Name> for str {
+    fn eq(&self, other: &Name) -> bool {
+        self == &*other.0
+    }
+}
+
+impl PartialEq<Name> for &str {
+    fn eq(&self, other: &Name) -> bool {
+        *self == &*other.0
+    }
+}
+
+impl PartialEq<Name> for String {
+    fn eq(&self, other: &Name) -> bool {
+        **self == *other.0
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Self {
+        Self(Arc::from(name))
+    }
+}
+
+impl From<&String> for Name {
+    fn from(name: &String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<Name> for String {
+    fn from(name: Name) -> Self {
+        name.0.to_string()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Name {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Name {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Name::from)
+    }
+}
+
+/// Names already seen, so that each distinct one is stored once.
+#[derive(Default)]
+struct Interner(HashSet<Name>);
+
+impl Interner {
+    fn intern(&mut self, name: &mut Name) {
+        match self.0.get(name.as_str()) {
+            Some(existing) => *name = existing.clone(),
+            None => {
+                self.0.insert(name.clone());
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Ast {
     Entity(Entity),
     Attribute(Attribute),
+    Index(Index),
     Relation(Relation),
+    NaryRelation(NaryRelation),
+    Enum(Enum),
+    Note(Note),
     GlobalOption(GlobalOption),
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Entity {
-    pub name: String,
+    pub name: Name,
     pub attribs: Vec<Attribute>,
     pub options: EntityOptions,
     pub header_options: HeaderOptions,
+    /// Fields which together make up the primary key, set with the `pk`
+    /// option, e.g. `[line_item] {pk: "order_id, line_no"}`. Attributes
+    /// marked with `*` are part of the key as well.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub primary_key: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub indexes: Vec<Index>,
+    /// What the entity represents, set with the `description` option.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub description: Option<String>,
+    /// Name of the group the entity is drawn in, along with the others in
+    /// it, set with the `group` option.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub group: Option<String>,
+    /// Link followed when the entity is clicked in SVG output, such as its
+    /// documentation page, set with the `url` (or `href`) option.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub url: Option<String>,
+    /// Whether the entity is weak, i.e. identified through a relationship
+    /// to its owner rather than by its own attributes. Declared with
+    /// `[[name]]`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub weak: bool,
+    /// Location of the `[name]` line, and the block of attributes following
+    /// it if they're given in one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
 }
 
 impl Entity {
     pub fn add_attribute(&mut self, attr: Attribute) {
         self.attribs.push(attr)
     }
+
+    /// Returns the attribute with the given field name.
+    pub fn attribute(&self, field: &str) -> Option<&Attribute> {
+        self.attribs.iter().find(|a| a.field == field)
+    }
+
+    /// Returns a copy of the entity without its attributes, for comparing
+    /// everything else about it.
+    pub(crate) fn definition(&self) -> Entity {
+        Entity { attribs: Vec::new(), ..self.clone() }
+    }
+
+    /// Returns whether the attribute is part of the primary key, either
+    /// marked with `*` or listed in the `pk` option.
+    pub fn is_primary_key(&self, a: &Attribute) -> bool {
+        a.pk || self.primary_key.iter().any(|k| *k == a.field)
+    }
+
+    /// Returns the attribute with the given field name, ignoring case.
+    pub fn attribute_ignore_case(&self, field: &str) -> Option<&Attribute> {
+        self.attribs.iter().find(|a| eq_ignore_case(&a.field, field))
+    }
 }
 
 #[derive(Clone, Default, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Attribute {
-    pub field: String,
+    pub field: Name,
     pub pk: bool,
     pub fk: bool,
+    /// Whether values must be distinct, set with the `unique` option.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unique: bool,
+    /// Whether the attribute may have no value, set with the `nullable`
+    /// option.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub nullable: bool,
+    /// Column type, e.g. `varchar(255)`, set with the `type` option.
+    pub datatype: Option<String>,
+    /// What the attribute holds, set with the `description` option.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub description: Option<String>,
+    /// Field the attribute is a foreign key to, written e.g.
+    /// `+customer_id -> customers.id`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub references: Option<Reference>,
     pub options: AttributeOptions,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
 }
 
 impl Attribute {
-    pub fn with_field<S: Into<String>>(field: S) -> Self {
+    pub fn with_field<S: Into<Name>>(field: S) -> Self {
         Self {
             field: field.into(),
             pk: false,
             fk: false,
+            unique: false,
+            nullable: false,
+            datatype: None,
+            description: None,
+            references: None,
             options: AttributeOptions::default(),
+            span: Span::default(),
+        }
+    }
+}
+
+/// A field of another entity, referred to by a foreign key.
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reference {
+    pub entity: String,
+    pub field: String,
+}
+
+impl Reference {
+    /// Parses a `references` option value, written `entity.field`.
+    pub fn parse(value: &str) -> Result<Self, ErdError> {
+        match value.rsplit_once('.') {
+            Some((entity, field)) if !entity.is_empty() && !field.is_empty() => Ok(Self {
+                entity: entity.to_owned(),
+                field: field.to_owned(),
+            }),
+            _ => Err(ErdError::InvalidOptionValue {
+                key: OPT_REFERENCES.to_owned(),
+                value: value.to_owned(),
+                expected: "entity.field",
+            }),
+        }
+    }
+}
+
+/// An index over some of an entity's fields, declared among its attributes
+/// with e.g. `index {columns: "last_name, first_name", unique: "true"}`.
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Index {
+    pub columns: Vec<String>,
+    pub unique: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
+}
+
+impl Index {
+    pub fn from_hashmap(m: &OptionMap) -> Result<Self, ErdError> {
+        let mut index = Self::default();
+        for (k, v) in m {
+            match canonical_key(k) {
+                OPT_COLUMNS => index.columns = split_list(v),
+                OPT_UNIQUE => index.unique = parse_bool(k, v)?,
+                _ => return Err(ErdError::unknown_option("index", k)),
+            }
+        }
+        Ok(index)
+    }
+
+    /// Returns the options which differ from their defaults, in canonical
+    /// order.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let mut opts = vec![(OPT_COLUMNS, self.columns.join(", "))];
+        if self.unique { opts.push((OPT_UNIQUE, "true".to_owned())) }
+        opts
+    }
+}
+
+/// A named list of values which attributes can take, declared with e.g.
+/// `enum status {values: "active, archived"}` and used by giving its name
+/// as an attribute's type.
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Enum {
+    pub name: String,
+    pub values: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
+}
+
+impl Enum {
+    /// Builds an enum from its options, which must list at least one value.
+    pub fn from_hashmap(name: &str, m: &OptionMap) -> Result<Self, ErdError> {
+        let mut values = Vec::new();
+        for (k, v) in m {
+            match canonical_key(k) {
+                OPT_VALUES => values = split_list(v),
+                _ => return Err(ErdError::unknown_option("enum", k)),
+            }
+        }
+        if values.is_empty() {
+            return Err(ErdError::Semantic(format!("enum '{}' has no values", name)));
+        }
+        Ok(Self { name: name.to_owned(), values, span: Span::default() })
+    }
+
+    /// Returns the enum's options, in canonical order.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        vec![(OPT_VALUES, self.values.join(", "))]
+    }
+}
+
+/// Free-standing text annotating the diagram, declared with e.g.
+/// `note "Rows are never deleted" {attached: "orders"}`.
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Note {
+    pub text: String,
+    /// Entity the note is joined to, set with the `attached` option.
+    pub attached: Option<String>,
+    pub background_color: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
+}
+
+impl Note {
+    pub fn from_hashmap(text: &str, m: &OptionMap) -> Result<Self, ErdError> {
+        let mut note = Self { text: text.to_owned(), ..Self::default() };
+        for (k, v) in m {
+            match canonical_key(k) {
+                OPT_ATTACHED => note.attached = Some(v.clone()),
+                OPT_BACKGROUND_COLOR => note.background_color = Some(v.clone()),
+                _ => return Err(ErdError::unknown_option("note", k)),
+            }
         }
+        Ok(note)
+    }
+
+    /// Returns the options which are set, in canonical order.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let mut opts = Vec::new();
+        if let Some(v) = &self.attached { opts.push((OPT_ATTACHED, v.clone())) }
+        if let Some(v) = &self.background_color { opts.push((OPT_BACKGROUND_COLOR, v.clone())) }
+        opts
+    }
+}
+
+/// Resolves options with `resolve`, which fails on the first unknown key.
+/// When `ignored` is given, options with unknown keys are removed and
+/// recorded there instead, as given at `span`.
+pub(crate) fn resolve_options<T>(
+    opts: &mut OptionMap,
+    ignored: Option<&mut Vec<IgnoredOption>>,
+    span: Span,
+    mut resolve: impl FnMut(&OptionMap) -> Result<T, ErdError>,
+) -> Result<T, ErdError> {
+    let ignored = match ignored {
+        Some(ignored) => ignored,
+        None => return resolve(opts),
+    };
+    loop {
+        match resolve(opts) {
+            Err(ErdError::UnknownOption { scope, key }) if opts.contains_key(&key) => {
+                opts.shift_remove(&key);
+                ignored.push(IgnoredOption { scope, key, span });
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Returns `base` with the options in `m` merged over it.
+pub(crate) fn merged<T: Clone>(
+    base: &T,
+    m: &OptionMap,
+    merge: impl FnOnce(&mut T, &OptionMap) -> Result<(), ErdError>,
+) -> Result<T, ErdError> {
+    let mut opts = base.clone();
+    merge(&mut opts, m)?;
+    Ok(opts)
+}
+
+/// Parses a `true` or `false` option value.
+pub(crate) fn parse_bool(key: &str, value: &str) -> Result<bool, ErdError> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(ErdError::invalid_bool(key, value)),
     }
 }
 
+/// Splits a comma separated option value into its trimmed, non-empty items.
+pub(crate) fn split_list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_owned).collect()
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Relation {
-    pub entity1: String,
-    pub entity2: String,
+    /// Identifier given with `rel name: ...`, by which the relationship can
+    /// be referred to.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub name: Option<String>,
+    pub entity1: Name,
+    pub entity2: Name,
     pub card1: Cardinality,
     pub card2: Cardinality,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub kind: RelationKind,
+    /// Whether the relationship identifies a weak entity, set with the
+    /// `identifying` option.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub identifying: bool,
+    /// Attributes of the relationship itself, such as when a link was made,
+    /// set with the `attributes` option.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub attribs: Vec<Attribute>,
+    pub options: RelationshipOptions,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
+}
+
+/// What a relationship between two entities means.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RelationKind {
+    /// An ordinary relationship, e.g. `a 1--* b`.
+    #[default]
+    Association,
+    /// `entity2` is a specialization of `entity1`, written `person ^-- employee`.
+    /// Both cardinalities are one.
+    Inheritance,
+}
+
+/// A relationship among several entities, usually three or more, written
+/// `supplies(supplier, part, project)` and drawn as a diamond joined to each
+/// of them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NaryRelation {
+    pub name: String,
+    /// Participating entities, in the order written.
+    pub entities: Vec<Name>,
     pub options: RelationshipOptions,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Cardinality {
     ZeroOne,
     One,
     ZeroPlus,
     OnePlus,
+    /// An explicit number or range, written `(3)`, `(0,5)` or `(1,*)`. A
+    /// `max` of `None` means there's no limit.
+    Range { min: u32, max: Option<u32> },
 }
 
 impl fmt::Display for Cardinality {
@@ -81,25 +777,47 @@ impl fmt::Display for Cardinality {
             Cardinality::One => write!(f, "1"),
             Cardinality::ZeroPlus => write!(f, "0..N"),
             Cardinality::OnePlus => write!(f, "1..N"),
+            Cardinality::Range { min, max: Some(max) } if min == max => write!(f, "({})", min),
+            Cardinality::Range { min, max: Some(max) } => write!(f, "({},{})", min, max),
+            Cardinality::Range { min, max: None } => write!(f, "({},*)", min),
+        }
+    }
+}
+
+impl Cardinality {
+    /// Returns whether more than one is allowed.
+    pub fn is_many(self) -> bool {
+        match self {
+            Cardinality::ZeroOne | Cardinality::One => false,
+            Cardinality::ZeroPlus | Cardinality::OnePlus => true,
+            Cardinality::Range { max, .. } => max.is_none_or(|max| max > 1),
         }
     }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GlobalOptionType {
     Title,
     Header,
     Entity,
     Relationship,
+    Graph,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GlobalOption {
     pub option_type: GlobalOptionType,
-    pub options: HashMap<String, String>,
+    pub options: OptionMap,
+    /// Location of the directive, if it was parsed from a file.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct TitleOptions {
     pub size: u8,
     pub label: Option<String>,
@@ -108,22 +826,34 @@ pub struct TitleOptions {
 }
 
 impl TitleOptions {
-    pub fn merge_hashmap(&mut self, m: &HashMap<String, String>) -> Result<(), String> {
+    pub fn merge_hashmap(&mut self, m: &OptionMap) -> Result<(), ErdError> {
          for (k, v) in m {
-            match k.as_str() {
+            match canonical_key(k) {
                 OPT_LABEL => self.label = Some(v.clone()),
                 OPT_COLOR => self.color = Some(v.clone()),
                 OPT_FONT => self.font = Some(v.clone()),
                 OPT_SIZE => self.size = match v.parse() {
                     Ok(v) => v,
-                    Err(_) => return Err(format!("could not parse size as integer: {}", v)),
+                    Err(_) => return Err(ErdError::invalid_integer(k, v)),
                 },
-                _ => return Err(format!("invalid header option: {}", v))
+                _ => return Err(ErdError::unknown_option("title", k)),
             }
         }
 
         Ok(())
     }
+
+    /// Returns the options which differ from their defaults, as they would be
+    /// written in a `title {}` directive.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let default = Self::default();
+        let mut opts = Vec::new();
+        if let Some(v) = &self.label { opts.push((OPT_LABEL, v.clone())) }
+        if self.size != default.size { opts.push((OPT_SIZE, self.size.to_string())) }
+        if let Some(v) = &self.color { opts.push((OPT_COLOR, v.clone())) }
+        if let Some(v) = &self.font { opts.push((OPT_FONT, v.clone())) }
+        opts
+    }
 }
 
 impl Default for TitleOptions {
@@ -136,10 +866,125 @@ impl Default for TitleOptions {
         }
     }
 }
+
+/// Graph layout directions accepted for the `rankdir` option, which is case
+/// insensitive.
+pub const RANKDIRS: &[&str] = &["LR", "TB", "RL", "BT"];
+
+/// Edge routing accepted for the `splines` option, as Graphviz names it.
+pub const SPLINES: &[&str] = &["spline", "ortho", "polyline", "line", "curved", "compound", "none"];
+
+/// Graphviz layout engines accepted for the `layout` option.
+pub const LAYOUTS: &[&str] = &["dot", "neato", "fdp", "sfdp", "circo", "twopi", "osage", "patchwork"];
+
+/// Layout of the whole diagram, set with a `graph {}` directive. Anything
+/// not set is left to the renderer's defaults.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GraphOptions {
+    /// Layout direction, one of [`RANKDIRS`].
+    pub rankdir: Option<String>,
+    /// How edges are routed, one of [`SPLINES`].
+    pub splines: Option<String>,
+    /// Graphviz layout engine, one of [`LAYOUTS`].
+    pub layout: Option<String>,
+    /// Color of the diagram's background.
+    pub background_color: Option<String>,
+    /// Which attributes entities show, for overviews of large schemas.
+    pub attributes: Option<AttributeFilter>,
+}
+
+impl GraphOptions {
+    pub fn merge_hashmap(&mut self, m: &OptionMap) -> Result<(), ErdError> {
+        for (k, v) in m {
+            match canonical_key(k) {
+                OPT_RANKDIR => {
+                    let rankdir = v.to_uppercase();
+                    if !RANKDIRS.contains(&rankdir.as_str()) {
+                        return Err(ErdError::invalid_rankdir(k, v));
+                    }
+                    self.rankdir = Some(rankdir);
+                },
+                OPT_SPLINES => {
+                    if !SPLINES.contains(&v.as_str()) {
+                        return Err(ErdError::invalid_splines(k, v));
+                    }
+                    self.splines = Some(v.clone());
+                },
+                OPT_LAYOUT => {
+                    if !LAYOUTS.contains(&v.as_str()) {
+                        return Err(ErdError::invalid_layout(k, v));
+                    }
+                    self.layout = Some(v.clone());
+                },
+                OPT_BACKGROUND_COLOR => self.background_color = Some(v.clone()),
+                OPT_ATTRIBUTES => self.attributes = Some(match v.parse() {
+                    Ok(v) => v,
+                    Err(_) => return Err(ErdError::invalid_attribute_filter(k, v)),
+                }),
+                _ => return Err(ErdError::unknown_option("graph", k)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the options which are set, as they would be written in a
+    /// `graph {}` directive.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let mut opts = Vec::new();
+        if let Some(v) = &self.rankdir { opts.push((OPT_RANKDIR, v.clone())) }
+        if let Some(v) = &self.splines { opts.push((OPT_SPLINES, v.clone())) }
+        if let Some(v) = &self.layout { opts.push((OPT_LAYOUT, v.clone())) }
+        if let Some(v) = &self.background_color { opts.push((OPT_BACKGROUND_COLOR, v.clone())) }
+        if let Some(v) = self.attributes { opts.push((OPT_ATTRIBUTES, v.as_str().to_owned())) }
+        opts
+    }
+}
+
+/// Which attributes are shown in each entity.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AttributeFilter {
+    /// Every attribute.
+    #[default]
+    All,
+    /// Only those in primary or foreign keys.
+    Keys,
+    /// None at all, leaving only entity names.
+    Hidden,
+}
+
+impl AttributeFilter {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AttributeFilter::All => "all",
+            AttributeFilter::Keys => "keys",
+            AttributeFilter::Hidden => "none",
+        }
+    }
+}
+
+impl std::str::FromStr for AttributeFilter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "all" => Ok(AttributeFilter::All),
+            "keys" => Ok(AttributeFilter::Keys),
+            "none" => Ok(AttributeFilter::Hidden),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct HeaderOptions {
     pub size: u8,
-    pub font: String,
+    pub font: Option<String>,
     pub border: u8,
     pub cell_border: u8,
     pub cell_spacing: u8,
@@ -153,41 +998,56 @@ pub struct HeaderOptions {
 
 
 impl HeaderOptions {
-    pub fn from_hashmap(m: &HashMap<String, String>) -> Result<Self, String> {
+    pub fn from_hashmap(m: &OptionMap) -> Result<Self, ErdError> {
         let mut opts = Self::default();
         opts.merge_hashmap(m)?;
         Ok(opts)
     }
 
-    pub fn merge_hashmap(&mut self, m: &HashMap<String, String>) -> Result<(), String> {
+    pub fn merge_hashmap(&mut self, m: &OptionMap) -> Result<(), ErdError> {
          for (k, v) in m {
-            match k.as_str() {
+            match canonical_key(k) {
                 OPT_SIZE => self.size = match v.parse() {
                     Ok(v) => v,
-                    Err(_) => return Err(format!("could not parse size as integer: {}", v)),
+                    Err(_) => return Err(ErdError::invalid_integer(k, v)),
                 },
                 OPT_LABEL => self.label = Some(v.clone()),
                 OPT_COLOR => self.color = Some(v.clone()),
                 OPT_BACKGROUND_COLOR => self.background_color = Some(v.clone()),
-                OPT_FONT => self.font = v.clone(),
+                OPT_FONT => self.font = Some(v.clone()),
                 OPT_BORDER_COLOR => self.border_color = Some(v.clone()),
                 OPT_BORDER => self.border = match v.parse() {
                     Ok(v) => v,
-                    Err(_) => return Err(format!("could not parse border as integer: {}", v)),
+                    Err(_) => return Err(ErdError::invalid_integer(k, v)),
                 },
-                _ => return Err(format!("invalid header option: {}", v))
+                _ => return Err(ErdError::unknown_option("header", k)),
             }
         }
 
         Ok(())
     }
+
+    /// Returns the options which differ from their defaults, in canonical
+    /// order.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let default = Self::default();
+        let mut opts = Vec::new();
+        if let Some(v) = &self.label { opts.push((OPT_LABEL, v.clone())) }
+        if self.size != default.size { opts.push((OPT_SIZE, self.size.to_string())) }
+        if let Some(v) = &self.font { opts.push((OPT_FONT, v.clone())) }
+        if let Some(v) = &self.color { opts.push((OPT_COLOR, v.clone())) }
+        if let Some(v) = &self.background_color { opts.push((OPT_BACKGROUND_COLOR, v.clone())) }
+        if self.border != default.border { opts.push((OPT_BORDER, self.border.to_string())) }
+        if let Some(v) = &self.border_color { opts.push((OPT_BORDER_COLOR, v.clone())) }
+        opts
+    }
 }
 
 impl Default for HeaderOptions {
     fn default() -> Self {
         Self {
             size: 16,
-            font: "Helvetica".to_owned(),
+            font: None,
             border: 0,
             cell_border: 1,
             cell_padding: 4,
@@ -201,12 +1061,14 @@ impl Default for HeaderOptions {
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct EntityOptions {
     pub border: u8,
     pub cell_border: u8,
     pub cell_spacing: u8,
     pub cell_padding: u8,
-    pub font: String,
+    pub font: Option<String>,
 
     pub background_color: Option<String>,
     pub label: Option<String>,
@@ -216,34 +1078,49 @@ pub struct EntityOptions {
 }
 
 impl EntityOptions {
-    pub fn from_hashmap(m: &HashMap<String, String>) -> Result<Self, String> {
+    pub fn from_hashmap(m: &OptionMap) -> Result<Self, ErdError> {
         let mut opts = Self::default();
         opts.merge_hashmap(m)?;
         Ok(opts)
     }
 
-    pub fn merge_hashmap(&mut self, m: &HashMap<String, String>) -> Result<(), String> {
+    pub fn merge_hashmap(&mut self, m: &OptionMap) -> Result<(), ErdError> {
         for (k, v) in m {
-            match k.as_str() {
+            match canonical_key(k) {
                 OPT_BACKGROUND_COLOR => self.background_color = Some(v.clone()),
                 OPT_LABEL => self.label = Some(v.clone()),
                 OPT_COLOR => self.color = Some(v.clone()),
                 OPT_SIZE => self.size = Some(match v.parse() {
                     Ok(v) => v,
-                    Err(_) => return Err(format!("could not parse size as integer: {}", v)),
+                    Err(_) => return Err(ErdError::invalid_integer(k, v)),
                 }),
-                OPT_FONT => self.font = v.clone(),
+                OPT_FONT => self.font = Some(v.clone()),
                 OPT_BORDER_COLOR => self.border_color = Some(v.clone()),
                 OPT_BORDER => self.border = match v.parse() {
                     Ok(v) => v,
-                    Err(_) => return Err(format!("could not parse border as integer: {}", v)),
+                    Err(_) => return Err(ErdError::invalid_integer(k, v)),
                 },
-                _ => return Err(format!("invalid entity option: {}", v))
+                _ => return Err(ErdError::unknown_option("entity", k)),
             }
         }
 
         Ok(())
     }
+
+    /// Returns the options which differ from their defaults, in canonical
+    /// order.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let default = Self::default();
+        let mut opts = Vec::new();
+        if let Some(v) = &self.label { opts.push((OPT_LABEL, v.clone())) }
+        if let Some(v) = &self.size { opts.push((OPT_SIZE, v.to_string())) }
+        if let Some(v) = &self.font { opts.push((OPT_FONT, v.clone())) }
+        if let Some(v) = &self.color { opts.push((OPT_COLOR, v.clone())) }
+        if let Some(v) = &self.background_color { opts.push((OPT_BACKGROUND_COLOR, v.clone())) }
+        if self.border != default.border { opts.push((OPT_BORDER, self.border.to_string())) }
+        if let Some(v) = &self.border_color { opts.push((OPT_BORDER_COLOR, v.clone())) }
+        opts
+    }
 }
 
 impl Default for EntityOptions {
@@ -253,7 +1130,7 @@ impl Default for EntityOptions {
             cell_border: 1,
             cell_spacing: 0,
             cell_padding: 4,
-            font: "Helvetica".to_owned(),
+            font: None,
             background_color: None,
             label: None,
             color: None,
@@ -264,6 +1141,8 @@ impl Default for EntityOptions {
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct AttributeOptions {
     pub text_alignment: String,
     pub label: Option<String>,
@@ -275,15 +1154,15 @@ pub struct AttributeOptions {
 }
 
 impl AttributeOptions {
-    pub fn from_hashmap(m: &HashMap<String, String>) -> Result<Self, String> {
+    pub fn from_hashmap(m: &OptionMap) -> Result<Self, ErdError> {
         let mut opts = Self::default();
         opts.merge_hashmap(m)?;
         Ok(opts)
     }
 
-    pub fn merge_hashmap(&mut self, m: &HashMap<String, String>) -> Result<(), String> {
+    pub fn merge_hashmap(&mut self, m: &OptionMap) -> Result<(), ErdError> {
         for (k, v) in m {
-            match k.as_str() {
+            match canonical_key(k) {
                 OPT_LABEL => self.label = Some(v.clone()),
                 OPT_COLOR => self.color = Some(v.clone()),
                 OPT_BACKGROUND_COLOR => self.background_color = Some(v.clone()),
@@ -291,14 +1170,36 @@ impl AttributeOptions {
                 OPT_BORDER_COLOR => self.border_color = Some(v.clone()),
                 OPT_BORDER => self.border = Some(match v.parse() {
                     Ok(v) => v,
-                    Err(_) => return Err(format!("could not parse border as integer: {}", v)),
+                    Err(_) => return Err(ErdError::invalid_integer(k, v)),
                 }),
-                _ => return Err(format!("invalid attribute option: {}", v))
+                OPT_TEXT_ALIGNMENT => {
+                    let alignment = v.to_uppercase();
+                    if !TEXT_ALIGNMENTS.contains(&alignment.as_str()) {
+                        return Err(ErdError::invalid_alignment(k, v));
+                    }
+                    self.text_alignment = alignment;
+                },
+                _ => return Err(ErdError::unknown_option("attribute", k)),
             }
         }
 
         Ok(())
     }
+
+    /// Returns the options which differ from their defaults, in canonical
+    /// order.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let default = Self::default();
+        let mut opts = Vec::new();
+        if let Some(v) = &self.label { opts.push((OPT_LABEL, v.clone())) }
+        if let Some(v) = &self.font { opts.push((OPT_FONT, v.clone())) }
+        if let Some(v) = &self.color { opts.push((OPT_COLOR, v.clone())) }
+        if let Some(v) = &self.background_color { opts.push((OPT_BACKGROUND_COLOR, v.clone())) }
+        if let Some(v) = &self.border { opts.push((OPT_BORDER, v.to_string())) }
+        if let Some(v) = &self.border_color { opts.push((OPT_BORDER_COLOR, v.clone())) }
+        if self.text_alignment != default.text_alignment { opts.push((OPT_TEXT_ALIGNMENT, self.text_alignment.clone())) }
+        opts
+    }
 }
 
 impl Default for AttributeOptions {
@@ -316,34 +1217,294 @@ impl Default for AttributeOptions {
 }
 
 #[derive(Clone, Default, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct RelationshipOptions {
-    label: Option<String>,
-    color: Option<String>,
-    size: Option<u8>,
-    font: Option<String>,
+    pub label: Option<String>,
+    pub color: Option<String>,
+    pub size: Option<u8>,
+    pub font: Option<String>,
+    /// Compass point, e.g. `ne`, where the edge meets the second entity. In
+    /// [implied relationships](Erd::implied_relationships), a field name.
+    pub head_port: Option<String>,
+    /// Compass point or field where the edge meets the first entity.
+    pub tail_port: Option<String>,
+    /// How the cardinalities are drawn, if not as the renderer's default.
+    pub notation: Option<Notation>,
+    /// Line style of the edge, one of [`EDGE_STYLES`].
+    pub style: Option<String>,
+    /// Minimum length of the edge, in ranks.
+    pub minlen: Option<u8>,
+    /// Width of the edge's line, in points.
+    pub penwidth: Option<String>,
+}
+
+/// Line styles accepted for the `style` option of relationships.
+pub const EDGE_STYLES: &[&str] = &["solid", "dashed", "dotted", "bold"];
+
+/// How the cardinalities at each end of a relationship are drawn.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Notation {
+    /// As text, e.g. `0..N`, next to each end.
+    #[default]
+    Labels,
+    /// As crow's foot arrowheads, with a crow for many, a bar for one and a
+    /// circle for zero.
+    CrowsFoot,
+}
+
+impl Notation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Notation::Labels => "labels",
+            Notation::CrowsFoot => "crows-foot",
+        }
+    }
 }
 
+impl std::str::FromStr for Notation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "labels" => Ok(Notation::Labels),
+            "crows-foot" => Ok(Notation::CrowsFoot),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Values accepted for the `text-alignment` option, which are case
+/// insensitive.
+pub const TEXT_ALIGNMENTS: &[&str] = &["LEFT", "CENTER", "RIGHT"];
+
+/// Compass points accepted for the `head-port` and `tail-port` options.
+pub const COMPASS_POINTS: &[&str] = &["n", "ne", "e", "se", "s", "sw", "w", "nw", "c"];
+
 impl RelationshipOptions {
-    pub fn from_hashmap(m: &HashMap<String, String>) -> Result<Self, String> {
+    pub fn from_hashmap(m: &OptionMap) -> Result<Self, ErdError> {
         let mut opts = Self::default();
         opts.merge_hashmap(m)?;
         Ok(opts)
     }
 
-    pub fn merge_hashmap(&mut self, m: &HashMap<String, String>) -> Result<(), String> {
+    pub fn merge_hashmap(&mut self, m: &OptionMap) -> Result<(), ErdError> {
         for (k, v) in m {
-            match k.as_str() {
+            match canonical_key(k) {
                 OPT_LABEL => self.label = Some(v.clone()),
                 OPT_COLOR => self.color = Some(v.clone()),
                 OPT_SIZE => self.size = Some(match v.parse() {
                     Ok(v) => v,
-                    Err(_) => return Err(format!("could not parse size as integer: {}", v)),
+                    Err(_) => return Err(ErdError::invalid_integer(k, v)),
                 }),
                 OPT_FONT => self.font = Some(v.clone()),
-                _ => return Err(format!("invalid relationship option: {}", v))
+                OPT_HEAD_PORT | OPT_TAIL_PORT => {
+                    if !COMPASS_POINTS.contains(&v.as_str()) {
+                        return Err(ErdError::invalid_compass_point(k, v));
+                    }
+                    match canonical_key(k) {
+                        OPT_HEAD_PORT => self.head_port = Some(v.clone()),
+                        _ => self.tail_port = Some(v.clone()),
+                    }
+                },
+                OPT_NOTATION => self.notation = Some(match v.parse() {
+                    Ok(v) => v,
+                    Err(_) => return Err(ErdError::invalid_notation(k, v)),
+                }),
+                OPT_STYLE => {
+                    if !EDGE_STYLES.contains(&v.as_str()) {
+                        return Err(ErdError::invalid_edge_style(k, v));
+                    }
+                    self.style = Some(v.clone());
+                },
+                OPT_MINLEN => self.minlen = Some(match v.parse() {
+                    Ok(v) => v,
+                    Err(_) => return Err(ErdError::invalid_integer(k, v)),
+                }),
+                OPT_PENWIDTH => match v.parse::<f64>() {
+                    Ok(w) if w.is_finite() && w >= 0.0 => self.penwidth = Some(v.clone()),
+                    _ => return Err(ErdError::invalid_number(k, v)),
+                },
+                _ => return Err(ErdError::unknown_option("relationship", k)),
             }
         }
 
         Ok(())
     }
+
+    /// Returns the options which differ from their defaults, in canonical
+    /// order.
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let mut opts = Vec::new();
+        if let Some(v) = &self.label { opts.push((OPT_LABEL, v.clone())) }
+        if let Some(v) = &self.size { opts.push((OPT_SIZE, v.to_string())) }
+        if let Some(v) = &self.font { opts.push((OPT_FONT, v.clone())) }
+        if let Some(v) = &self.color { opts.push((OPT_COLOR, v.clone())) }
+        if let Some(v) = &self.tail_port { opts.push((OPT_TAIL_PORT, v.clone())) }
+        if let Some(v) = &self.head_port { opts.push((OPT_HEAD_PORT, v.clone())) }
+        if let Some(v) = &self.notation { opts.push((OPT_NOTATION, v.as_str().to_owned())) }
+        if let Some(v) = &self.style { opts.push((OPT_STYLE, v.clone())) }
+        if let Some(v) = &self.minlen { opts.push((OPT_MINLEN, v.to_string())) }
+        if let Some(v) = &self.penwidth { opts.push((OPT_PENWIDTH, v.clone())) }
+        opts
+    }
+}
+
+/// Hooks called while walking an `Erd` with [`walk`]. All methods default to
+/// doing nothing, so implementors only need to override what they use.
+pub trait Visitor {
+    fn visit_title(&mut self, _title: &TitleOptions) {}
+    fn visit_entity(&mut self, _entity: &Entity) {}
+    fn visit_attribute(&mut self, _entity: &Entity, _attribute: &Attribute) {}
+    fn visit_relation(&mut self, _relation: &Relation) {}
+    fn visit_nary_relation(&mut self, _relation: &NaryRelation) {}
+    fn visit_enum(&mut self, _enum: &Enum) {}
+    fn visit_note(&mut self, _note: &Note) {}
+}
+
+/// Walks an `Erd` in declaration order: the title first, then each entity
+/// followed by its attributes, then relationships, n-ary relationships,
+/// enums and notes.
+///
+/// Options seen by the visitor have already had global directives applied.
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, erd: &Erd) {
+    visitor.visit_title(&erd.title_options);
+
+    for e in &erd.entities {
+        visitor.visit_entity(e);
+        for a in &e.attribs {
+            visitor.visit_attribute(e, a);
+        }
+    }
+
+    for r in &erd.relationships {
+        visitor.visit_relation(r);
+    }
+
+    for r in &erd.nary_relationships {
+        visitor.visit_nary_relation(r);
+    }
+
+    for e in &erd.enums {
+        visitor.visit_enum(e);
+    }
+
+    for n in &erd.notes {
+        visitor.visit_note(n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_erd;
+    use super::*;
+
+    #[test]
+    fn walk_order() {
+        struct Recorder(Vec<String>);
+
+        impl Visitor for Recorder {
+            fn visit_entity(&mut self, entity: &Entity) {
+                self.0.push(format!("entity {}", entity.name));
+            }
+
+            fn visit_attribute(&mut self, entity: &Entity, attribute: &Attribute) {
+                self.0.push(format!("attribute {}.{}", entity.name, attribute.field));
+            }
+
+            fn visit_relation(&mut self, relation: &Relation) {
+                self.0.push(format!("relation {} {}", relation.entity1, relation.entity2));
+            }
+        }
+
+        let erd = parse_erd(include_str!("../examples/simple.er")).unwrap();
+        let mut r = Recorder(Vec::new());
+        walk(&mut r, &erd);
+        assert_eq!(r.0, vec![
+            "entity Person",
+            "attribute Person.name",
+            "attribute Person.height",
+            "attribute Person.weight",
+            "attribute Person.birth date",
+            "attribute Person.birth_place_id",
+            "entity Birth Place",
+            "attribute Birth Place.id",
+            "attribute Birth Place.birth city",
+            "attribute Birth Place.birth state",
+            "attribute Birth Place.birth country",
+            "relation Person Birth Place",
+        ]);
+    }
+
+    #[test]
+    fn lookups() {
+        let erd = parse_erd(include_str!("../examples/nfldb.er")).unwrap();
+
+        let drive = erd.entity("drive").unwrap();
+        assert_eq!(drive.name, "drive");
+        assert!(erd.entity("Drive").is_none());
+        assert_eq!(erd.entity_ignore_case("DRIVE"), Some(drive));
+
+        assert!(drive.attribute("drive_id").unwrap().pk);
+        assert!(drive.attribute("Drive_ID").is_none());
+        assert_eq!(drive.attribute_ignore_case("Drive_ID"), drive.attribute("drive_id"));
+
+        let rels: Vec<_> = erd.relationships_for("drive")
+            .map(|r| (r.entity1.as_str(), r.entity2.as_str()))
+            .collect();
+        assert_eq!(rels, vec![("drive", "team"), ("game", "drive"), ("drive", "play"), ("drive", "play_player")]);
+        assert_eq!(erd.relationships_for("DRIVE").count(), 0);
+        assert_eq!(erd.relationships_for_ignore_case("DRIVE").count(), 4);
+    }
+
+    #[test]
+    fn interned_names() {
+        let erd = parse_erd("[a]\n*id\n[b]\n*id\na 1--* b\nb 1--1 a\n").unwrap();
+        let shared = |x: &Name, y: &Name| Arc::ptr_eq(&x.0, &y.0);
+        let (a, b) = (&erd.entities[0], &erd.entities[1]);
+        assert!(shared(&a.attribs[0].field, &b.attribs[0].field));
+        assert!(shared(&erd.relationships[0].entity1, &a.name));
+        assert!(shared(&erd.relationships[1].entity2, &a.name));
+        assert!(shared(&erd.relationships[1].entity1, &b.name));
+
+        assert_eq!(Name::new("a"), Name::from(String::from("a")));
+        assert_eq!(a.name, "a");
+        assert_eq!("a", a.name);
+        assert_eq!(format!("{} {:?}", a.name, a.name), "a \"a\"");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let erd = parse_erd(include_str!("../examples/nfldb.er")).unwrap();
+        let json = serde_json::to_string(&erd).unwrap();
+        let decoded: Erd = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, erd);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_option_defaults() {
+        let opts: EntityOptions = serde_json::from_str(r#"{"border": 2}"#).unwrap();
+        assert_eq!(opts, EntityOptions { border: 2, ..Default::default() });
+    }
+
+    #[test]
+    fn option_key_aliases() {
+        for key in &["bgcolor", "bgColor", "BGCOLOR", "bg_color", "background-color", "backgroundColor"] {
+            assert_eq!(canonical_key(key), OPT_BACKGROUND_COLOR, "{}", key);
+        }
+        assert_eq!(canonical_key("border_color"), OPT_BORDER_COLOR);
+        assert_eq!(canonical_key("fontname"), OPT_FONT);
+        assert_eq!(canonical_key("textAlignment"), OPT_TEXT_ALIGNMENT);
+        assert_eq!(canonical_key("nope"), "nope");
+
+        let erd = parse_erd("[a] {Color: \"red\", PK: \"id\"}\nid {Type: \"int\"}\nindex {COLUMNS: \"id\"}\n").unwrap();
+        let a = &erd.entities[0];
+        assert_eq!(a.options.color.as_deref(), Some("red"));
+        assert_eq!(a.primary_key, vec!["id"]);
+        assert_eq!(a.attribs[0].datatype.as_deref(), Some("int"));
+        assert_eq!(a.indexes.len(), 1);
+    }
 }
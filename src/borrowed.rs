@@ -0,0 +1,396 @@
+//! An unresolved view of an ERD which borrows from the source text.
+//!
+//! Names and option values are slices of the input wherever possible, and
+//! options are kept as the raw key/value pairs they were written as, with no
+//! global directives applied. Use [`Erd::into_owned`] to resolve into an
+//! [`ast::Erd`](crate::ast::Erd).
+
+use std::borrow::Cow;
+use indexmap::IndexMap;
+use crate::ast::{self, Cardinality, GlobalOptionType, RelationKind, Span};
+use crate::error::ErdError;
+use crate::parser::{Defaults, ParseOptions};
+
+/// Option key/value pairs in the order they were written.
+pub type Options<'a> = Vec<(&'a str, Cow<'a, str>)>;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Erd<'a> {
+    /// Paths given by `include` declarations, as written.
+    pub includes: Vec<Cow<'a, str>>,
+    /// Variables given by `set` declarations, with their values.
+    pub variables: IndexMap<&'a str, Cow<'a, str>>,
+    pub directives: Vec<Directive<'a>>,
+    pub entities: Vec<Entity<'a>>,
+    pub relationships: Vec<Relation<'a>>,
+    pub nary_relationships: Vec<NaryRelation<'a>>,
+    pub enums: Vec<Enum<'a>>,
+    pub notes: Vec<Note<'a>>,
+}
+
+/// A global option directive such as `title {...}`, applying to the
+/// declarations after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Directive<'a> {
+    pub option_type: GlobalOptionType,
+    pub options: Options<'a>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entity<'a> {
+    pub name: Cow<'a, str>,
+    pub weak: bool,
+    pub attribs: Vec<Attribute<'a>>,
+    pub indexes: Vec<Index<'a>>,
+    pub options: Options<'a>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute<'a> {
+    pub field: Cow<'a, str>,
+    pub pk: bool,
+    pub fk: bool,
+    pub options: Options<'a>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Index<'a> {
+    pub options: Options<'a>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relation<'a> {
+    pub name: Option<Cow<'a, str>>,
+    pub entity1: Cow<'a, str>,
+    pub entity2: Cow<'a, str>,
+    pub card1: Cardinality,
+    pub card2: Cardinality,
+    pub kind: RelationKind,
+    pub options: Options<'a>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NaryRelation<'a> {
+    pub name: Cow<'a, str>,
+    pub entities: Vec<Cow<'a, str>>,
+    pub options: Options<'a>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Enum<'a> {
+    pub name: Cow<'a, str>,
+    pub options: Options<'a>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Note<'a> {
+    pub text: Cow<'a, str>,
+    pub options: Options<'a>,
+    pub span: Span,
+}
+
+impl<'a> Erd<'a> {
+    /// Resolves into an owned `ast::Erd`, validating options and applying
+    /// global directives.
+    pub fn into_owned(self) -> Result<ast::Erd, ErdError> {
+        self.into_owned_with(&ParseOptions::default())
+    }
+
+    /// As [`into_owned`](Self::into_owned), applying the defaults and
+    /// directives in `options` ahead of those in the input, and resolving
+    /// includes if `options` gives the path of the input.
+    pub fn into_owned_with(self, options: &ParseOptions) -> Result<ast::Erd, ErdError> {
+        let mut errors = Vec::new();
+        let mut ignored = Vec::new();
+        let defaults = options.resolve_defaults(options.lenient.then_some(&mut ignored))?;
+        let mut items = options.leading_items();
+        let includes = match &options.path {
+            Some(path) => {
+                items.extend(crate::parser::include_files(&self.includes, path, options, &defaults, &mut ignored, &mut errors));
+                Vec::new()
+            },
+            None => self.includes.iter().map(|p| p.to_string()).collect(),
+        };
+        let variables = self.variables.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        items.extend(self.into_items(&defaults, options.lenient.then_some(&mut ignored))?);
+
+        let mut erd = crate::parser::build_erd(items, options, ignored, &mut errors);
+        erd.includes = includes;
+        erd.variables = variables;
+        match errors.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(erd),
+        }
+    }
+
+    /// Converts declarations into items, in the order they're resolved.
+    ///
+    /// Items are ordered by where they start in the input, so directives
+    /// only apply to what follows them. Declarations without spans keep
+    /// their order, with directives first. Entities and relationships start
+    /// from `defaults`, and options with unknown keys are recorded in
+    /// `ignored`, if given, rather than failing.
+    pub(crate) fn into_items(self, defaults: &Defaults, mut ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<Vec<ast::Ast>, ErdError> {
+        // Each item is paired with the start of the declaration it's part
+        // of, so attributes and indexes stay with their entity.
+        let mut items = Vec::new();
+        for d in self.directives {
+            items.push((d.span.start, ast::Ast::GlobalOption(d.into_owned())));
+        }
+        for Entity { name, weak, attribs, indexes, options, span } in self.entities {
+            let entity = Entity { name, weak, attribs: Vec::new(), indexes: Vec::new(), options, span };
+            items.push((span.start, ast::Ast::Entity(entity.resolve(defaults, ignored.as_deref_mut())?)));
+            for a in attribs {
+                items.push((span.start, ast::Ast::Attribute(a.resolve(ignored.as_deref_mut())?)));
+            }
+            for index in indexes {
+                items.push((span.start, ast::Ast::Index(index.resolve(ignored.as_deref_mut())?)));
+            }
+        }
+        for r in self.relationships {
+            items.push((r.span.start, ast::Ast::Relation(r.resolve(defaults, ignored.as_deref_mut())?)));
+        }
+        for r in self.nary_relationships {
+            items.push((r.span.start, ast::Ast::NaryRelation(r.resolve(defaults, ignored.as_deref_mut())?)));
+        }
+        for e in self.enums {
+            items.push((e.span.start, ast::Ast::Enum(e.resolve(ignored.as_deref_mut())?)));
+        }
+        for n in self.notes {
+            items.push((n.span.start, ast::Ast::Note(n.resolve(ignored.as_deref_mut())?)));
+        }
+        // Stable, so items with equal starts keep the order above.
+        items.sort_by_key(|(start, _)| *start);
+        Ok(items.into_iter().map(|(_, item)| item).collect())
+    }
+}
+
+impl<'a> Directive<'a> {
+    pub fn into_owned(self) -> ast::GlobalOption {
+        ast::GlobalOption {
+            option_type: self.option_type,
+            options: options_map(&self.options),
+            span: self.span,
+        }
+    }
+}
+
+impl<'a> Entity<'a> {
+    /// Converts into an `ast::Entity` with only its own options applied.
+    pub fn into_owned(self) -> Result<ast::Entity, ErdError> {
+        self.resolve(&Defaults::default(), None)
+    }
+
+    /// As [`into_owned`](Self::into_owned), with its options merged over
+    /// `defaults`, and recording options with unknown keys in `ignored`, if
+    /// given, rather than failing.
+    pub(crate) fn resolve(self, defaults: &Defaults, mut ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<ast::Entity, ErdError> {
+        let mut opts = options_map(&self.options);
+        let primary_key = match opts.shift_remove(ast::OPT_PRIMARY_KEY) {
+            Some(fields) => ast::split_list(&fields),
+            None => Vec::new(),
+        };
+        let description = opts.shift_remove(ast::OPT_DESCRIPTION);
+        let group = opts.shift_remove(ast::OPT_GROUP);
+        let url = opts.shift_remove(ast::OPT_URL);
+        Ok(ast::Entity {
+            name: self.name.as_ref().into(),
+            attribs: self.attribs.into_iter()
+                .map(|a| a.resolve(ignored.as_deref_mut()))
+                .collect::<Result<_, _>>()?,
+            options: ast::resolve_options(&mut opts, ignored.as_deref_mut(), self.span, |m| {
+                ast::merged(&defaults.entity, m, ast::EntityOptions::merge_hashmap)
+            })?,
+            header_options: ast::resolve_options(&mut opts, ignored.as_deref_mut(), self.span, |m| {
+                ast::merged(&defaults.header, m, ast::HeaderOptions::merge_hashmap)
+            })?,
+            primary_key,
+            indexes: self.indexes.into_iter()
+                .map(|x| x.resolve(ignored.as_deref_mut()))
+                .collect::<Result<_, _>>()?,
+            description,
+            group,
+            url,
+            weak: self.weak,
+            span: self.span,
+        })
+    }
+}
+
+impl<'a> Attribute<'a> {
+    pub fn into_owned(self) -> Result<ast::Attribute, ErdError> {
+        self.resolve(None)
+    }
+
+    pub(crate) fn resolve(self, ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<ast::Attribute, ErdError> {
+        let mut opts = options_map(&self.options);
+        let references = opts.shift_remove(ast::OPT_REFERENCES)
+            .map(|v| ast::Reference::parse(&v))
+            .transpose()?;
+        Ok(ast::Attribute {
+            field: self.field.as_ref().into(),
+            pk: self.pk,
+            // Referring to another entity's field makes it a foreign key.
+            fk: self.fk || references.is_some(),
+            unique: match opts.shift_remove(ast::OPT_UNIQUE) {
+                Some(v) => ast::parse_bool(ast::OPT_UNIQUE, &v)?,
+                None => false,
+            },
+            nullable: match opts.shift_remove(ast::OPT_NULLABLE) {
+                Some(v) => ast::parse_bool(ast::OPT_NULLABLE, &v)?,
+                None => false,
+            },
+            datatype: opts.shift_remove(ast::OPT_TYPE),
+            description: opts.shift_remove(ast::OPT_DESCRIPTION),
+            references,
+            options: ast::resolve_options(&mut opts, ignored, self.span, ast::AttributeOptions::from_hashmap)?,
+            span: self.span,
+        })
+    }
+}
+
+impl<'a> Index<'a> {
+    pub fn into_owned(self) -> Result<ast::Index, ErdError> {
+        self.resolve(None)
+    }
+
+    pub(crate) fn resolve(self, ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<ast::Index, ErdError> {
+        let index = ast::resolve_options(&mut options_map(&self.options), ignored, self.span, ast::Index::from_hashmap)?;
+        Ok(ast::Index { span: self.span, ..index })
+    }
+}
+
+impl<'a> Relation<'a> {
+    /// Converts into an `ast::Relation` with only its own options applied.
+    pub fn into_owned(self) -> Result<ast::Relation, ErdError> {
+        self.resolve(&Defaults::default(), None)
+    }
+
+    pub(crate) fn resolve(self, defaults: &Defaults, ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<ast::Relation, ErdError> {
+        let mut opts = options_map(&self.options);
+        Ok(ast::Relation {
+            name: self.name.map(Cow::into_owned),
+            entity1: self.entity1.as_ref().into(),
+            entity2: self.entity2.as_ref().into(),
+            card1: self.card1,
+            card2: self.card2,
+            kind: self.kind,
+            identifying: match opts.shift_remove(ast::OPT_IDENTIFYING) {
+                Some(v) => ast::parse_bool(ast::OPT_IDENTIFYING, &v)?,
+                None => false,
+            },
+            attribs: match opts.shift_remove(ast::OPT_ATTRIBUTES) {
+                Some(fields) => ast::split_list(&fields).into_iter().map(ast::Attribute::with_field).collect(),
+                None => Vec::new(),
+            },
+            options: ast::resolve_options(&mut opts, ignored, self.span, |m| {
+                ast::merged(&defaults.relationship, m, ast::RelationshipOptions::merge_hashmap)
+            })?,
+            span: self.span,
+        })
+    }
+}
+
+impl<'a> NaryRelation<'a> {
+    /// Converts into an `ast::NaryRelation` with only its own options applied.
+    pub fn into_owned(self) -> Result<ast::NaryRelation, ErdError> {
+        self.resolve(&Defaults::default(), None)
+    }
+
+    pub(crate) fn resolve(self, defaults: &Defaults, ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<ast::NaryRelation, ErdError> {
+        let mut opts = options_map(&self.options);
+        Ok(ast::NaryRelation {
+            name: self.name.into_owned(),
+            entities: self.entities.iter().map(|e| e.as_ref().into()).collect(),
+            options: ast::resolve_options(&mut opts, ignored, self.span, |m| {
+                ast::merged(&defaults.relationship, m, ast::RelationshipOptions::merge_hashmap)
+            })?,
+            span: self.span,
+        })
+    }
+}
+
+impl<'a> Enum<'a> {
+    pub fn into_owned(self) -> Result<ast::Enum, ErdError> {
+        self.resolve(None)
+    }
+
+    pub(crate) fn resolve(self, ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<ast::Enum, ErdError> {
+        let mut opts = options_map(&self.options);
+        let e = ast::resolve_options(&mut opts, ignored, self.span, |m| ast::Enum::from_hashmap(&self.name, m))?;
+        Ok(ast::Enum { span: self.span, ..e })
+    }
+}
+
+impl<'a> Note<'a> {
+    pub fn into_owned(self) -> Result<ast::Note, ErdError> {
+        self.resolve(None)
+    }
+
+    pub(crate) fn resolve(self, ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<ast::Note, ErdError> {
+        let mut opts = options_map(&self.options);
+        let note = ast::resolve_options(&mut opts, ignored, self.span, |m| ast::Note::from_hashmap(&self.text, m))?;
+        Ok(ast::Note { span: self.span, ..note })
+    }
+}
+
+/// Collects options into a map under their canonical keys, with later values
+/// for the same key winning.
+pub(crate) fn options_map(opts: &[(&str, Cow<'_, str>)]) -> ast::OptionMap {
+    opts.iter().map(|(k, v)| (ast::canonical_key(k).to_owned(), v.to_string())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_erd, parse_erd_borrowed};
+    use super::*;
+
+    #[test]
+    fn borrows_from_input() {
+        let s = "title {label: \"t\"}\n[a] {color: \"red\"}\n*id\nb\na 1--* a\n";
+        let erd = parse_erd_borrowed(s).unwrap();
+
+        assert_eq!(erd.directives, vec![Directive {
+            option_type: GlobalOptionType::Title,
+            options: vec![("label", Cow::Borrowed("t"))],
+            span: Span::default(),
+        }]);
+        assert_eq!(erd.entities.len(), 1);
+        assert!(matches!(erd.entities[0].name, Cow::Borrowed("a")));
+        assert_eq!(erd.entities[0].options, vec![("color", Cow::Borrowed("red"))]);
+        assert_eq!(erd.entities[0].attribs[0], Attribute {
+            field: Cow::Borrowed("id"),
+            pk: true,
+            fk: false,
+            options: vec![],
+            span: Span::default(),
+        });
+        assert_eq!(erd.entities[0].attribs[1].field, "b");
+        assert_eq!(erd.relationships[0].card2, Cardinality::ZeroPlus);
+    }
+
+    #[test]
+    fn into_owned_matches_parse_erd() {
+        let s = include_str!("../examples/nfldb.er");
+        let erd = parse_erd_borrowed(s).unwrap();
+        assert_eq!(erd.into_owned().unwrap(), parse_erd(s).unwrap());
+    }
+
+    #[test]
+    fn into_owned_validates_options() {
+        let erd = parse_erd_borrowed("[a]\n*id {nope: \"1\"}\n").unwrap();
+        assert_eq!(
+            erd.into_owned(),
+            Err(ErdError::UnknownOption { scope: "attribute", key: "nope".to_owned() }),
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Rendering of diagrams in Chen's notation: entities as rectangles,
+//! attributes as ellipses joined to their entity, and relationships as
+//! diamonds between the entities they relate.
+
+use std::io::{self, Result};
+use crate::ast;
+use crate::render::{escape_html, filter_attributes, graph_style_attrs, layout_attrs, quote, title_markup, Backend, Output, RenderOptions};
+
+/// Backend producing Graphviz DOT output in Chen's notation.
+#[derive(Clone, Debug, Default)]
+pub struct Chen {
+    pub options: RenderOptions,
+}
+
+impl Backend for Chen {
+    fn name(&self) -> &'static str {
+        "chen"
+    }
+
+    fn render(&self, erd: &ast::Erd, w: &mut dyn io::Write) -> Result<()> {
+        ChenRenderer::with_options(w, self.options.clone()).render_erd(erd)
+    }
+}
+
+/// Writes a diagram as DOT in Chen's notation.
+///
+/// Primary key attributes are underlined, weak entities and the
+/// relationships identifying them have double borders, and inheritance is
+/// drawn as an `ISA` triangle. Enums and notes aren't shown.
+pub struct ChenRenderer<W: Output> {
+    w: W,
+    options: RenderOptions,
+}
+
+impl<W: Output> ChenRenderer<W> {
+    pub fn new(w: W) -> Self {
+        Self::with_options(w, RenderOptions::default())
+    }
+
+    pub fn with_options(w: W, options: RenderOptions) -> Self {
+        Self { w, options }
+    }
+
+    pub fn render_erd(&mut self, erd: &ast::Erd) -> Result<()> {
+        let indent = self.options.indent.clone();
+        writeln!(self.w, "graph {{")?;
+        writeln!(self.w, "{}graph [", indent)?;
+        if let Some(label) = &erd.title_options.label {
+            writeln!(
+                self.w,
+                "{i}{i}label=<<FONT POINT-SIZE=\"{}\">{}</FONT>>,\n{i}{i}labeljust=l,\n{i}{i}labelloc=t,",
+                erd.title_options.size,
+                title_markup(label),
+                i=indent,
+            )?;
+        }
+        let attrs = graph_style_attrs(erd, &self.options).into_iter()
+            .chain(layout_attrs(&erd.graph_options, &self.options));
+        for (k, v) in attrs {
+            writeln!(self.w, "{i}{i}{}={},", k, v, i=indent)?;
+        }
+        writeln!(self.w, "{}];", indent)?;
+        writeln!(self.w, "{i}node [\n{i}{i}fontname={},\n{i}];", quote(&self.options.font), i=indent)?;
+        writeln!(self.w, "{i}edge [\n{i}{i}color={},", quote(&self.options.edge_color), i=indent)?;
+        if let Some(color) = &self.options.edge_font_color {
+            writeln!(self.w, "{i}{i}fontcolor={},", quote(color), i=indent)?;
+        }
+        writeln!(self.w, "{}];", indent)?;
+
+        let filter = erd.graph_options.attributes.unwrap_or(self.options.attributes);
+        for (i, e) in erd.entities.iter().enumerate() {
+            self.entity(&filter_attributes(e, filter, &[]), i)?;
+        }
+        let implied = erd.implied_relationships();
+        for (i, r) in erd.relationships.iter().chain(&implied).enumerate() {
+            self.relationship(r, i)?;
+        }
+        for (i, r) in erd.nary_relationships.iter().enumerate() {
+            self.nary_relationship(r, i)?;
+        }
+
+        writeln!(self.w, "}}")
+    }
+
+    fn entity(&mut self, e: &ast::Entity, index: usize) -> Result<()> {
+        let indent = &self.options.indent;
+        let peripheries = if e.weak { ", peripheries=2" } else { "" };
+        writeln!(
+            self.w,
+            "{}{} [ shape=box, label={}{} ];",
+            indent,
+            quote(&e.name),
+            quote(e.options.label.as_deref().unwrap_or(&e.name)),
+            peripheries,
+        )?;
+        for (i, a) in e.attribs.iter().enumerate() {
+            let id = quote(&format!("attr:{}:{}", index, i));
+            self.attribute(&id, a, e.is_primary_key(a))?;
+            writeln!(self.w, "{}{} -- {};", self.options.indent, quote(&e.name), id)?;
+        }
+        Ok(())
+    }
+
+    /// Draws an attribute as an ellipse, with its name underlined if it's
+    /// part of a key.
+    fn attribute(&mut self, id: &str, a: &ast::Attribute, key: bool) -> Result<()> {
+        let name = escape_html(&a.field);
+        let name = if key { format!("<U>{}</U>", name) } else { name };
+        writeln!(self.w, "{}{} [ shape=ellipse, label=<{}> ];", self.options.indent, id, name)
+    }
+
+    fn relationship(&mut self, r: &ast::Relation, index: usize) -> Result<()> {
+        let indent = &self.options.indent;
+        let id = quote(&format!("rel:{}", index));
+        if r.kind == ast::RelationKind::Inheritance {
+            writeln!(self.w, "{}{} [ shape=invtriangle, label=\"ISA\" ];", indent, id)?;
+            writeln!(self.w, "{}{} -- {};", indent, quote(&r.entity1), id)?;
+            return writeln!(self.w, "{}{} -- {};", indent, id, quote(&r.entity2));
+        }
+
+        let label = r.options.label.as_deref().or(r.name.as_deref()).unwrap_or("");
+        let peripheries = if r.identifying { ", peripheries=2" } else { "" };
+        writeln!(self.w, "{}{} [ shape=diamond, label={}{} ];", indent, id, quote(label), peripheries)?;
+        writeln!(self.w, "{}{} -- {} [ label={} ];", indent, quote(&r.entity1), id, quote(&r.card1.to_string()))?;
+        writeln!(self.w, "{}{} -- {} [ label={} ];", indent, id, quote(&r.entity2), quote(&r.card2.to_string()))?;
+        for (i, a) in r.attribs.iter().enumerate() {
+            let attr = quote(&format!("rel:{}:{}", index, i));
+            self.attribute(&attr, a, false)?;
+            writeln!(self.w, "{}{} -- {};", self.options.indent, id, attr)?;
+        }
+        Ok(())
+    }
+
+    fn nary_relationship(&mut self, r: &ast::NaryRelation, index: usize) -> Result<()> {
+        let indent = &self.options.indent;
+        let id = quote(&format!("nary:{}", index));
+        let label = r.options.label.as_deref().unwrap_or(&r.name);
+        writeln!(self.w, "{}{} [ shape=diamond, label={} ];", indent, id, quote(label))?;
+        for e in &r.entities {
+            writeln!(self.w, "{}{} -- {};", indent, quote(e), id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_erd;
+    use crate::render::Fmt;
+    use pretty_assertions::assert_eq;
+    use super::*;
+
+    #[test]
+    fn chen() {
+        let s = r#"
+[person]
+*id
+name
+[[dependent]]
+*+person_id
+*name
+[employee]
+person 1==* dependent {label: "has", attributes: "since"}
+person ^-- employee
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut out = String::new();
+        ChenRenderer::new(Fmt(&mut out)).render_erd(&erd).unwrap();
+        assert_eq!(out, r#"graph {
+    graph [
+        rankdir=LR,
+        splines=spline,
+    ];
+    node [
+        fontname="Helvetica",
+    ];
+    edge [
+        color="gray50",
+    ];
+    "person" [ shape=box, label="person" ];
+    "attr:0:0" [ shape=ellipse, label=<<U>id</U>> ];
+    "person" -- "attr:0:0";
+    "attr:0:1" [ shape=ellipse, label=<name> ];
+    "person" -- "attr:0:1";
+    "dependent" [ shape=box, label="dependent", peripheries=2 ];
+    "attr:1:0" [ shape=ellipse, label=<<U>person_id</U>> ];
+    "dependent" -- "attr:1:0";
+    "attr:1:1" [ shape=ellipse, label=<<U>name</U>> ];
+    "dependent" -- "attr:1:1";
+    "employee" [ shape=box, label="employee" ];
+    "rel:0" [ shape=diamond, label="has", peripheries=2 ];
+    "person" -- "rel:0" [ label="1" ];
+    "rel:0" -- "dependent" [ label="0..N" ];
+    "rel:0:0" [ shape=ellipse, label=<since> ];
+    "rel:0" -- "rel:0:0";
+    "rel:1" [ shape=invtriangle, label="ISA" ];
+    "person" -- "rel:1";
+    "rel:1" -- "employee";
+}
+"#);
+    }
+}
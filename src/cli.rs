@@ -0,0 +1,588 @@
+use std::num::NonZeroUsize;
+use clap::{builder::PossibleValuesParser, Args, Parser, Subcommand, ValueEnum, ValueHint};
+use erd::{parser::{invalid_ident_char, DuplicateEntityPolicy}, render, sort::AttributeOrder, theme};
+use crate::exit;
+
+/// Create entity-relationship diagrams from plain text markup.
+///
+/// Renders the input when no command is given, so `erd FILE` is the same as
+/// `erd render FILE`.
+#[derive(Parser, Debug)]
+#[command(name = env!("CARGO_BIN_NAME"), version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub render: RenderArgs,
+}
+
+impl Cli {
+    /// The command to run, which is `render` if none was given.
+    pub fn into_command(self) -> Command {
+        self.command.unwrap_or(Command::Render(self.render))
+    }
+}
+
+// Only one is ever created, so the size difference doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Render the input as a diagram (the default).
+    Render(RenderArgs),
+    /// Check the input for errors.
+    Validate(CheckArgs),
+    /// Check the input for errors and warnings.
+    Lint(CheckArgs),
+    /// Reformat the input as canonical .er source.
+    Fmt(FmtArgs),
+    /// Show the changes between two .er files.
+    ///
+    /// Exits with status 1 if there are any.
+    Diff(DiffArgs),
+    /// Run an HTTP server rendering .er source posted to /render.
+    ///
+    /// The output format, edge type and layout direction can be chosen with
+    /// the format, edge and rankdir query parameters, e.g.
+    /// `curl --data-binary @schema.er 'localhost:8080/render?rankdir=TB'`.
+    Serve(ServeArgs),
+    /// Run a language server for .er files, communicating over stdin and
+    /// stdout.
+    ///
+    /// Provides diagnostics, go to definition and hover for entities, and
+    /// completion of entity names in relationships.
+    Lsp,
+    /// Print a completion script for the given shell.
+    Completions {
+        shell: clap_complete::Shell,
+    },
+}
+
+/// Where input is read from.
+#[derive(Args, Debug)]
+pub struct InputArgs {
+    /// Read input from the given file, otherwise input is read from stdin.
+    ///
+    /// May be given more than once, or files may be given as arguments, to
+    /// merge several files into one diagram. Glob patterns such as
+    /// 'schemas/**/*.er' are expanded, in which case each matching file is
+    /// read.
+    #[arg(short, long = "input", value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input: Vec<String>,
+
+    /// Input files, read as if given with --input.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub files: Vec<String>,
+
+    /// Report all errors in the input rather than stopping at the first one.
+    #[arg(short, long)]
+    pub keep_going: bool,
+
+    /// What to do when an entity is declared more than once in a file
+    /// [default: warn].
+    #[arg(long, value_name = "POLICY")]
+    pub duplicate_entities: Option<DuplicateEntities>,
+
+    /// Skip options with unknown keys, warning about them, rather than
+    /// failing. Useful for input generated by other tools.
+    #[arg(long)]
+    pub lenient: bool,
+
+    /// Characters to allow in unquoted names besides letters, digits and
+    /// '_', e.g. '.-' for names like auth.users or legacy-orders.
+    #[arg(long, value_name = "CHARS", value_parser = parse_ident_chars)]
+    pub ident_chars: Option<String>,
+
+    /// Number of files to read, or entity diagrams to write, at once
+    /// [default: the number of CPUs].
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+impl InputArgs {
+    /// Returns input files given with `-i`, followed by any given as
+    /// arguments.
+    pub fn paths(&self) -> Vec<String> {
+        self.input.iter().chain(&self.files).cloned().collect()
+    }
+
+    pub fn duplicate_entity_policy(&self) -> DuplicateEntityPolicy {
+        self.duplicate_entities.map(DuplicateEntityPolicy::from).unwrap_or_default()
+    }
+
+    /// Returns the characters to allow in unquoted names, from the command
+    /// line if given, otherwise `config`.
+    pub fn ident_chars(&self, config: Option<&str>) -> String {
+        self.ident_chars.as_deref().or(config).unwrap_or_default().to_owned()
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs
+            .or_else(|| std::thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct VerbosityArgs {
+    /// Only print errors.
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also print which files are read and written.
+    #[arg(short, long)]
+    pub verbose: bool,
+}
+
+impl VerbosityArgs {
+    pub fn level(&self) -> exit::Verbosity {
+        match (self.quiet, self.verbose) {
+            (true, _) => exit::Verbosity::Quiet,
+            (false, true) => exit::Verbosity::Verbose,
+            (false, false) => exit::Verbosity::Normal,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Write output to the given file, otherwise output is written to stdout.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output: Option<String>,
+
+    /// Also write a diagram of each entity and its direct relationships to
+    /// the given directory, named after the entity.
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    pub out_dir: Option<String>,
+
+    /// Output format [default: dot].
+    #[arg(short, long, alias = "to", value_parser = backend_names())]
+    pub format: Option<String>,
+
+    /// Built-in theme setting colors and fonts, which the config file and
+    /// other options override [default: classic].
+    #[arg(short, long, value_parser = theme_names())]
+    pub theme: Option<String>,
+
+    /// Edge type, overriding any splines set by a graph directive in the
+    /// input [default: spline].
+    #[arg(short, long, value_name = "TYPE", value_parser = edge_types())]
+    pub edge: Option<String>,
+
+    /// Layout direction, e.g. TB to lay out the diagram top to bottom,
+    /// overriding any set by a graph directive in the input [default: LR].
+    #[arg(short, long, value_name = "DIR", value_parser = rankdirs())]
+    pub rankdir: Option<String>,
+
+    /// Graphviz layout engine to ask for, e.g. neato or fdp, which can cope
+    /// better than dot with very large diagrams. Overrides any set by a graph
+    /// directive in the input.
+    #[arg(long, value_name = "ENGINE", value_parser = layouts())]
+    pub layout: Option<String>,
+
+    /// Use the given diagram title, overriding any set in the input.
+    #[arg(long, conflicts_with = "no_title")]
+    pub title: Option<String>,
+
+    /// Leave out the diagram title, even if one is set in the input.
+    #[arg(long)]
+    pub no_title: bool,
+
+    /// Sort each entity's attributes.
+    #[arg(long, value_name = "ORDER")]
+    pub sort_attributes: Option<SortOrder>,
+
+    /// Sort entities by name.
+    #[arg(long)]
+    pub sort_entities: bool,
+
+    /// Replace many-to-many relationships with junction entities holding
+    /// foreign keys to both sides.
+    #[arg(long)]
+    pub expand_junctions: bool,
+
+    /// Only include the given entity and those near it.
+    #[arg(long, value_name = "ENTITY")]
+    pub focus: Option<String>,
+
+    /// How many relationships away from the focused entity to include
+    /// [default: 1].
+    #[arg(long, value_name = "N", requires = "focus")]
+    pub depth: Option<usize>,
+
+    /// Print counts of entities, attributes and relationships, unconnected
+    /// entities and the largest entities, instead of rendering the diagram.
+    #[arg(long, conflicts_with_all = ["output", "out_dir", "format"])]
+    pub stats: bool,
+
+    /// List each entity's indexes below its attributes.
+    #[arg(long)]
+    pub indexes: bool,
+
+    /// Add a column listing which keys each attribute is part of.
+    #[arg(long)]
+    pub constraints: bool,
+
+    /// Don't mark attributes which may be null.
+    #[arg(long)]
+    pub hide_nullable: bool,
+
+    /// Add a column showing attribute descriptions.
+    #[arg(long)]
+    pub descriptions: bool,
+
+    /// Give attributes and relationships tooltips, and entities without a
+    /// description a tooltip summarising them.
+    #[arg(long)]
+    pub tooltips: bool,
+
+    /// How to draw attributes of relationships [default: label].
+    #[arg(long, value_name = "STYLE")]
+    pub relationship_attributes: Option<RelationshipAttributes>,
+
+    /// Which attributes to show in entities, e.g. only keys for an overview
+    /// of a large schema [default: all].
+    #[arg(long, value_name = "WHICH")]
+    pub attributes: Option<AttributeFilter>,
+
+    /// How to mark attributes which are keys [default: markup].
+    #[arg(long, value_name = "STYLE")]
+    pub keys: Option<KeyStyle>,
+
+    /// How to draw the cardinalities at each end of a relationship, unless
+    /// it sets its own notation [default: labels].
+    #[arg(long, value_name = "STYLE")]
+    pub notation: Option<Notation>,
+
+    /// Add a table explaining how keys and cardinalities are drawn.
+    #[arg(long)]
+    pub legend: bool,
+
+    /// Draw entities whose names are qualified with a schema, such as
+    /// billing.invoices, in a box for each schema, unless they set a group.
+    #[arg(long)]
+    pub schema_clusters: bool,
+
+    /// Text of each attribute's row, built from the placeholders {name},
+    /// {field}, {key}, {type} and {label}. Other text inside a
+    /// placeholder's braces only shows if it has a value
+    /// [default: "{name}{ [label]}"].
+    #[arg(long, value_name = "TEMPLATE", value_parser = str::parse::<render::RowTemplate>)]
+    pub row_template: Option<render::RowTemplate>,
+
+    /// Add a line below the title with entity/relationship counts,
+    /// generation time and source file. The time is taken from
+    /// SOURCE_DATE_EPOCH if it's set.
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Only warn about relationships with undeclared entities, rather than
+    /// failing.
+    #[arg(long)]
+    pub allow_unknown_entities: bool,
+
+    /// Keep running, and regenerate the output whenever an input file
+    /// changes.
+    #[arg(short, long)]
+    pub watch: bool,
+
+    #[command(flatten)]
+    pub verbosity: VerbosityArgs,
+
+    /// Read default settings from the given TOML file, rather than
+    /// ./erd.toml or ~/.erdrc.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub config: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Only warn about relationships with undeclared entities, rather than
+    /// failing.
+    #[arg(long)]
+    pub allow_unknown_entities: bool,
+
+    #[command(flatten)]
+    pub verbosity: VerbosityArgs,
+
+    /// Read default settings from the given TOML file, rather than
+    /// ./erd.toml or ~/.erdrc.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub config: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct FmtArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Write output to the given file, otherwise the input file is
+    /// overwritten (or output is written to stdout, when reading stdin).
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output: Option<String>,
+
+    #[command(flatten)]
+    pub verbosity: VerbosityArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    #[arg(value_hint = ValueHint::FilePath)]
+    pub old: String,
+
+    #[arg(value_hint = ValueHint::FilePath)]
+    pub new: String,
+
+    #[command(flatten)]
+    pub verbosity: VerbosityArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Port to listen on.
+    #[arg(short, long, default_value_t = 8080)]
+    pub port: u16,
+
+    /// Address to listen on. Use 0.0.0.0 to accept connections from other
+    /// hosts.
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Built-in theme setting colors and fonts, which the config file
+    /// overrides [default: classic].
+    #[arg(short, long, value_parser = theme_names())]
+    pub theme: Option<String>,
+
+    #[command(flatten)]
+    pub verbosity: VerbosityArgs,
+
+    /// Read default settings from the given TOML file, rather than
+    /// ./erd.toml or ~/.erdrc.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub config: Option<String>,
+}
+
+/// Order to sort attributes into, see [`AttributeOrder`].
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum SortOrder {
+    /// By field name.
+    Alphabetical,
+    /// Primary keys, then foreign keys, then everything else.
+    KeysFirst,
+}
+
+impl From<SortOrder> for AttributeOrder {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Alphabetical => AttributeOrder::Alphabetical,
+            SortOrder::KeysFirst => AttributeOrder::KeysFirst,
+        }
+    }
+}
+
+/// How to draw attributes of relationships, see
+/// [`render::RelationshipAttributes`].
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum RelationshipAttributes {
+    /// List them in the relationship's label.
+    Label,
+    /// Draw them in a box between the entities.
+    Entity,
+}
+
+impl From<RelationshipAttributes> for render::RelationshipAttributes {
+    fn from(style: RelationshipAttributes) -> Self {
+        match style {
+            RelationshipAttributes::Label => render::RelationshipAttributes::Label,
+            RelationshipAttributes::Entity => render::RelationshipAttributes::Entity,
+        }
+    }
+}
+
+/// Which attributes to show, see [`erd::ast::AttributeFilter`].
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum AttributeFilter {
+    /// Every attribute.
+    All,
+    /// Only those in primary or foreign keys.
+    Keys,
+    /// No attributes, only entity names.
+    None,
+}
+
+impl From<AttributeFilter> for erd::ast::AttributeFilter {
+    fn from(filter: AttributeFilter) -> Self {
+        match filter {
+            AttributeFilter::All => erd::ast::AttributeFilter::All,
+            AttributeFilter::Keys => erd::ast::AttributeFilter::Keys,
+            AttributeFilter::None => erd::ast::AttributeFilter::Hidden,
+        }
+    }
+}
+
+/// How to mark keys, see [`render::KeyStyle`].
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum KeyStyle {
+    /// Underline primary keys and italicise foreign keys.
+    Markup,
+    /// Add a PK or FK badge before their names.
+    Badges,
+    /// Both underline or italicise them and add badges.
+    Both,
+}
+
+impl From<KeyStyle> for render::KeyStyle {
+    fn from(style: KeyStyle) -> Self {
+        match style {
+            KeyStyle::Markup => render::KeyStyle::Markup,
+            KeyStyle::Badges => render::KeyStyle::Badges,
+            KeyStyle::Both => render::KeyStyle::Both,
+        }
+    }
+}
+
+/// How to draw cardinalities, see [`erd::ast::Notation`].
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum Notation {
+    /// As text next to each end, e.g. 0..N.
+    Labels,
+    /// As crow's foot arrowheads.
+    CrowsFoot,
+}
+
+impl From<Notation> for erd::ast::Notation {
+    fn from(notation: Notation) -> Self {
+        match notation {
+            Notation::Labels => erd::ast::Notation::Labels,
+            Notation::CrowsFoot => erd::ast::Notation::CrowsFoot,
+        }
+    }
+}
+
+/// What to do with duplicate entities, see [`DuplicateEntityPolicy`].
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum DuplicateEntities {
+    /// Warn, and keep every declaration.
+    Warn,
+    /// Fail with an error.
+    Error,
+    /// Append later declarations' attributes to the first.
+    Merge,
+}
+
+impl From<DuplicateEntities> for DuplicateEntityPolicy {
+    fn from(policy: DuplicateEntities) -> Self {
+        match policy {
+            DuplicateEntities::Warn => DuplicateEntityPolicy::Warn,
+            DuplicateEntities::Error => DuplicateEntityPolicy::Error,
+            DuplicateEntities::Merge => DuplicateEntityPolicy::Merge,
+        }
+    }
+}
+
+fn backend_names() -> PossibleValuesParser {
+    PossibleValuesParser::new(render::Backends::default().names())
+}
+
+fn theme_names() -> PossibleValuesParser {
+    PossibleValuesParser::new(theme::THEMES.iter().map(|t| t.name))
+}
+
+fn rankdirs() -> PossibleValuesParser {
+    PossibleValuesParser::new(render::RANKDIRS)
+}
+
+fn layouts() -> PossibleValuesParser {
+    PossibleValuesParser::new(render::LAYOUTS)
+}
+
+fn edge_types() -> PossibleValuesParser {
+    PossibleValuesParser::new(render::EDGE_TYPES.iter().map(|(name, _)| *name))
+}
+
+fn parse_ident_chars(chars: &str) -> Result<String, String> {
+    match invalid_ident_char(chars) {
+        Some(c) => Err(format!("{:?} can't be used in unquoted names", c)),
+        None => Ok(chars.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+    use super::*;
+
+    #[test]
+    fn verify() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn default_command() {
+        let cli = Cli::try_parse_from(["erd", "-f", "er", "a.er", "-i", "b.er"]).unwrap();
+        match cli.into_command() {
+            Command::Render(args) => {
+                assert_eq!(args.format.as_deref(), Some("er"));
+                assert_eq!(args.input.paths(), vec!["b.er", "a.er"]);
+            },
+            c => panic!("unexpected command: {:?}", c),
+        }
+
+        let cli = Cli::try_parse_from(["erd", "lint", "-q", "--duplicate-entities", "merge", "a.er"]).unwrap();
+        match cli.into_command() {
+            Command::Lint(args) => {
+                assert_eq!(args.input.duplicate_entity_policy(), DuplicateEntityPolicy::Merge);
+                assert!(!args.allow_unknown_entities);
+                assert!(!args.input.lenient);
+                assert_eq!(args.input.ident_chars(Some(".")), ".");
+            },
+            c => panic!("unexpected command: {:?}", c),
+        }
+    }
+
+    #[test]
+    fn format_spellings() {
+        for args in &[&["erd", "-f", "er"], &["erd", "--format", "er"], &["erd", "--to", "er"]] {
+            match Cli::try_parse_from(args.iter()).unwrap().into_command() {
+                Command::Render(args) => assert_eq!(args.format.as_deref(), Some("er")),
+                c => panic!("unexpected command: {:?}", c),
+            }
+        }
+    }
+
+    #[test]
+    fn ident_chars() {
+        let ident_chars = |args: &[&str], config| match Cli::try_parse_from(args).unwrap().into_command() {
+            Command::Render(args) => args.input.ident_chars(config),
+            c => panic!("unexpected command: {:?}", c),
+        };
+        assert_eq!(ident_chars(&["erd", "--ident-chars", ".-"], Some("$")), ".-");
+        assert_eq!(ident_chars(&["erd"], Some("$")), "$");
+        assert_eq!(ident_chars(&["erd"], None), "");
+
+        let err = Cli::try_parse_from(["erd", "--ident-chars", ".:"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+        assert!(err.to_string().contains("':' can't be used in unquoted names"), "{}", err);
+    }
+
+    #[test]
+    fn invalid() {
+        for args in &[
+            &["erd", "-f", "png"][..],
+            &["erd", "--rankdir", "up"],
+            &["erd", "--title", "t", "--no-title"],
+            &["erd", "--depth", "2"],
+            &["erd", "-q", "-v"],
+            &["erd", "diff", "a.er"],
+            &["erd", "--row-template", "{size}"],
+        ] {
+            assert!(Cli::try_parse_from(*args).is_err(), "{:?}", args);
+        }
+    }
+}
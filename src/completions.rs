@@ -0,0 +1,36 @@
+use clap::CommandFactory;
+use clap_complete::Shell;
+use crate::cli::Cli;
+
+/// Returns a completion script for the given shell.
+pub fn generate(shell: Shell, prog: &str) -> String {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), prog, &mut script);
+    String::from_utf8(script).expect("completion script should be UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+    use super::*;
+
+    #[test]
+    fn all_shells() {
+        for shell in Shell::value_variants() {
+            let script = generate(*shell, "erd");
+            assert!(script.contains("keep-going"), "{}", shell);
+            assert!(script.contains("validate"), "{}", shell);
+        }
+    }
+
+    #[test]
+    fn values() {
+        let script = generate(Shell::Bash, "erd");
+        assert!(script.contains("complete -F _erd"));
+        assert!(script.contains("dot er"));
+
+        let script = generate(Shell::Zsh, "erd");
+        assert!(script.contains("#compdef erd"));
+        assert!(script.contains("compound"));
+    }
+}
@@ -0,0 +1,315 @@
+use std::{env, fs, path::{Path, PathBuf}, str::FromStr};
+use erd::ast::{canonical_key, GlobalOption, GlobalOptionType, OptionMap, Span, EDGE_STYLES};
+use erd::error::ErdError;
+use erd::parser::invalid_ident_char;
+use erd::render::{RenderOptions, RowTemplate, LAYOUTS, RANKDIRS};
+use erd::theme::Theme;
+
+/// Config file looked for in the current directory, so that it can be shared
+/// by everyone working on a project.
+const PROJECT_CONFIG: &str = "erd.toml";
+/// Config file looked for in the user's home directory.
+const USER_CONFIG: &str = ".erdrc";
+
+/// Defaults loaded from a TOML config file. Command line flags take
+/// precedence over anything set here.
+///
+/// ```toml
+/// format = "dot"
+/// theme = "pastel"
+/// edge = "ortho"
+/// rankdir = "TB"
+/// layout = "neato"
+/// font = "Courier"
+/// bgcolor = "#fdfdf6"
+/// edge-color = "black"
+/// edge-style = "solid"
+/// ident-chars = ".-"
+/// row-template = "{key }{name}{: type}"
+/// schema-clusters = true
+/// cluster-color = "gray50"
+/// cluster-bgcolor = "#f8f8f8"
+///
+/// [entity]
+/// bgcolor = "#ececfc"
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub format: Option<String>,
+    /// Name of a built-in theme, which the other settings are applied on
+    /// top of.
+    pub theme: Option<String>,
+    pub edge: Option<String>,
+    pub rankdir: Option<String>,
+    pub layout: Option<String>,
+    pub font: Option<String>,
+    pub bgcolor: Option<String>,
+    pub edge_color: Option<String>,
+    pub edge_style: Option<String>,
+    /// Characters allowed in unquoted identifiers besides letters, digits
+    /// and `_`.
+    pub ident_chars: Option<String>,
+    pub row_template: Option<RowTemplate>,
+    /// Whether to group entities by the schema their names are qualified
+    /// with.
+    pub schema_clusters: Option<bool>,
+    pub cluster_style: Option<String>,
+    pub cluster_color: Option<String>,
+    pub cluster_bgcolor: Option<String>,
+    /// Default options from the `[title]`, `[header]`, `[entity]`,
+    /// `[relationship]` and `[graph]` tables, which the input's own options
+    /// and directives override.
+    pub defaults: Vec<GlobalOption>,
+}
+
+impl Config {
+    /// Loads the config file at `path` if given, otherwise the first of
+    /// `./erd.toml` and `~/.erdrc` which exists. Having no config file at all
+    /// isn't an error.
+    pub fn find(path: Option<&str>) -> Result<Self, String> {
+        if let Some(path) = path {
+            return Self::load(Path::new(path));
+        }
+
+        let mut candidates = vec![PathBuf::from(PROJECT_CONFIG)];
+        if let Some(home) = home_dir() {
+            candidates.push(home.join(USER_CONFIG));
+        }
+        match candidates.into_iter().find(|p| p.is_file()) {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|s| s.parse())
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Overrides render defaults with any set in the config.
+    pub fn apply(&self, options: &mut RenderOptions) {
+        let settings = [
+            (&self.rankdir, &mut options.rankdir),
+            (&self.font, &mut options.font),
+            (&self.edge_color, &mut options.edge_color),
+            (&self.edge_style, &mut options.edge_style),
+            (&self.cluster_style, &mut options.cluster_style),
+            (&self.cluster_color, &mut options.cluster_color),
+        ];
+        for (value, option) in settings {
+            if let Some(v) = value {
+                *option = v.clone();
+            }
+        }
+        if let Some(c) = &self.bgcolor {
+            options.background_color = Some(c.clone());
+        }
+        if let Some(layout) = &self.layout {
+            options.layout = Some(layout.clone());
+        }
+        if let Some(template) = &self.row_template {
+            options.row_template = template.clone();
+        }
+        if let Some(clusters) = self.schema_clusters {
+            options.schema_clusters = clusters;
+        }
+        if let Some(c) = &self.cluster_bgcolor {
+            options.cluster_background = Some(c.clone());
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let table: toml::Table = s.parse().map_err(|err: toml::de::Error| err.message().to_owned())?;
+
+        let mut config = Config::default();
+        for (key, value) in table {
+            let option_type = match key.as_str() {
+                "format" => { config.format = Some(string(&key, value)?); continue },
+                "theme" => {
+                    let theme = string(&key, value)?;
+                    if Theme::by_name(&theme).is_none() {
+                        return Err(format!("unknown theme: {}", theme));
+                    }
+                    config.theme = Some(theme);
+                    continue
+                },
+                "edge" => { config.edge = Some(string(&key, value)?); continue },
+                "rankdir" => {
+                    let rankdir = string(&key, value)?;
+                    if !RANKDIRS.contains(&rankdir.as_str()) {
+                        return Err(format!("rankdir must be one of: {}", RANKDIRS.join(", ")));
+                    }
+                    config.rankdir = Some(rankdir);
+                    continue
+                },
+                "layout" => {
+                    let layout = string(&key, value)?;
+                    if !LAYOUTS.contains(&layout.as_str()) {
+                        return Err(format!("layout must be one of: {}", LAYOUTS.join(", ")));
+                    }
+                    config.layout = Some(layout);
+                    continue
+                },
+                "font" => { config.font = Some(string(&key, value)?); continue },
+                "bgcolor" => { config.bgcolor = Some(string(&key, value)?); continue },
+                "edge-color" => { config.edge_color = Some(string(&key, value)?); continue },
+                "edge-style" => {
+                    let style = string(&key, value)?;
+                    if !EDGE_STYLES.contains(&style.as_str()) {
+                        return Err(ErdError::invalid_edge_style(&key, &style).to_string());
+                    }
+                    config.edge_style = Some(style);
+                    continue
+                },
+                "schema-clusters" => match value {
+                    toml::Value::Boolean(b) => { config.schema_clusters = Some(b); continue },
+                    _ => return Err(format!("{} must be a boolean", key)),
+                },
+                "cluster-style" => { config.cluster_style = Some(string(&key, value)?); continue },
+                "cluster-color" => { config.cluster_color = Some(string(&key, value)?); continue },
+                "cluster-bgcolor" => { config.cluster_bgcolor = Some(string(&key, value)?); continue },
+                "ident-chars" => {
+                    let chars = string(&key, value)?;
+                    if let Some(c) = invalid_ident_char(&chars) {
+                        return Err(format!("ident-chars can't include {:?}", c));
+                    }
+                    config.ident_chars = Some(chars);
+                    continue
+                },
+                "row-template" => {
+                    let template = string(&key, value)?.parse()
+                        .map_err(|err| format!("row-template: {}", err))?;
+                    config.row_template = Some(template);
+                    continue
+                },
+                "title" => GlobalOptionType::Title,
+                "header" => GlobalOptionType::Header,
+                "entity" => GlobalOptionType::Entity,
+                "relationship" => GlobalOptionType::Relationship,
+                "graph" => GlobalOptionType::Graph,
+                _ => return Err(format!("unknown config key: {}", key)),
+            };
+
+            let table = match value {
+                toml::Value::Table(t) => t,
+                _ => return Err(format!("{} must be a table", key)),
+            };
+            let mut options = OptionMap::new();
+            for (k, v) in table {
+                let v = match v {
+                    toml::Value::String(s) => s,
+                    toml::Value::Integer(i) => i.to_string(),
+                    _ => return Err(format!("{}.{} must be a string or integer", key, k)),
+                };
+                options.insert(canonical_key(&k).to_owned(), v);
+            }
+            config.defaults.push(GlobalOption { option_type, options, span: Span::default() });
+        }
+
+        Ok(config)
+    }
+}
+
+fn string(key: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        _ => Err(format!("{} must be a string", key)),
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use erd::parser::{parse_erd_with, ParseOptions};
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config: Config = r##"
+format = "er"
+edge = "ortho"
+font = "Courier"
+ident-chars = ".$"
+row-template = "{field}{ (type)}"
+layout = "fdp"
+schema-clusters = true
+cluster-bgcolor = "#f8f8f8"
+
+[entity]
+bgcolor = "#ececfc"
+size = 12
+
+[title]
+label = "Schema"
+"##.parse().unwrap();
+
+        assert_eq!(config.format.as_deref(), Some("er"));
+        assert_eq!(config.edge.as_deref(), Some("ortho"));
+        assert_eq!(config.ident_chars.as_deref(), Some(".$"));
+        assert_eq!(config.defaults.len(), 2);
+        assert_eq!(config.defaults[0].option_type, GlobalOptionType::Entity);
+        assert_eq!(config.defaults[0].options["bgcolor"], "#ececfc");
+        assert_eq!(config.defaults[0].options["size"], "12");
+        assert_eq!(config.defaults[1].option_type, GlobalOptionType::Title);
+
+        let mut options = RenderOptions::default();
+        config.apply(&mut options);
+        assert_eq!(options.font, "Courier");
+        assert_eq!(options.rankdir, RenderOptions::default().rankdir);
+        assert_eq!(options.row_template, "{field}{ (type)}".parse().unwrap());
+        assert_eq!(options.layout.as_deref(), Some("fdp"));
+        assert!(options.schema_clusters);
+        assert_eq!(options.cluster_background.as_deref(), Some("#f8f8f8"));
+        assert_eq!(options.cluster_color, "gray70");
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config: Config = "[entity]\nbgcolor = \"blue\"\n".parse().unwrap();
+        let options = ParseOptions { defaults: config.defaults, ..Default::default() };
+        let erd = parse_erd_with("[a] {bgcolor: \"red\"}\n[b]\n", &options).unwrap();
+        assert_eq!(erd.entities[0].options.background_color.as_deref(), Some("red"));
+        assert_eq!(erd.entities[0].header_options.background_color.as_deref(), Some("red"));
+        assert_eq!(erd.entities[1].options.background_color.as_deref(), Some("blue"));
+        assert_eq!(erd.entities[1].header_options.background_color, None);
+
+        // Directives in the input override them too.
+        let erd = parse_erd_with("entity {bgcolor: \"green\"}\n[a]\n", &options).unwrap();
+        assert_eq!(erd.entities[0].options.background_color.as_deref(), Some("green"));
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert_eq!("colour = \"red\"".parse::<Config>(), Err("unknown config key: colour".to_owned()));
+        assert_eq!("font = 1".parse::<Config>(), Err("font must be a string".to_owned()));
+        assert_eq!("entity = \"x\"".parse::<Config>(), Err("entity must be a table".to_owned()));
+        assert_eq!(
+            "[entity]\ncolor = [1]".parse::<Config>(),
+            Err("entity.color must be a string or integer".to_owned()),
+        );
+        assert_eq!(
+            "rankdir = \"up\"".parse::<Config>(),
+            Err("rankdir must be one of: LR, TB, RL, BT".to_owned()),
+        );
+        assert_eq!(
+            "edge-style = \"wavy\"".parse::<Config>(),
+            Err("could not parse edge-style as solid, dashed, dotted or bold: wavy".to_owned()),
+        );
+        assert_eq!("theme = \"neon\"".parse::<Config>(), Err("unknown theme: neon".to_owned()));
+        assert_eq!("ident-chars = \".:\"".parse::<Config>(), Err("ident-chars can't include ':'".to_owned()));
+        assert!("row-template = \"{size}\"".parse::<Config>().unwrap_err().starts_with("row-template: unknown placeholder"));
+        assert_eq!("schema-clusters = \"yes\"".parse::<Config>(), Err("schema-clusters must be a boolean".to_owned()));
+        assert!("font = ".parse::<Config>().is_err());
+    }
+}
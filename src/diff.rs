@@ -0,0 +1,272 @@
+use std::fmt;
+use crate::ast::{Entity, Erd, NaryRelation, Note, Relation};
+use crate::format::{escape, nary_relation, quote_ident, relation_operator};
+
+/// A difference between two versions of a diagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    TitleChanged,
+    GraphChanged,
+    EntityAdded { entity: String },
+    EntityRemoved { entity: String },
+    /// Anything other than the entity's attributes differs, such as its
+    /// options or indexes.
+    EntityChanged { entity: String },
+    AttributeAdded { entity: String, attribute: String },
+    AttributeRemoved { entity: String, attribute: String },
+    /// The attribute's keys, type or options differ.
+    AttributeChanged { entity: String, attribute: String },
+    RelationshipAdded(Relation),
+    RelationshipRemoved(Relation),
+    /// A relationship with the same name, or if unnamed, between the same
+    /// entities with the same kind and cardinalities, has changed.
+    RelationshipChanged(Relation),
+    NaryRelationshipAdded(NaryRelation),
+    NaryRelationshipRemoved(NaryRelation),
+    /// An n-ary relationship with the same name has changed.
+    NaryRelationshipChanged(NaryRelation),
+    EnumAdded { name: String },
+    EnumRemoved { name: String },
+    /// The enum's values differ.
+    EnumChanged { name: String },
+    NoteAdded(Note),
+    NoteRemoved(Note),
+}
+
+impl Erd {
+    /// Compares this diagram against a newer version of it, returning the
+    /// changes needed to get from one to the other.
+    ///
+    /// Entities and attributes are matched by name, and relationships by
+    /// name, or if unnamed, by their entities and cardinalities. N-ary
+    /// relationships and enums are matched by name. Notes have nothing to
+    /// match them by, so a changed note is a removal and an addition.
+    /// Changes are listed in the order the elements appear, with removals
+    /// before additions.
+    pub fn diff(&self, new: &Erd) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        if self.title_options != new.title_options {
+            changes.push(Change::TitleChanged);
+        }
+        if self.graph_options != new.graph_options {
+            changes.push(Change::GraphChanged);
+        }
+
+        for e in &self.entities {
+            match new.entity(&e.name) {
+                Some(ne) => diff_entity(e, ne, &mut changes),
+                None => changes.push(Change::EntityRemoved { entity: e.name.to_string() }),
+            }
+        }
+        for e in &new.entities {
+            if self.entity(&e.name).is_none() {
+                changes.push(Change::EntityAdded { entity: e.name.to_string() });
+            }
+        }
+
+        // Relationships present unchanged in both are ignored, and the rest
+        // paired up where possible to be reported as changes.
+        let unmatched: Vec<&Relation> = new.relationships.iter()
+            .filter(|r| !self.relationships.contains(r))
+            .collect();
+        let mut paired = vec![false; unmatched.len()];
+        for r in &self.relationships {
+            if new.relationships.contains(r) {
+                continue;
+            }
+            match (0..unmatched.len()).find(|&i| !paired[i] && same_relation(r, unmatched[i])) {
+                Some(i) => {
+                    paired[i] = true;
+                    changes.push(Change::RelationshipChanged(unmatched[i].clone()));
+                },
+                None => changes.push(Change::RelationshipRemoved(r.clone())),
+            }
+        }
+        for (r, paired) in unmatched.into_iter().zip(paired) {
+            if !paired {
+                changes.push(Change::RelationshipAdded(r.clone()));
+            }
+        }
+
+        for r in &self.nary_relationships {
+            match new.nary_relationships.iter().find(|nr| nr.name == r.name) {
+                Some(nr) if nr != r => changes.push(Change::NaryRelationshipChanged(nr.clone())),
+                Some(_) => {},
+                None => changes.push(Change::NaryRelationshipRemoved(r.clone())),
+            }
+        }
+        for r in &new.nary_relationships {
+            if !self.nary_relationships.iter().any(|or| or.name == r.name) {
+                changes.push(Change::NaryRelationshipAdded(r.clone()));
+            }
+        }
+
+        for t in &self.enums {
+            match new.enumeration(&t.name) {
+                Some(nt) if nt != t => changes.push(Change::EnumChanged { name: t.name.to_string() }),
+                Some(_) => {},
+                None => changes.push(Change::EnumRemoved { name: t.name.to_string() }),
+            }
+        }
+        for t in &new.enums {
+            if self.enumeration(&t.name).is_none() {
+                changes.push(Change::EnumAdded { name: t.name.to_string() });
+            }
+        }
+
+        for n in &self.notes {
+            if !new.notes.contains(n) {
+                changes.push(Change::NoteRemoved(n.clone()));
+            }
+        }
+        for n in &new.notes {
+            if !self.notes.contains(n) {
+                changes.push(Change::NoteAdded(n.clone()));
+            }
+        }
+
+        changes
+    }
+}
+
+fn diff_entity(old: &Entity, new: &Entity, changes: &mut Vec<Change>) {
+    if old.definition() != new.definition() {
+        changes.push(Change::EntityChanged { entity: new.name.to_string() });
+    }
+
+    for a in &old.attribs {
+        let (entity, attribute) = (new.name.to_string(), a.field.to_string());
+        match new.attribute(&a.field) {
+            Some(na) if na != a => changes.push(Change::AttributeChanged { entity, attribute }),
+            Some(_) => {},
+            None => changes.push(Change::AttributeRemoved { entity, attribute }),
+        }
+    }
+    for a in &new.attribs {
+        if old.attribute(&a.field).is_none() {
+            changes.push(Change::AttributeAdded { entity: new.name.to_string(), attribute: a.field.to_string() });
+        }
+    }
+}
+
+fn same_relation(a: &Relation, b: &Relation) -> bool {
+    match (&a.name, &b.name) {
+        (Some(x), Some(y)) => x == y,
+        (Some(_), None) | (None, Some(_)) => false,
+        (None, None) => {
+            a.entity1 == b.entity1 && a.entity2 == b.entity2
+                && a.kind == b.kind && a.card1 == b.card1 && a.card2 == b.card2
+        },
+    }
+}
+
+/// Writes each change on the model of a line of a unified diff: `+` for
+/// additions, `-` for removals and `~` for changes, followed by the element
+/// in `.er` syntax.
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let relation = |f: &mut fmt::Formatter<'_>, prefix: char, r: &Relation| {
+            write!(f, "{} ", prefix)?;
+            if let Some(name) = &r.name {
+                write!(f, "rel {}: ", quote_ident(name))?;
+            }
+            write!(f, "{} {} {}", quote_ident(&r.entity1), relation_operator(r), quote_ident(&r.entity2))
+        };
+
+        match self {
+            Change::TitleChanged => write!(f, "~ title"),
+            Change::GraphChanged => write!(f, "~ graph"),
+            Change::EntityAdded { entity } => write!(f, "+ [{}]", quote_ident(entity)),
+            Change::EntityRemoved { entity } => write!(f, "- [{}]", quote_ident(entity)),
+            Change::EntityChanged { entity } => write!(f, "~ [{}]", quote_ident(entity)),
+            Change::AttributeAdded { entity, attribute } => {
+                write!(f, "+ [{}] {}", quote_ident(entity), quote_ident(attribute))
+            },
+            Change::AttributeRemoved { entity, attribute } => {
+                write!(f, "- [{}] {}", quote_ident(entity), quote_ident(attribute))
+            },
+            Change::AttributeChanged { entity, attribute } => {
+                write!(f, "~ [{}] {}", quote_ident(entity), quote_ident(attribute))
+            },
+            Change::RelationshipAdded(r) => relation(f, '+', r),
+            Change::RelationshipRemoved(r) => relation(f, '-', r),
+            Change::RelationshipChanged(r) => relation(f, '~', r),
+            Change::NaryRelationshipAdded(r) => write!(f, "+ {}", nary_relation(r)),
+            Change::NaryRelationshipRemoved(r) => write!(f, "- {}", nary_relation(r)),
+            Change::NaryRelationshipChanged(r) => write!(f, "~ {}", nary_relation(r)),
+            Change::EnumAdded { name } => write!(f, "+ enum {}", quote_ident(name)),
+            Change::EnumRemoved { name } => write!(f, "- enum {}", quote_ident(name)),
+            Change::EnumChanged { name } => write!(f, "~ enum {}", quote_ident(name)),
+            Change::NoteAdded(n) => write!(f, "+ note \"{}\"", escape(&n.text, '"')),
+            Change::NoteRemoved(n) => write!(f, "- note \"{}\"", escape(&n.text, '"')),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_erd;
+
+    #[test]
+    fn no_changes() {
+        let s = include_str!("../examples/nfldb.er");
+        let erd = parse_erd(s).unwrap();
+        assert_eq!(erd.diff(&erd.clone()), vec![]);
+    }
+
+    #[test]
+    fn changes() {
+        let old = parse_erd(r#"
+[a]
+*id
+name
+old
+[b]
+*id
+[gone]
+a 1--* b
+a 1--1 gone
+b 1--1 a
+rel owner: a 1--1 b
+a ^-- b
+r(a, b, gone)
+s(a, b, b)
+"#).unwrap();
+        let new = parse_erd(r#"
+title {label: "T"}
+[a] {color: "red"}
+*id
+name {type: "text"}
+new
+[b]
+*id
+["new one"]
+a 1--* b {label: "has"}
+b 1--? a
+rel owner: b 1--1 a
+r(a, b, a)
+t(a, b, b)
+"#).unwrap();
+
+        let changes: Vec<_> = old.diff(&new).iter().map(ToString::to_string).collect();
+        assert_eq!(changes, vec![
+            "~ title",
+            "~ [a]",
+            "~ [a] name",
+            "- [a] old",
+            "+ [a] new",
+            "- [gone]",
+            "+ [\"new one\"]",
+            "~ a 1--* b",
+            "- a 1--1 gone",
+            "- b 1--1 a",
+            "~ rel owner: b 1--1 a",
+            "- a ^-- b",
+            "+ b 1--? a",
+            "~ r(a, b, a)",
+            "- s(a, b, b)",
+            "+ t(a, b, b)",
+        ]);
+    }
+}
@@ -0,0 +1,305 @@
+use std::fmt;
+use crate::ast::Span;
+
+/// Errors produced while parsing an ERD and resolving its options.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErdError {
+    /// The input isn't valid `.er` syntax.
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// An option key isn't valid for the element it was given on.
+    UnknownOption {
+        scope: &'static str,
+        key: String,
+    },
+    /// An option was given a value that couldn't be interpreted.
+    InvalidOptionValue {
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+    /// The input parsed, but doesn't describe a valid diagram.
+    Semantic(String),
+    /// A file named by an `include` declaration couldn't be read or parsed.
+    Include {
+        path: String,
+        message: String,
+    },
+    /// An option value refers to a variable which hasn't been `set`.
+    UndefinedVariable {
+        name: String,
+    },
+    /// An entity was declared more than once, on the given lines. Lines of
+    /// declarations in included files are 0.
+    DuplicateEntity {
+        name: String,
+        first_line: usize,
+        line: usize,
+    },
+}
+
+impl ErdError {
+    pub(crate) fn invalid_integer(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "integer",
+        }
+    }
+
+    pub(crate) fn invalid_bool(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "true or false",
+        }
+    }
+
+    pub(crate) fn invalid_compass_point(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "compass point",
+        }
+    }
+
+    pub(crate) fn invalid_number(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "non-negative number",
+        }
+    }
+
+    /// Error for a line style other than one of
+    /// [`EDGE_STYLES`](crate::ast::EDGE_STYLES).
+    pub fn invalid_edge_style(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "solid, dashed, dotted or bold",
+        }
+    }
+
+    pub(crate) fn invalid_rankdir(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "LR, TB, RL or BT",
+        }
+    }
+
+    pub(crate) fn invalid_splines(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "spline, ortho, polyline, line, curved, compound or none",
+        }
+    }
+
+    pub(crate) fn invalid_layout(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "dot, neato, fdp, sfdp, circo, twopi, osage or patchwork",
+        }
+    }
+
+    pub(crate) fn invalid_attribute_filter(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "all, keys or none",
+        }
+    }
+
+    pub(crate) fn invalid_notation(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "labels or crows-foot",
+        }
+    }
+
+    pub(crate) fn invalid_alignment(key: &str, value: &str) -> Self {
+        ErdError::InvalidOptionValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: "left, center or right",
+        }
+    }
+
+    pub(crate) fn unknown_option(scope: &'static str, key: &str) -> Self {
+        ErdError::UnknownOption { scope, key: key.to_owned() }
+    }
+}
+
+impl fmt::Display for ErdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErdError::Parse { line, column, message } => {
+                write!(f, "line {}, column {}: {}", line, column, message)
+            },
+            ErdError::UnknownOption { scope, key } => write!(f, "invalid {} option: {}", scope, key),
+            ErdError::InvalidOptionValue { key, value, expected } => {
+                write!(f, "could not parse {} as {}: {}", key, expected, value)
+            },
+            ErdError::Semantic(msg) => write!(f, "{}", msg),
+            ErdError::Include { path, message } => write!(f, "in included file {}: {}", path, message),
+            ErdError::UndefinedVariable { name } => write!(f, "undefined variable: ${}", name),
+            ErdError::DuplicateEntity { name, first_line, line } => write!(
+                f,
+                "entity '{}' is declared more than once, on {} and {}",
+                name, describe_line(*first_line), describe_line(*line),
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ErdError {}
+
+/// Describes where a declaration on the given line is, for which 0 means an
+/// included file.
+fn describe_line(line: usize) -> String {
+    match line {
+        0 => "an included file".to_owned(),
+        n => format!("line {}", n),
+    }
+}
+
+/// How serious a diagnostic is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in an otherwise parseable diagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    /// Location of the declaration the problem was found in.
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A relationship refers to an entity which was never declared, along
+    /// with the most similar declared entity, if any is close.
+    UnknownEntity { entity: String, suggestion: Option<String> },
+    /// More than one relationship has the same name.
+    DuplicateRelationship { name: String },
+    /// An n-ary relationship has the same name as an entity, so the two
+    /// can't be told apart in the diagram.
+    RelationshipNameConflict { name: String },
+    /// More than one enum has the same name.
+    DuplicateEnum { name: String },
+    /// An enum has the same name as an entity, so the two can't be told
+    /// apart in the diagram.
+    EnumNameConflict { name: String },
+    /// An attribute refers to a field, written `entity.field`, which was
+    /// never declared.
+    UnknownReference { entity: String, attribute: String, target: String },
+    /// A note is attached to an entity which was never declared.
+    UnknownNoteEntity { entity: String },
+    /// An entity is declared more than once. Gives the line of the first
+    /// declaration, the diagnostic being located at the later one.
+    DuplicateEntity { entity: String, first_line: usize },
+    /// An entity declares the same attribute more than once.
+    DuplicateAttribute { entity: String, attribute: String },
+    /// An entity has no attributes.
+    EmptyEntity { entity: String },
+    /// A weak entity isn't part of any identifying relationship.
+    UnidentifiedWeakEntity { entity: String },
+    /// An entity's `pk` option lists a field it doesn't declare.
+    UnknownKeyAttribute { entity: String, attribute: String },
+    /// An index on an entity includes a field it doesn't declare.
+    UnknownIndexColumn { entity: String, column: String },
+    /// Options were set which have no effect in combination.
+    ConflictingOptions { element: String, message: String },
+    /// An option with an unknown key was skipped, as the input was parsed
+    /// leniently.
+    IgnoredOption { scope: &'static str, key: String },
+}
+
+impl Diagnostic {
+    pub fn error(kind: DiagnosticKind) -> Self {
+        Self { severity: Severity::Error, kind, span: Span::default() }
+    }
+
+    pub fn warning(kind: DiagnosticKind) -> Self {
+        Self { severity: Severity::Warning, kind, span: Span::default() }
+    }
+
+    /// Locates the diagnostic at the given declaration.
+    pub fn at(self, span: Span) -> Self {
+        Self { span, ..self }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.kind)
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::UnknownEntity { entity, suggestion } => {
+                write!(f, "relationship refers to undeclared entity '{}'", entity)?;
+                match suggestion {
+                    Some(s) => write!(f, " (did you mean '{}'?)", s),
+                    None => Ok(()),
+                }
+            },
+            DiagnosticKind::DuplicateRelationship { name } => {
+                write!(f, "more than one relationship is named '{}'", name)
+            },
+            DiagnosticKind::RelationshipNameConflict { name } => {
+                write!(f, "relationship '{}' has the same name as an entity", name)
+            },
+            DiagnosticKind::DuplicateEnum { name } => write!(f, "more than one enum is named '{}'", name),
+            DiagnosticKind::EnumNameConflict { name } => {
+                write!(f, "enum '{}' has the same name as an entity", name)
+            },
+            DiagnosticKind::UnknownReference { entity, attribute, target } => {
+                write!(f, "attribute '{}.{}' refers to undeclared field '{}'", entity, attribute, target)
+            },
+            DiagnosticKind::UnknownNoteEntity { entity } => {
+                write!(f, "note is attached to undeclared entity '{}'", entity)
+            },
+            DiagnosticKind::DuplicateEntity { entity, first_line } => {
+                write!(f, "entity '{}' was already declared on {}", entity, describe_line(*first_line))
+            },
+            DiagnosticKind::DuplicateAttribute { entity, attribute } => {
+                write!(f, "entity '{}' declares attribute '{}' more than once", entity, attribute)
+            },
+            DiagnosticKind::EmptyEntity { entity } => write!(f, "entity '{}' has no attributes", entity),
+            DiagnosticKind::UnidentifiedWeakEntity { entity } => {
+                write!(f, "weak entity '{}' has no identifying relationship", entity)
+            },
+            DiagnosticKind::UnknownKeyAttribute { entity, attribute } => {
+                write!(f, "primary key of entity '{}' refers to undeclared attribute '{}'", entity, attribute)
+            },
+            DiagnosticKind::UnknownIndexColumn { entity, column } => {
+                write!(f, "index on entity '{}' refers to undeclared attribute '{}'", entity, column)
+            },
+            DiagnosticKind::ConflictingOptions { element, message } => write!(f, "{}: {}", element, message),
+            DiagnosticKind::IgnoredOption { scope, key } => write!(f, "ignored unknown {} option: {}", scope, key),
+        }
+    }
+}
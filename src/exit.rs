@@ -0,0 +1,114 @@
+use std::fmt;
+
+/// Exit status when `diff` finds changes.
+pub const CHANGES: i32 = 1;
+/// Exit status for invalid arguments or config.
+pub const USAGE: i32 = 2;
+pub const PARSE: i32 = 3;
+pub const VALIDATION: i32 = 4;
+pub const IO: i32 = 5;
+pub const RENDER: i32 = 6;
+
+/// Why a command failed, which determines the exit status.
+#[derive(Debug)]
+pub enum Failure {
+    Usage(String),
+    Parse(String),
+    /// The input parsed, but has errors, or inputs couldn't be merged.
+    Validation(String),
+    Io(String),
+    Render(String),
+}
+
+impl Failure {
+    pub fn code(&self) -> i32 {
+        match self {
+            Failure::Usage(_) => USAGE,
+            Failure::Parse(_) => PARSE,
+            Failure::Validation(_) => VALIDATION,
+            Failure::Io(_) => IO,
+            Failure::Render(_) => RENDER,
+        }
+    }
+}
+
+impl Failure {
+    /// Combines failures into one reporting all of them, with the exit
+    /// status of the first, or returns `None` if there are none.
+    pub fn combine(failures: impl IntoIterator<Item = Failure>) -> Option<Failure> {
+        let mut failures = failures.into_iter();
+        let mut combined = failures.next()?;
+        for f in failures {
+            let message = combined.message_mut();
+            message.push('\n');
+            message.push_str(&f.to_string());
+        }
+        Some(combined)
+    }
+
+    fn message_mut(&mut self) -> &mut String {
+        match self {
+            Failure::Usage(s)
+            | Failure::Parse(s)
+            | Failure::Validation(s)
+            | Failure::Io(s)
+            | Failure::Render(s) => s,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Usage(s)
+            | Failure::Parse(s)
+            | Failure::Validation(s)
+            | Failure::Io(s)
+            | Failure::Render(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// How much is printed to stderr besides fatal errors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors.
+    Quiet,
+    /// Errors and warnings.
+    Normal,
+    /// Errors, warnings, and what's being read and written.
+    Verbose,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinct_codes() {
+        let failures = [
+            Failure::Usage(String::new()),
+            Failure::Parse(String::new()),
+            Failure::Validation(String::new()),
+            Failure::Io(String::new()),
+            Failure::Render(String::new()),
+        ];
+        let mut codes: Vec<_> = failures.iter().map(Failure::code).collect();
+        codes.push(CHANGES);
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), failures.len() + 1);
+        assert!(!codes.contains(&0));
+    }
+
+    #[test]
+    fn combine() {
+        assert!(Failure::combine(Vec::new()).is_none());
+        let combined = Failure::combine(vec![
+            Failure::Parse("a.er: bad".to_owned()),
+            Failure::Io("b.er: missing".to_owned()),
+        ]).unwrap();
+        assert_eq!(combined.code(), PARSE);
+        assert_eq!(combined.to_string(), "a.er: bad\nb.er: missing");
+    }
+}
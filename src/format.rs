@@ -0,0 +1,389 @@
+use std::borrow::Cow;
+use std::io::{Write, Result};
+use crate::ast;
+use crate::render::{Backend, Fmt, Output};
+
+/// Backend producing canonical `.er` source text.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Er;
+
+impl Backend for Er {
+    fn name(&self) -> &'static str {
+        "er"
+    }
+
+    fn render(&self, erd: &ast::Erd, w: &mut dyn Write) -> Result<()> {
+        Formatter::new(w).format_erd(erd)
+    }
+}
+
+/// Order in which entity and header options are written.
+const ENTITY_KEYS: &[&str] = &[
+    ast::OPT_LABEL, ast::OPT_SIZE, ast::OPT_FONT, ast::OPT_COLOR,
+    ast::OPT_BACKGROUND_COLOR, ast::OPT_BORDER, ast::OPT_BORDER_COLOR,
+];
+
+/// Formats an `ast::Erd` as canonical `.er` source text.
+///
+/// Output uses two-space indented attributes, a blank line after each entity,
+/// identifiers quoted only when needed, and options in a fixed order. Global
+/// directives are folded into the elements they apply to, and comments aren't
+/// preserved.
+pub fn format_erd(erd: &ast::Erd) -> String {
+    let mut out = String::new();
+    Formatter::new(Fmt(&mut out)).format_erd(erd).expect("writing to a String can't fail");
+    out
+}
+
+/// Writes an `ast::Erd` back out as canonical `.er` source text.
+pub struct Formatter<W: Output> {
+    w: W
+}
+
+impl<W: Output> Formatter<W> {
+    pub fn new(w: W) -> Self {
+        Self { w }
+    }
+
+    pub fn format_erd(&mut self, erd: &ast::Erd) -> Result<()> {
+        for path in &erd.includes {
+            writeln!(self.w, "include \"{}\"", escape(path, '"'))?;
+        }
+        if !erd.includes.is_empty() {
+            writeln!(self.w)?;
+        }
+
+        for (name, value) in &erd.variables {
+            writeln!(self.w, "set {} = \"{}\"", name, escape(value, '"'))?;
+        }
+        if !erd.variables.is_empty() {
+            writeln!(self.w)?;
+        }
+
+        let title_opts = erd.title_options.to_options();
+        if !title_opts.is_empty() {
+            write!(self.w, "title")?;
+            self.options(&title_opts)?;
+            writeln!(self.w)?;
+            writeln!(self.w)?;
+        }
+
+        let graph_opts = erd.graph_options.to_options();
+        if !graph_opts.is_empty() {
+            write!(self.w, "graph")?;
+            self.options(&graph_opts)?;
+            writeln!(self.w)?;
+            writeln!(self.w)?;
+        }
+
+        for t in &erd.enums {
+            write!(self.w, "enum {}", quote_ident(&t.name))?;
+            self.options(&t.to_options())?;
+            writeln!(self.w)?;
+        }
+        if !erd.enums.is_empty() {
+            writeln!(self.w)?;
+        }
+
+        for e in &erd.entities {
+            self.entity(e)?;
+            writeln!(self.w)?;
+        }
+
+        for r in &erd.relationships {
+            self.relationship(r)?;
+        }
+
+        for r in &erd.nary_relationships {
+            write!(self.w, "{}", nary_relation(r))?;
+            self.options(&r.options.to_options())?;
+            writeln!(self.w)?;
+        }
+
+        // Entities are already followed by a blank line.
+        let relations = !erd.relationships.is_empty() || !erd.nary_relationships.is_empty();
+        if relations && !erd.notes.is_empty() {
+            writeln!(self.w)?;
+        }
+        for n in &erd.notes {
+            write!(self.w, "note \"{}\"", escape(&n.text, '"'))?;
+            self.options(&n.to_options())?;
+            writeln!(self.w)?;
+        }
+
+        Ok(())
+    }
+
+    fn entity(&mut self, e: &ast::Entity) -> Result<()> {
+        if e.weak {
+            write!(self.w, "[[{}]]", quote_ident(&e.name))?;
+        } else {
+            write!(self.w, "[{}]", quote_ident(&e.name))?;
+        }
+
+        // Inline entity options apply to both the entity and its header, so
+        // fall back to header values for any key the entity doesn't set.
+        let mut opts = e.options.to_options();
+        for (k, v) in e.header_options.to_options() {
+            if !opts.iter().any(|(key, _)| *key == k) {
+                opts.push((k, v));
+            }
+        }
+        // Keep keys in one order whichever of the two set them, so that
+        // formatting the output again doesn't move them.
+        opts.sort_by_key(|(k, _)| ENTITY_KEYS.iter().position(|key| key == k));
+        if let Some(u) = &e.url {
+            opts.insert(0, (ast::OPT_URL, u.clone()));
+        }
+        if let Some(g) = &e.group {
+            opts.insert(0, (ast::OPT_GROUP, g.clone()));
+        }
+        if let Some(d) = &e.description {
+            opts.insert(0, (ast::OPT_DESCRIPTION, d.clone()));
+        }
+        if !e.primary_key.is_empty() {
+            opts.insert(0, (ast::OPT_PRIMARY_KEY, e.primary_key.join(", ")));
+        }
+        self.options(&opts)?;
+        writeln!(self.w)?;
+
+        for a in &e.attribs {
+            self.attribute(a)?;
+        }
+        for index in &e.indexes {
+            write!(self.w, "  index")?;
+            self.options(&index.to_options())?;
+            writeln!(self.w)?;
+        }
+
+        Ok(())
+    }
+
+    fn attribute(&mut self, a: &ast::Attribute) -> Result<()> {
+        write!(self.w, "  ")?;
+        if a.pk {
+            write!(self.w, "*")?;
+        }
+        if a.fk {
+            write!(self.w, "+")?;
+        }
+        write!(self.w, "{}", quote_ident(&a.field))?;
+        if let Some(r) = &a.references {
+            write!(self.w, " -> {}.{}", quote_ident(&r.entity), quote_ident(&r.field))?;
+        }
+        let mut opts = a.options.to_options();
+        if let Some(d) = &a.description {
+            opts.insert(0, (ast::OPT_DESCRIPTION, d.clone()));
+        }
+        if a.nullable {
+            opts.insert(0, (ast::OPT_NULLABLE, "true".to_owned()));
+        }
+        if a.unique {
+            opts.insert(0, (ast::OPT_UNIQUE, "true".to_owned()));
+        }
+        if let Some(t) = &a.datatype {
+            opts.insert(0, (ast::OPT_TYPE, t.clone()));
+        }
+        self.options(&opts)?;
+        writeln!(self.w)
+    }
+
+    fn relationship(&mut self, r: &ast::Relation) -> Result<()> {
+        if let Some(name) = &r.name {
+            write!(self.w, "rel {}: ", quote_ident(name))?;
+        }
+        write!(
+            self.w,
+            "{} {} {}",
+            quote_ident(&r.entity1),
+            relation_operator(r),
+            quote_ident(&r.entity2),
+        )?;
+        let mut opts = r.options.to_options();
+        if !r.attribs.is_empty() {
+            let fields: Vec<_> = r.attribs.iter().map(|a| a.field.as_str()).collect();
+            opts.push((ast::OPT_ATTRIBUTES, fields.join(", ")));
+        }
+        self.options(&opts)?;
+        writeln!(self.w)
+    }
+
+    fn options(&mut self, opts: &[(&str, String)]) -> Result<()> {
+        if opts.is_empty() {
+            return Ok(());
+        }
+
+        write!(self.w, " {{")?;
+        for (i, (k, v)) in opts.iter().enumerate() {
+            if i > 0 {
+                write!(self.w, ", ")?;
+            }
+            write!(self.w, "{}: \"{}\"", k, escape(v, '"'))?;
+        }
+        write!(self.w, "}}")
+    }
+}
+
+/// Returns the operator joining a relationship's entities, e.g. `1--*`, or
+/// `1==*` if it's identifying.
+pub(crate) fn relation_operator(r: &ast::Relation) -> String {
+    match r.kind {
+        ast::RelationKind::Association => {
+            let line = if r.identifying { "==" } else { "--" };
+            format!("{}{}{}", cardinality_symbol(r.card1), line, cardinality_symbol(r.card2))
+        },
+        ast::RelationKind::Inheritance => "^--".to_owned(),
+    }
+}
+
+/// Writes an n-ary relationship's name and entities, without options.
+pub(crate) fn nary_relation(r: &ast::NaryRelation) -> String {
+    let entities: Vec<_> = r.entities.iter().map(|e| quote_ident(e)).collect();
+    format!("{}({})", quote_ident(&r.name), entities.join(", "))
+}
+
+pub(crate) fn cardinality_symbol(c: ast::Cardinality) -> String {
+    match c {
+        ast::Cardinality::ZeroOne => "?".to_owned(),
+        ast::Cardinality::One => "1".to_owned(),
+        ast::Cardinality::ZeroPlus => "*".to_owned(),
+        ast::Cardinality::OnePlus => "+".to_owned(),
+        // Written the same as they're displayed.
+        ast::Cardinality::Range { .. } => c.to_string(),
+    }
+}
+
+/// Quotes an identifier if it can't be written bare, picking a quote
+/// character that doesn't appear in the identifier itself if there is one.
+pub fn quote_ident(id: &str) -> String {
+    if !id.is_empty() && id.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return id.to_owned();
+    }
+
+    let q = ['"', '\'', '`'].iter()
+        .copied()
+        .find(|q| !id.contains(*q))
+        .unwrap_or('"');
+    format!("{q}{id}{q}", q=q, id=escape(id, q))
+}
+
+/// Escapes backslashes, newlines and `quote` for writing between quotes.
+pub(crate) fn escape(s: &str, quote: char) -> Cow<'_, str> {
+    if !s.contains(['\\', '\n', quote]) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len() + 2);
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\\' => out.push_str("\\\\"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            },
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_erd;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn empty() {
+        assert_eq!(format_erd(&ast::Erd::default()), "");
+    }
+
+    #[test]
+    fn simple() {
+        let erd = parse_erd(include_str!("../examples/simple.er")).unwrap();
+        assert_eq!(format_erd(&erd), r#"[Person]
+  *name
+  height
+  weight
+  "birth date"
+  +birth_place_id
+
+["Birth Place"]
+  *id
+  "birth city"
+  "birth state"
+  "birth country"
+
+Person *--1 "Birth Place"
+"#);
+    }
+
+    #[test]
+    fn header_options_keep_their_place() {
+        let erd = parse_erd("header {size: \"18\"}\nentity {font: \"Times\", border: \"2\"}\n[a]\n").unwrap();
+        let formatted = format_erd(&erd);
+        assert_eq!(formatted, "[a] {size: \"18\", font: \"Times\", border: \"2\"}\n\n");
+        assert_eq!(format_erd(&parse_erd(&formatted).unwrap()), formatted);
+    }
+
+    #[test]
+    fn options() {
+        let s = r##"
+title {label: "T", size: "20"}
+[a] {bgcolor: "#d0e0d0", href: "docs/a.html", description: "The a", size: "10", pk: "name"}
+*+`x "y"` {label: "int"}
+name {bgcolor: "red", description: "Its name", type: "text", unique: "true"}
+index {unique: "true", columns: "name,x"}
+a 1--? a {color: "red", attributes: "since,note"}
+rel "a to a": a 1--1 a
+a (0, 5)--(2,*) a
+"##;
+        let erd = parse_erd(s).unwrap();
+        assert_eq!(format_erd(&erd), r##"title {label: "T", size: "20"}
+
+[a] {pk: "name", description: "The a", url: "docs/a.html", size: "10", bgcolor: "#d0e0d0"}
+  *+'x "y"' {label: "int"}
+  name {type: "text", unique: "true", description: "Its name", bgcolor: "red"}
+  index {columns: "name, x", unique: "true"}
+
+a 1--? a {color: "red", attributes: "since, note"}
+rel "a to a": a 1--1 a
+a (0,5)--(2,*) a
+"##);
+    }
+
+    #[test]
+    fn idempotent() {
+        let s = "[ a ]   {size:\"10\" ,}\n\t+* `x`\n\n\n[\"b c\"]\n a 1--*   'b c'\n";
+        let formatted = format_erd(&parse_erd(s).unwrap());
+        assert_eq!(formatted, "[a] {size: \"10\"}\n  *+x\n\n[\"b c\"]\n\na 1--* \"b c\"\n");
+        assert_eq!(format_erd(&parse_erd(&formatted).unwrap()), formatted);
+    }
+
+    #[test]
+    fn escapes() {
+        let s = r#"[`a "b" 'c'`] {label: "say \"hi\"\nC:\\temp"}
+*`\`x\``
+"#;
+        let erd = parse_erd(s).unwrap();
+        assert_eq!(erd.entities[0].name, r#"a "b" 'c'"#);
+        assert_eq!(erd.entities[0].options.label.as_deref(), Some("say \"hi\"\nC:\\temp"));
+        assert_eq!(erd.entities[0].attribs[0].field, "`x`");
+
+        let formatted = format_erd(&erd);
+        assert_eq!(formatted, r#"[`a "b" 'c'`] {label: "say \"hi\"\nC:\\temp"}
+  *"`x`"
+
+"#);
+        assert_eq!(quote_ident("a\"b'c`d"), r#""a\"b'c`d""#);
+        assert_eq!(parse_erd(&formatted).unwrap(), erd);
+    }
+
+    #[test]
+    fn round_trip() {
+        let erd = parse_erd(include_str!("../examples/nfldb.er")).unwrap();
+        let formatted = format_erd(&erd);
+        assert_eq!(parse_erd(&formatted).unwrap(), erd);
+    }
+}
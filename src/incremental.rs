@@ -0,0 +1,297 @@
+//! Re-parsing of edited source text, for editor integrations which can't
+//! afford to parse a large file again on every keystroke.
+//!
+//! A [`Document`] keeps the text it was parsed from. When told which bytes
+//! an edit replaced, it reuses the declarations before the entity the edit
+//! starts in and those from the first entity after it, and parses only the
+//! text in between. Whenever an edit might affect more than that, such as
+//! when it touches a directive, the whole text is parsed again instead.
+
+use std::ops::Range;
+use crate::ast::{self, GlobalOption, Span};
+use crate::error::ErdError;
+use crate::parser::{self, line_column, DuplicateEntityPolicy, ParseOptions};
+
+/// A change to a document's text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    /// Bytes of the old text which were replaced.
+    pub range: Range<usize>,
+    /// Length in bytes of the text which replaced them.
+    pub new_len: usize,
+}
+
+/// Source text along with the diagram parsed from it.
+#[derive(Clone, Debug)]
+pub struct Document {
+    text: String,
+    options: ParseOptions,
+    erd: Result<ast::Erd, ErdError>,
+    /// Directives in the text, with where they are, as the diagram only
+    /// keeps their effects.
+    directives: Vec<(Span, GlobalOption)>,
+    /// Whether the text has includes or variables, either of which can
+    /// make any declaration depend on any other.
+    linked: bool,
+}
+
+impl Document {
+    /// Parses `text` with the given options.
+    pub fn new(text: String, options: ParseOptions) -> Self {
+        let (erd, directives, linked) = parse(&text, &options);
+        Self { text, options, erd, directives, linked }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the diagram, or why the text couldn't be parsed.
+    pub fn erd(&self) -> Result<&ast::Erd, &ErdError> {
+        self.erd.as_ref()
+    }
+
+    /// Replaces the document's text with `text`, the result of applying
+    /// `edit` to the old text, and parses what it might have affected.
+    ///
+    /// Gives the same result as parsing `text` from scratch, as long as
+    /// `edit` correctly describes the change.
+    pub fn update(&mut self, text: String, edit: &Edit) -> Result<&ast::Erd, &ErdError> {
+        match self.reparse(&text, edit) {
+            Some((erd, directives)) => {
+                self.erd = Ok(erd);
+                self.directives = directives;
+            },
+            None => {
+                let (erd, directives, linked) = parse(&text, &self.options);
+                self.erd = erd;
+                self.directives = directives;
+                self.linked = linked;
+            },
+        }
+        self.text = text;
+        self.erd()
+    }
+
+    /// Parses only the declarations `edit` might have affected, returning
+    /// `None` if the whole text needs parsing again.
+    fn reparse(&self, text: &str, edit: &Edit) -> Option<(ast::Erd, Vec<(Span, GlobalOption)>)> {
+        let old = self.erd.as_ref().ok()?;
+        // Other policies for duplicates also relate declarations, and
+        // lenient parsing records skipped options with no way to tell which
+        // declaration they came from.
+        let independent = !self.linked
+            && !self.options.lenient
+            && self.options.duplicate_entities == DuplicateEntityPolicy::Warn;
+        let range = &edit.range;
+        let consistent = range.start <= range.end
+            && range.end <= self.text.len()
+            && text.len() == self.text.len() - range.len() + edit.new_len;
+        if !independent || !consistent {
+            return None;
+        }
+        let moved = |offset: usize| offset - range.end + range.start + edit.new_len;
+
+        // Attributes following the edit may belong to the entity it starts
+        // in, so that's parsed again along with anything up to the next
+        // entity.
+        let start = old.entities.iter()
+            .map(|e| e.span.start)
+            .filter(|&s| s <= range.start)
+            .max()
+            .unwrap_or(0);
+        let end = old.entities.iter()
+            .map(|e| e.span.start)
+            .filter(|&s| s >= range.end && s > start)
+            .min()
+            .unwrap_or(self.text.len());
+        // Declarations after the fragment keep their columns as long as
+        // the one it ends at does.
+        if end < self.text.len() && line_column(&self.text, end).1 != line_column(text, moved(end)).1 {
+            return None;
+        }
+        if self.directives.iter().any(|(span, _)| span.start >= start && span.start < end) {
+            return None;
+        }
+
+        let old_fragment = &self.text[start..end];
+        let fragment = text.get(start..moved(end))?;
+        let parsed = parser::parse_erd_borrowed_with(fragment, &self.options).ok()?;
+        if !parsed.directives.is_empty() || !parsed.includes.is_empty() || !parsed.variables.is_empty() {
+            return None;
+        }
+        // Directives before the fragment still apply to it.
+        let mut options = self.options.clone();
+        options.directives.extend(self.directives.iter()
+            .filter(|(span, _)| span.start < start)
+            .map(|(_, d)| d.clone()));
+        let mut middle = parsed.into_owned_with(&options).ok()?;
+
+        let (line, column) = line_column(text, start);
+        shift_spans(&mut middle, |span| Span {
+            start: span.start + start,
+            end: span.end + start,
+            line: span.line + line - 1,
+            column: if span.line == 1 { span.column + column - 1 } else { span.column },
+        });
+
+        let lines = fragment.matches('\n').count() as isize - old_fragment.matches('\n').count() as isize;
+        let shift = |span: Span| Span {
+            start: moved(span.start),
+            end: moved(span.end),
+            line: (span.line as isize + lines) as usize,
+            column: span.column,
+        };
+        let mut after = take_items(old, |s| s >= end);
+        shift_spans(&mut after, shift);
+
+        let mut erd = take_items(old, |s| s < start);
+        append(&mut erd, middle);
+        append(&mut erd, after);
+        erd.title_options = old.title_options.clone();
+        erd.graph_options = old.graph_options.clone();
+
+        let directives = self.directives.iter()
+            .map(|(span, d)| (if span.start >= end { shift(*span) } else { *span }, d.clone()))
+            .collect();
+        Some((erd, directives))
+    }
+}
+
+/// Parses the whole of `text`.
+fn parse(text: &str, options: &ParseOptions) -> (Result<ast::Erd, ErdError>, Vec<(Span, GlobalOption)>, bool) {
+    match parser::parse_erd_borrowed_with(text, options) {
+        Ok(parsed) => {
+            let directives = parsed.directives.iter()
+                .map(|d| (d.span, d.clone().into_owned()))
+                .collect();
+            let linked = !parsed.includes.is_empty() || !parsed.variables.is_empty();
+            (parsed.into_owned_with(options), directives, linked)
+        },
+        Err(err) => (Err(err), Vec::new(), false),
+    }
+}
+
+/// Copies the declarations which start at offsets matching `keep`.
+fn take_items(erd: &ast::Erd, keep: impl Fn(usize) -> bool) -> ast::Erd {
+    fn filter<T: Clone>(items: &[T], span: impl Fn(&T) -> Span, keep: &impl Fn(usize) -> bool) -> Vec<T> {
+        items.iter().filter(|x| keep(span(x).start)).cloned().collect()
+    }
+    ast::Erd {
+        entities: filter(&erd.entities, |e| e.span, &keep),
+        relationships: filter(&erd.relationships, |r| r.span, &keep),
+        nary_relationships: filter(&erd.nary_relationships, |r| r.span, &keep),
+        enums: filter(&erd.enums, |e| e.span, &keep),
+        notes: filter(&erd.notes, |n| n.span, &keep),
+        ..Default::default()
+    }
+}
+
+/// Appends the declarations of `other` to those of `erd`.
+fn append(erd: &mut ast::Erd, other: ast::Erd) {
+    erd.entities.extend(other.entities);
+    erd.relationships.extend(other.relationships);
+    erd.nary_relationships.extend(other.nary_relationships);
+    erd.enums.extend(other.enums);
+    erd.notes.extend(other.notes);
+}
+
+/// Replaces the span of every declaration with `f` of it.
+fn shift_spans(erd: &mut ast::Erd, mut f: impl FnMut(Span) -> Span) {
+    for e in &mut erd.entities {
+        e.span = f(e.span);
+        for a in &mut e.attribs {
+            a.span = f(a.span);
+        }
+        for x in &mut e.indexes {
+            x.span = f(x.span);
+        }
+    }
+    for r in &mut erd.relationships {
+        r.span = f(r.span);
+    }
+    for r in &mut erd.nary_relationships {
+        r.span = f(r.span);
+    }
+    for e in &mut erd.enums {
+        e.span = f(e.span);
+    }
+    for n in &mut erd.notes {
+        n.span = f(n.span);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use super::*;
+
+    const TEXT: &str = "title {label: \"t\"}
+entity {bgcolor: \"#eee\"}
+
+[a]
+*id
+name
+
+# comment
+[b] {color: \"red\"}
+*id
++a_id
+
+a 1--* b
+[c]
+x
+b 1--1 c
+";
+
+    /// Every span in the diagram, as spans always compare equal.
+    fn spans(erd: &ast::Erd) -> Vec<(usize, usize, usize, usize)> {
+        let mut spans = Vec::new();
+        let mut all = erd.clone();
+        shift_spans(&mut all, |s| {
+            spans.push(s);
+            s
+        });
+        spans.into_iter().map(|s| (s.start, s.end, s.line, s.column)).collect()
+    }
+
+    /// Applies an edit replacing `old` with `new`, checking it was parsed
+    /// incrementally or not as expected, and gave the same result as
+    /// parsing from scratch.
+    fn check(doc: &mut Document, old: &str, new: &str, incremental: bool) {
+        let start = doc.text().find(old).expect("text to replace");
+        let edit = Edit { range: start..start + old.len(), new_len: new.len() };
+        let text = format!("{}{}{}", &doc.text()[..start], new, &doc.text()[start + old.len()..]);
+
+        assert_eq!(doc.reparse(&text, &edit).is_some(), incremental, "{:?} -> {:?}", old, new);
+        let expected = Document::new(text.clone(), doc.options.clone());
+        doc.update(text, &edit).unwrap();
+        assert_eq!(doc.erd(), expected.erd());
+        assert_eq!(spans(doc.erd().unwrap()), spans(expected.erd().unwrap()));
+        assert_eq!(doc.directives, expected.directives);
+    }
+
+    #[test]
+    fn incremental_edits() {
+        let mut doc = Document::new(TEXT.to_owned(), ParseOptions::default());
+        check(&mut doc, "name\n", "name\nemail {type: \"text\"}\nphone\n", true);
+        check(&mut doc, "+a_id", "*+a_id", true);
+        check(&mut doc, "a 1--* b", "a 1--+ b {label: \"has\"}", true);
+        check(&mut doc, "[c]\nx\n", "[c]\nx\ny\n\n[d]\nz\n", true);
+        check(&mut doc, "b 1--1 c\n", "", true);
+        check(&mut doc, "# comment\n", "", true);
+        assert_eq!(doc.erd().unwrap().entities[0].options.background_color.as_deref(), Some("#eee"));
+    }
+
+    #[test]
+    fn full_reparse() {
+        let mut doc = Document::new(TEXT.to_owned(), ParseOptions::default());
+        // Directives affect everything after them.
+        check(&mut doc, "\n[b]", "\nrelationship {color: \"blue\"}\n[b]", false);
+        check(&mut doc, "entity {bgcolor: \"#eee\"}", "entity {bgcolor: \"#fff\"}", false);
+
+        let edit = Edit { range: 0..1, new_len: 0 };
+        assert!(doc.update("title {".to_owned(), &edit).is_err());
+        check(&mut doc, "title {", "[a]\nx\n", false);
+    }
+}
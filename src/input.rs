@@ -0,0 +1,146 @@
+use std::{fs::File, io::{self, Read}, str};
+use memmap2::Mmap;
+
+/// Expands any glob patterns among the given paths, e.g. `schemas/**/*.er`,
+/// keeping the order they were given in. Matches of each pattern are sorted,
+/// and skipped if they're already included. Paths without glob characters
+/// are kept as is, whether or not they exist.
+pub fn expand(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut expanded: Vec<String> = Vec::new();
+    for p in paths {
+        if !p.contains(['*', '?', '[']) {
+            expanded.push(p.clone());
+            continue;
+        }
+
+        let entries = glob::glob(p).map_err(|err| format!("Invalid pattern '{}': {}", p, err))?;
+        let mut matched = false;
+        for entry in entries {
+            let path = entry.map_err(|err| format!("Failed to read '{}': {}", err.path().display(), err.error()))?;
+            if !path.is_file() {
+                continue;
+            }
+            matched = true;
+            let path = path.to_string_lossy().into_owned();
+            if !expanded.contains(&path) {
+                expanded.push(path);
+            }
+        }
+        if !matched {
+            return Err(format!("No files match '{}'", p));
+        }
+    }
+    Ok(expanded)
+}
+
+/// Files at least this large are memory-mapped rather than read into memory.
+const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Source text of an ERD, either read into memory or memory-mapped from disk.
+pub enum Input {
+    Owned(String),
+    Mapped(Mmap),
+}
+
+impl Input {
+    /// Reads the given file, memory-mapping it if it's large enough for the
+    /// upfront copy to matter.
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        Self::open(path, MMAP_THRESHOLD)
+    }
+
+    /// Reads the given file into memory, however large it is, for files which
+    /// may be rewritten while they're being read.
+    pub fn from_file_unmapped(path: &str) -> io::Result<Self> {
+        Self::open(path, u64::MAX)
+    }
+
+    /// Reads the given file, memory-mapping it if it's at least
+    /// `mmap_threshold` bytes long.
+    fn open(path: &str, mmap_threshold: u64) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        if f.metadata()?.len() < mmap_threshold {
+            let mut buf = String::new();
+            f.read_to_string(&mut buf)?;
+            return Ok(Input::Owned(buf));
+        }
+
+        // SAFETY: the map is only ever read as bytes, which are validated as
+        // UTF-8 each time they're accessed, so another process rewriting the
+        // file while it's mapped can't produce an invalid `&str`. Truncating
+        // it can still fault the process on access.
+        let m = unsafe { Mmap::map(&f)? };
+        Ok(Input::Mapped(m))
+    }
+
+    pub fn from_stdin() -> io::Result<Self> {
+        let mut buf = String::new();
+        io::stdin().read_to_string(&mut buf)?;
+        Ok(Input::Owned(buf))
+    }
+
+    /// The source text, failing if a mapped file isn't (or is no longer)
+    /// valid UTF-8.
+    pub fn as_str(&self) -> io::Result<&str> {
+        match self {
+            Input::Owned(s) => Ok(s),
+            Input::Mapped(m) => str::from_utf8(m).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn expand_globs() {
+        let dir = std::env::temp_dir().join(format!("erd-expand-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for name in &["b.er", "a.er", "notes.txt", "nested/c.er"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let expanded = expand(&[path("b.er"), path("**/*.er"), path("missing.er")]).unwrap();
+        assert_eq!(expanded, vec![path("b.er"), path("a.er"), path("nested/c.er"), path("missing.er")]);
+
+        assert_eq!(expand(&[path("*.sql")]), Err(format!("No files match '{}'", path("*.sql"))));
+        assert!(expand(&[path("[")]).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn map_large_files() {
+        let path = std::env::temp_dir().join(format!("erd-mmap-{}.er", std::process::id()));
+        let path_str = path.to_string_lossy().into_owned();
+
+        fs::write(&path, "[Person]\nname\n").unwrap();
+        let input = Input::open(&path_str, 1).unwrap();
+        assert!(matches!(input, Input::Mapped(_)));
+        assert_eq!(input.as_str().unwrap(), "[Person]\nname\n");
+        let input = Input::open(&path_str, 1024).unwrap();
+        assert!(matches!(input, Input::Owned(_)));
+        assert_eq!(input.as_str().unwrap(), "[Person]\nname\n");
+
+        fs::write(&path, b"[Person]\n\xff\n").unwrap();
+        let input = Input::open(&path_str, 1).unwrap();
+        assert_eq!(input.as_str().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unmapped_files() {
+        let path = std::env::temp_dir().join(format!("erd-unmapped-{}.er", std::process::id()));
+        let path_str = path.to_string_lossy().into_owned();
+        fs::write(&path, vec![b' '; MMAP_THRESHOLD as usize]).unwrap();
+
+        assert!(matches!(Input::from_file(&path_str).unwrap(), Input::Mapped(_)));
+        assert!(matches!(Input::from_file_unmapped(&path_str).unwrap(), Input::Owned(_)));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
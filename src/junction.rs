@@ -0,0 +1,142 @@
+use crate::ast::{Attribute, Cardinality, Entity, EntityOptions, Erd, HeaderOptions, Name, Reference, Relation, RelationKind};
+
+impl Erd {
+    /// Replaces each many-to-many relationship with a junction entity, named
+    /// after the relationship or else the two entities, e.g.
+    /// `students_courses`, joined one-to-many to both sides.
+    ///
+    /// The junction's primary key is made up of foreign keys to each side's
+    /// primary key fields, or an `{entity}_id` field where there are none.
+    /// Attributes of the relationship become attributes of the junction.
+    /// Relationships whose junction name is already taken by an entity are
+    /// left as they are.
+    pub fn expand_junctions(&mut self) {
+        let mut relationships = Vec::with_capacity(self.relationships.len());
+        for r in std::mem::take(&mut self.relationships) {
+            let many_to_many = r.kind == RelationKind::Association && r.card1.is_many() && r.card2.is_many();
+            let name: Name = r.name.clone().unwrap_or_else(|| format!("{}_{}", r.entity1, r.entity2)).into();
+            if !many_to_many || self.entity(&name).is_some() {
+                relationships.push(r);
+                continue;
+            }
+
+            let mut junction = Entity {
+                name: name.clone(),
+                attribs: Vec::new(),
+                options: EntityOptions::default(),
+                header_options: HeaderOptions::default(),
+                primary_key: Vec::new(),
+                indexes: Vec::new(),
+                description: None,
+                group: None,
+                url: None,
+                weak: false,
+                span: r.span,
+            };
+            for entity in [&r.entity1, &r.entity2] {
+                for key in self.foreign_keys(entity) {
+                    let mut field = key.field.to_string();
+                    while junction.attribute(&field).is_some() {
+                        field.push_str("_2");
+                    }
+                    junction.add_attribute(Attribute { field: field.into(), ..key });
+                }
+            }
+            junction.attribs.extend(r.attribs.iter().cloned());
+            self.entities.push(junction);
+
+            // Each side's cardinality now applies to its links in the junction.
+            for (entity, card) in [(&r.entity1, r.card2), (&r.entity2, r.card1)] {
+                relationships.push(Relation {
+                    name: None,
+                    entity1: entity.clone(),
+                    entity2: name.clone(),
+                    card1: Cardinality::One,
+                    card2: card,
+                    kind: RelationKind::Association,
+                    identifying: true,
+                    attribs: Vec::new(),
+                    options: r.options.clone(),
+                    span: r.span,
+                });
+            }
+        }
+        self.relationships = relationships;
+    }
+
+    /// Returns foreign key attributes referring to the named entity's primary
+    /// key fields, for use in a junction entity.
+    fn foreign_keys(&self, entity: &str) -> Vec<Attribute> {
+        let keys: Vec<Attribute> = self.entity(entity).into_iter()
+            .flat_map(|e| e.attribs.iter().filter(move |a| e.is_primary_key(a)))
+            .map(|a| Attribute {
+                field: format!("{}_{}", entity, a.field).into(),
+                pk: true,
+                fk: true,
+                datatype: a.datatype.clone(),
+                references: Some(Reference { entity: entity.to_owned(), field: a.field.to_string() }),
+                ..Attribute::with_field("")
+            })
+            .collect();
+        if keys.is_empty() {
+            vec![Attribute { pk: true, fk: true, ..Attribute::with_field(format!("{}_id", entity)) }]
+        } else {
+            keys
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use crate::{format_erd, parser::parse_erd};
+
+    #[test]
+    fn expand_junctions() {
+        let mut erd = parse_erd(r#"
+[students]
+*id {type: "int"}
+name
+[courses] {pk: "code, year"}
+code
+year
+[tags]
+students *--+ courses {label: "enrolled", attributes: "grade"}
+students 1--* tags
+rel friends: students *--* students
+tags *--* courses
+[tags_courses]
+"#).unwrap();
+        erd.expand_junctions();
+
+        assert_eq!(format_erd(&erd), r#"[students]
+  *id {type: "int"}
+  name
+
+[courses] {pk: "code, year"}
+  code
+  year
+
+[tags]
+
+[tags_courses]
+
+[students_courses]
+  *+students_id -> students.id {type: "int"}
+  *+courses_code -> courses.code
+  *+courses_year -> courses.year
+  grade
+
+[friends]
+  *+students_id -> students.id {type: "int"}
+  *+students_id_2 -> students.id {type: "int"}
+
+students 1==+ students_courses {label: "enrolled"}
+courses 1==* students_courses {label: "enrolled"}
+students 1--* tags
+students 1==* friends
+students 1==* friends
+tags *--* courses
+"#);
+    }
+}
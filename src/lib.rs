@@ -0,0 +1,33 @@
+//! Library for parsing entity-relationship diagrams written in the `.er`
+//! markup format, and rendering them to Graphviz DOT or back to `.er` text.
+//!
+//! ```
+//! let erd = erd::parse_erd("[Person]\n*name\n").unwrap();
+//!
+//! let mut buf = Vec::new();
+//! erd::render::Renderer::new(&mut buf).render_erd(&erd).unwrap();
+//! assert!(String::from_utf8(buf).unwrap().starts_with("graph {"));
+//! ```
+
+pub mod ast;
+pub mod borrowed;
+pub mod chen;
+pub mod diff;
+pub mod error;
+pub mod format;
+pub mod incremental;
+pub mod junction;
+pub mod merge;
+pub mod parser;
+pub mod render;
+pub mod sort;
+pub mod stats;
+pub mod style;
+pub mod subgraph;
+pub mod theme;
+pub mod uml;
+pub mod validate;
+
+pub use error::{Diagnostic, ErdError, Severity};
+pub use format::format_erd;
+pub use parser::{parse_erd, parse_erd_borrowed, parse_erd_items};
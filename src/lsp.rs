@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use serde_json::{json, Value};
+use erd::{format::quote_ident, parser, ErdError, Severity};
+use crate::exit::Failure;
+
+/// JSON-RPC error code for a message that isn't valid JSON.
+const PARSE_ERROR: i64 = -32700;
+/// JSON-RPC error code for a request the server doesn't support.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Language server for `.er` files, speaking the Language Server Protocol
+/// over stdin and stdout.
+///
+/// Documents are synced in full on every change, and reparsed for each
+/// request, which is fast enough for any diagram a person would write.
+#[derive(Default)]
+pub struct Server {
+    /// Text of each open document, by URI.
+    documents: HashMap<String, String>,
+    shutdown: bool,
+    exited: bool,
+}
+
+/// Serves requests until the client sends `exit` or closes stdin.
+pub fn run() -> Result<(), Failure> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let (mut input, mut output) = (stdin.lock(), stdout.lock());
+    let io_failure = |err: io::Error| Failure::Io(format!("Language server connection failed: {}", err));
+
+    let mut server = Server::default();
+    while let Some(body) = read_message(&mut input).map_err(io_failure)? {
+        let replies = match serde_json::from_slice::<Value>(&body) {
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![error(&Value::Null, PARSE_ERROR, err.to_string())],
+        };
+        for reply in &replies {
+            write_message(&mut output, reply).map_err(io_failure)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+
+    if server.exited && !server.shutdown {
+        return Err(Failure::Io("Language server exited without a shutdown request".to_owned()));
+    }
+    Ok(())
+}
+
+/// Reads the body of the next message, or `None` at the end of input.
+fn read_message(r: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if r.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    r.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(w: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    w.flush()
+}
+
+fn response(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error(id: &Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+impl Server {
+    /// Handles a request or notification, returning any messages to send
+    /// back to the client.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+
+        let id = match message.get("id") {
+            Some(id) => id,
+            None => {
+                match method {
+                    "textDocument/didOpen" => {
+                        let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                        self.documents.insert(uri.to_owned(), text.to_owned());
+                    },
+                    "textDocument/didChange" => {
+                        let changes = params["contentChanges"].as_array();
+                        match changes.and_then(|c| c.last()).and_then(|c| c["text"].as_str()) {
+                            Some(text) => self.documents.insert(uri.to_owned(), text.to_owned()),
+                            None => return Vec::new(),
+                        };
+                    },
+                    "textDocument/didClose" => {
+                        self.documents.remove(uri);
+                        return vec![publish_diagnostics(uri, Vec::new())];
+                    },
+                    "exit" => self.exited = true,
+                    _ => {},
+                }
+                return match self.documents.get(uri) {
+                    Some(text) if method.starts_with("textDocument/") => {
+                        vec![publish_diagnostics(uri, diagnostics(uri, text))]
+                    },
+                    _ => Vec::new(),
+                };
+            },
+        };
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": env!("CARGO_BIN_NAME"), "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            },
+            "textDocument/definition" => self.at_position(params, definition),
+            "textDocument/hover" => self.at_position(params, hover),
+            "textDocument/completion" => self.at_position(params, completion),
+            _ => return vec![error(id, METHOD_NOT_FOUND, format!("Unsupported method: {}", method))],
+        };
+        vec![response(id, result)]
+    }
+
+    /// Answers a request about a position in a document, or returns null if
+    /// the document isn't open.
+    fn at_position(&self, params: &Value, f: fn(&str, &str, usize) -> Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let text = match self.documents.get(uri) {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+        f(uri, text, offset(text, line, character))
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics }))
+}
+
+/// Returns parse errors and validation problems in the document.
+///
+/// Includes are resolved for documents with `file:` URIs, but problems found
+/// in included files are left to be reported when they're opened.
+fn diagnostics(uri: &str, text: &str) -> Vec<Value> {
+    let options = parser::ParseOptions {
+        path: uri.strip_prefix("file://").map(PathBuf::from),
+        ..Default::default()
+    };
+    let (erd, errors) = parser::parse_erd_recover_with(text, &options);
+    let mut diagnostics = Vec::new();
+
+    for err in errors {
+        // Only syntax errors are located; anything else is reported at the
+        // start of the document.
+        let (start, message) = match err {
+            ErdError::Parse { line, column, message } => (line_offset(text, line) + column - 1, message),
+            err => (0, err.to_string()),
+        };
+        let start = start.min(text.len());
+        let end = text[start..].find(['\r', '\n']).map_or(text.len(), |p| start + p);
+        diagnostics.push(json!({ "range": range(text, start, end), "severity": 1, "source": "erd", "message": message }));
+    }
+
+    // Anything in an included file has a default span, with a line of 0.
+    for d in erd.validate().into_iter().filter(|d| d.span.line > 0) {
+        let severity = match d.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        };
+        diagnostics.push(json!({
+            "range": range(text, d.span.start, d.span.end),
+            "severity": severity,
+            "source": "erd",
+            "message": d.kind.to_string(),
+        }));
+    }
+    diagnostics
+}
+
+/// Returns the location of the declaration of the entity named at `offset`.
+fn definition(uri: &str, text: &str, offset: usize) -> Value {
+    let (erd, _) = parser::parse_erd_recover(text);
+    match ident_at(text, offset).and_then(|(start, end)| erd.entity(&text[start..end])) {
+        Some(e) => json!({ "uri": uri, "range": range(text, e.span.start, e.span.end) }),
+        None => Value::Null,
+    }
+}
+
+/// Describes the entity named at `offset`: its attributes, and what it's
+/// related to.
+fn hover(_: &str, text: &str, offset: usize) -> Value {
+    let (erd, _) = parser::parse_erd_recover(text);
+    let (start, end) = match ident_at(text, offset) {
+        Some(ident) => ident,
+        None => return Value::Null,
+    };
+    let e = match erd.entity(&text[start..end]) {
+        Some(e) => e,
+        None => return Value::Null,
+    };
+
+    let mut lines = vec![format!("**{}**", quote_ident(&e.name)), String::new()];
+    if let Some(d) = &e.description {
+        lines.push(d.clone());
+        lines.push(String::new());
+    }
+    for a in &e.attribs {
+        let keys = format!("{}{}", if e.is_primary_key(a) { "*" } else { "" }, if a.fk { "+" } else { "" });
+        let mut line = format!("- `{}{}`", keys, quote_ident(&a.field));
+        if let Some(t) = &a.datatype {
+            line.push(' ');
+            line.push_str(t);
+        }
+        if a.unique {
+            line.push_str(" unique");
+        }
+        if a.nullable {
+            line.push_str(" nullable");
+        }
+        if let Some(d) = &a.description {
+            line.push_str(" — ");
+            line.push_str(d);
+        }
+        lines.push(line);
+    }
+
+    let mut related = Vec::new();
+    for r in erd.relationships_for(&e.name) {
+        let other = quote_ident(if r.entity1 == e.name { &r.entity2 } else { &r.entity1 });
+        if !related.contains(&other) {
+            related.push(other);
+        }
+    }
+    if !related.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("Related to: {}", related.join(", ")));
+    }
+
+    json!({
+        "contents": { "kind": "markdown", "value": lines.join("\n") },
+        "range": range(text, start, end),
+    })
+}
+
+/// Offers entity names when `offset` is on an entity in a relationship.
+fn completion(_: &str, text: &str, offset: usize) -> Value {
+    let line_start = text[..offset].rfind('\n').map_or(0, |p| p + 1);
+    let line_end = text[offset..].find('\n').map_or(text.len(), |p| offset + p);
+    let line = &text[line_start..line_end];
+    let in_options = line.find('{').is_some_and(|p| line_start + p < offset);
+    if !line.contains("--") || in_options {
+        return json!([]);
+    }
+
+    let (erd, _) = parser::parse_erd_recover(text);
+    let items: Vec<_> = erd.entities.iter()
+        .map(|e| json!({
+            "label": e.name.as_str(),
+            "kind": 7,
+            "detail": format!("{} attribute(s)", e.attribs.len()),
+            "insertText": quote_ident(&e.name),
+        }))
+        .collect();
+    json!(items)
+}
+
+/// Returns the byte range of the identifier containing `offset`, without
+/// any quotes around it.
+fn ident_at(text: &str, offset: usize) -> Option<(usize, usize)> {
+    let line_start = text[..offset].rfind('\n').map_or(0, |p| p + 1);
+    let line_end = text[offset..].find('\n').map_or(text.len(), |p| offset + p);
+    let line = &text[line_start..line_end];
+    let col = offset - line_start;
+
+    let mut i = 0;
+    while let Some(p) = line[i..].find(['"', '\'', '`']) {
+        let open = i + p;
+        let quote = &line[open..open + 1];
+        let close = match line[open + 1..].find(quote) {
+            Some(len) => open + 1 + len,
+            None => break,
+        };
+        if (open..=close).contains(&col) {
+            return Some((line_start + open + 1, line_start + close));
+        }
+        i = close + 1;
+    }
+
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let start = line[..col].char_indices()
+        .rev()
+        .take_while(|(_, c)| is_ident(*c))
+        .last()
+        .map_or(col, |(p, _)| p);
+    let end = line[col..].char_indices()
+        .find(|(_, c)| !is_ident(*c))
+        .map_or(line.len(), |(p, _)| col + p);
+    if start == end {
+        return None;
+    }
+    Some((line_start + start, line_start + end))
+}
+
+/// Returns the byte offset of the start of a 1-based line.
+fn line_offset(text: &str, line: usize) -> usize {
+    text.split_inclusive('\n').take(line.saturating_sub(1)).map(str::len).sum()
+}
+
+/// Converts an LSP position, counted in 0-based lines and UTF-16 code units,
+/// into a byte offset, clamped to the end of the line.
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let start = line_offset(text, line + 1).min(text.len());
+    let mut units = 0;
+    for (p, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' || c == '\r' {
+            return start + p;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+/// Converts a byte offset into an LSP position.
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |p| p + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({ "start": position(text, start), "end": position(text, end) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use pretty_assertions::assert_eq;
+    use super::*;
+
+    const URI: &str = "file:///schema.er";
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        server.handle(&notification(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "erd", "version": 1, "text": text } }),
+        ))
+    }
+
+    fn request(server: &mut Server, method: &str, line: usize, character: usize) -> Value {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": { "textDocument": { "uri": URI }, "position": { "line": line, "character": character } },
+        });
+        server.handle(&message).remove(0)["result"].take()
+    }
+
+    #[test]
+    fn framing() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &json!({ "id": 1 })).unwrap();
+        write_message(&mut buf, &json!({ "id": "é" })).unwrap();
+        assert!(buf.starts_with(b"Content-Length: 8\r\n\r\n{\"id\":1}"));
+
+        let mut r = Cursor::new(buf);
+        assert_eq!(read_message(&mut r).unwrap(), Some(b"{\"id\":1}".to_vec()));
+        assert_eq!(read_message(&mut r).unwrap(), Some("{\"id\":\"é\"}".as_bytes().to_vec()));
+        assert_eq!(read_message(&mut r).unwrap(), None);
+    }
+
+    #[test]
+    fn lifecycle() {
+        let mut server = Server::default();
+        let reply = server.handle(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+        assert_eq!(reply[0]["result"]["capabilities"]["hoverProvider"], true);
+
+        let reply = server.handle(&json!({ "jsonrpc": "2.0", "id": 2, "method": "workspace/symbol" }));
+        assert_eq!(reply[0]["error"]["code"], METHOD_NOT_FOUND);
+        assert!(server.handle(&notification("$/cancelRequest", json!({ "id": 2 }))).is_empty());
+
+        server.handle(&json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }));
+        server.handle(&notification("exit", Value::Null));
+        assert!(server.shutdown && server.exited);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut server = Server::default();
+        let published = open(&mut server, "[a]\n*id\n*id\na 1--* b\n[c\n");
+        assert_eq!(published[0]["method"], "textDocument/publishDiagnostics");
+
+        let diagnostics = &published[0]["params"]["diagnostics"];
+        let summary: Vec<_> = diagnostics.as_array().unwrap().iter()
+            .map(|d| (d["range"]["start"]["line"].as_u64().unwrap(), d["severity"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(summary, vec![(4, 1), (3, 1), (2, 1)]);
+        assert_eq!(diagnostics[0]["message"], "unexpected input: [c");
+        assert_eq!(diagnostics[2]["range"], json!({
+            "start": { "line": 2, "character": 0 },
+            "end": { "line": 2, "character": 3 },
+        }));
+
+        let closed = server.handle(&notification("textDocument/didClose", json!({ "textDocument": { "uri": URI } })));
+        assert_eq!(closed[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn navigation() {
+        let mut server = Server::default();
+        open(&mut server, "[person] {description: \"Someone\"}\n*id {description: \"Key\"}\nname {type: \"text\", unique: \"true\"}\n['ünï code']\n+person_id\nperson 1--* 'ünï code'\n");
+
+        let location = request(&mut server, "textDocument/definition", 5, 2);
+        assert_eq!(location["range"]["start"], json!({ "line": 0, "character": 0 }));
+        let location = request(&mut server, "textDocument/definition", 5, 16);
+        assert_eq!(location["range"]["start"], json!({ "line": 3, "character": 0 }));
+        assert_eq!(request(&mut server, "textDocument/definition", 1, 1), Value::Null);
+
+        let hover = request(&mut server, "textDocument/hover", 5, 0);
+        assert_eq!(hover["contents"]["value"], "**person**\n\nSomeone\n\n- `*id` — Key\n- `name` text unique\n\nRelated to: \"ünï code\"");
+
+        let items = request(&mut server, "textDocument/completion", 5, 12);
+        let labels: Vec<_> = items.as_array().unwrap().iter().map(|i| i["insertText"].clone()).collect();
+        assert_eq!(labels, vec![json!("person"), json!("\"ünï code\"")]);
+        assert_eq!(request(&mut server, "textDocument/completion", 2, 2), json!([]));
+    }
+
+    #[test]
+    fn positions() {
+        let text = "ab\n€x\n";
+        assert_eq!(offset(text, 1, 1), 6);
+        assert_eq!(offset(text, 1, 9), 7);
+        assert_eq!(offset(text, 5, 0), text.len());
+        assert_eq!(position(text, 6), json!({ "line": 1, "character": 1 }));
+        assert_eq!(ident_at(text, 1), Some((0, 2)));
+        assert_eq!(ident_at(text, 2), Some((0, 2)));
+        assert_eq!(ident_at("a -- b", 2), None);
+    }
+}
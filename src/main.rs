@@ -1,82 +1,680 @@
-use std::{fs::File, io::{self, Read}};
-mod ast;
-mod parser;
-mod render;
+use std::{env, fs::{self, File}, io, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use cli::Command;
+use erd::{ast::{GlobalOption, GraphOptions}, chen, merge::ConflictPolicy, parser, render, sort::AttributeOrder, theme::{self, Theme}, uml, validate::ValidateOptions, Severity};
+use exit::{Failure, Verbosity};
+mod cli;
+mod completions;
+mod config;
+mod exit;
+mod input;
+mod lsp;
+mod parallel;
+mod serve;
+mod watch;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let prog = args[0].clone();
+    let result = match cli::Cli::parse().into_command() {
+        Command::Render(args) => run_render(args),
+        Command::Validate(args) => run_check(args, Severity::Error),
+        Command::Lint(args) => run_check(args, Severity::Warning),
+        Command::Fmt(args) => run_fmt(args),
+        Command::Diff(args) => run_diff(args),
+        Command::Serve(args) => run_serve(args),
+        Command::Lsp => lsp::run(),
+        Command::Completions { shell } => {
+            print!("{}", completions::generate(shell, env!("CARGO_BIN_NAME")));
+            Ok(())
+        },
+    };
 
-    let mut opts = getopts::Options::new();
-    opts.optopt("i", "input", "When set, input will be read from the given file, otherwise input will be read from stdin.", "FILE");
-    opts.optopt("o", "output", "When set, output will be written to the given file, otherwise output will be written to stdout.", "FILE");
-    opts.optflag("h", "help", "Print this help menu.");
+    if let Err(failure) = result {
+        eprintln!("{}", failure);
+        std::process::exit(failure.code());
+    }
+}
 
-    let matches = match opts.parse(&args[1..]) {
-        Ok(m) => m,
-        Err(_) => print_usage_fatal(&prog, opts),
-    };
+fn run_render(args: cli::RenderArgs) -> Result<(), Failure> {
+    let config = load_config(args.config.as_deref())?;
+    let input_files = input_files(&args.input)?;
+    let verbosity = args.verbosity.level();
+    let layout = flag_layout(&args);
 
-    if matches.opt_present("h") {
-        print_usage(&prog, opts);
-        return;
+    let output_format = args.format
+        .or_else(|| config.format.clone())
+        .unwrap_or_else(|| "dot".to_owned());
+
+    let (mut render_options, defaults) = styling(&config, args.theme, "render");
+    if let Some(rankdir) = args.rankdir {
+        render_options.rankdir = rankdir;
+    }
+    if let Some(layout) = args.layout {
+        render_options.layout = Some(layout);
+    }
+    render_options.indexes = args.indexes;
+    render_options.constraints = args.constraints;
+    render_options.nullable = !args.hide_nullable;
+    render_options.descriptions = args.descriptions;
+    render_options.tooltips = args.tooltips;
+    render_options.schema_clusters |= args.schema_clusters;
+    render_options.legend = args.legend;
+    if let Some(style) = args.relationship_attributes {
+        render_options.relationship_attributes = style.into();
+    }
+    if let Some(filter) = args.attributes {
+        render_options.attributes = filter.into();
     }
+    if let Some(keys) = args.keys {
+        render_options.keys = keys.into();
+    }
+    if let Some(notation) = args.notation {
+        render_options.notation = notation.into();
+    }
+    if let Some(template) = args.row_template {
+        render_options.row_template = template;
+    }
+    if args.summary {
+        render_options.summary = Some(render::Summary {
+            source: if input_files.is_empty() { None } else { Some(input_files.join(", ")) },
+            generated: Some(generation_time()),
+        });
+    }
+    if let Some(edge) = args.edge.or_else(|| config.edge.clone()) {
+        match render::splines_for_edge_type(&edge) {
+            Some(splines) => render_options.splines = splines.to_owned(),
+            None => usage_error("render", format!("Unknown edge type: {}", edge)),
+        }
+    }
+
+    let mut backends = render::Backends::default();
+    backends.register(Box::new(chen::Chen { options: render_options.clone() }));
+    backends.register(Box::new(uml::Uml { options: render_options.clone() }));
+    backends.register(Box::new(render::Dot { options: render_options }));
+
+    let backend = match backends.get(&output_format) {
+        Some(b) => b,
+        None => usage_error("render", format!("Unknown output format: {}", output_format)),
+    };
 
-    let input_file = matches.opt_str("i");
-    let output_file = matches.opt_str("o");
+    let title = match (args.title, args.no_title) {
+        (Some(t), _) => Title::Set(t),
+        (None, true) => Title::Hidden,
+        (None, false) => Title::FromInput,
+    };
 
-    // Ensure that no positional arguments are set.
-    if !matches.free.is_empty() {
-        print_usage_fatal(&prog, opts);
+    if args.watch && input_files.is_empty() {
+        usage_error("render", "--watch requires at least one input file");
+    }
+    if args.watch && args.output.as_ref().is_some_and(|o| input_files.contains(o)) {
+        usage_error("render", "--watch can't be used when the output is one of the inputs");
     }
 
-    let input = match input_file {
-        Some(s) => {
-            std::fs::read_to_string(s).unwrap()
+    let depth = args.depth.unwrap_or(1);
+    let job = Job {
+        inputs: Inputs {
+            paths: &input_files,
+            keep_going: args.input.keep_going,
+            resolve_includes: true,
+            parse_options: parser::ParseOptions {
+                defaults,
+                ident_chars: args.input.ident_chars(config.ident_chars.as_deref()),
+                duplicate_entities: args.input.duplicate_entity_policy(),
+                lenient: args.input.lenient,
+                ..Default::default()
+            },
+            verbosity,
+            jobs: args.input.jobs(),
+            mmap: !args.watch,
+        },
+        transforms: Transforms {
+            title,
+            focus: args.focus.map(|entity| (entity, depth)),
+            sort_attributes: args.sort_attributes.map(AttributeOrder::from),
+            sort_entities: args.sort_entities,
+            expand_junctions: args.expand_junctions,
+            layout,
         },
-        None => {
-            let mut buf = String::new();
-            io::stdin().read_to_string(&mut buf).unwrap();
-            buf
+        backend,
+        output: args.output.as_deref(),
+        out_dir: args.out_dir.as_deref().map(Path::new),
+        stats: args.stats,
+        validate_options: ValidateOptions { allow_unknown_entities: args.allow_unknown_entities },
+    };
+
+    if !args.watch {
+        return job.run();
+    }
+
+    if let Err(err) = job.run() {
+        eprintln!("{}", err);
+    }
+    watch::watch(&input_files, || {
+        if let Err(err) = job.run() {
+            eprintln!("{}", err);
         }
+    });
+}
+
+/// Reports diagnostics for the input, failing if any are at least as severe
+/// as `fail_at`.
+fn run_check(args: cli::CheckArgs, fail_at: Severity) -> Result<(), Failure> {
+    let config = load_config(args.config.as_deref())?;
+    let input_files = input_files(&args.input)?;
+    let verbosity = args.verbosity.level();
+    let inputs = Inputs {
+        paths: &input_files,
+        keep_going: args.input.keep_going,
+        resolve_includes: true,
+        parse_options: parser::ParseOptions {
+            defaults: config.defaults,
+            ident_chars: args.input.ident_chars(config.ident_chars.as_deref()),
+            duplicate_entities: args.input.duplicate_entity_policy(),
+            lenient: args.input.lenient,
+            ..Default::default()
+        },
+        verbosity,
+        jobs: args.input.jobs(),
+        mmap: true,
+    };
+    let options = ValidateOptions { allow_unknown_entities: args.allow_unknown_entities };
+    check(&inputs.load()?, fail_at, &options, verbosity)
+}
+
+fn run_fmt(args: cli::FmtArgs) -> Result<(), Failure> {
+    let input_files = input_files(&args.input)?;
+    let verbosity = args.verbosity.level();
+    let output_file = match args.output {
+        Some(path) => Some(path),
+        None if input_files.len() > 1 => usage_error("fmt", "fmt with more than one input requires --output"),
+        None => input_files.first().cloned(),
     };
 
-    let erd = match parser::parse_erd(&input) {
-        Ok(erd) => erd,
-        Err(err) => {
-            eprintln!("Failed to parse ERD file: {}", err);
-            std::process::exit(1);
+    // Config file directives aren't applied, and includes aren't resolved,
+    // as they'd be written into the formatted output.
+    let inputs = Inputs {
+        paths: &input_files,
+        keep_going: args.input.keep_going,
+        resolve_includes: false,
+        parse_options: parser::ParseOptions {
+            ident_chars: args.input.ident_chars(None),
+            duplicate_entities: args.input.duplicate_entity_policy(),
+            lenient: args.input.lenient,
+            ..Default::default()
+        },
+        verbosity,
+        jobs: args.input.jobs(),
+        mmap: true,
+    };
+    let erd = inputs.load()?;
+    // Option values have had variables substituted into them, so
+    // formatting would replace each reference with its value.
+    if !erd.variables.is_empty() {
+        return Err(Failure::Usage("fmt doesn't support input which sets variables".to_owned()));
+    }
+    // Options skipped when parsing leniently are left out of the output.
+    if verbosity > Verbosity::Quiet {
+        for o in &erd.ignored_options {
+            eprintln!("warning: dropping unknown {} option: {}", o.scope, o.key);
+        }
+    }
+    write_output(&erd, &erd::format::Er, output_file.as_deref(), verbosity)
+}
+
+/// Prints the changes between two files, exiting with a failure status if
+/// there are any. Nothing is printed when quiet.
+fn run_diff(args: cli::DiffArgs) -> Result<(), Failure> {
+    let options = |path: &str| parser::ParseOptions { path: Some(PathBuf::from(path)), ..Default::default() };
+    let old = read_erd(Some(&args.old), false, true, &options(&args.old))?;
+    let new = read_erd(Some(&args.new), false, true, &options(&args.new))?;
+
+    let changes = old.diff(&new);
+    if args.verbosity.level() > Verbosity::Quiet {
+        for c in &changes {
+            println!("{}", c);
+        }
+    }
+    if !changes.is_empty() {
+        std::process::exit(exit::CHANGES);
+    }
+    Ok(())
+}
+
+fn run_serve(args: cli::ServeArgs) -> Result<(), Failure> {
+    let config = load_config(args.config.as_deref())?;
+    let (mut options, defaults) = styling(&config, args.theme, "serve");
+    if let Some(edge) = &config.edge {
+        match render::splines_for_edge_type(edge) {
+            Some(splines) => options.splines = splines.to_owned(),
+            None => usage_error("serve", format!("Unknown edge type: {}", edge)),
         }
+    }
+
+    let server = serve::Server {
+        options,
+        defaults,
+        ident_chars: config.ident_chars.unwrap_or_default(),
+        verbosity: args.verbosity.level(),
+    };
+    server.run(&format!("{}:{}", args.host, args.port))
+}
+
+/// Returns render options and default options from the theme, with the
+/// config file's settings applied on top.
+fn styling(config: &config::Config, theme: Option<String>, command: &str) -> (render::RenderOptions, Vec<GlobalOption>) {
+    let theme_name = theme.or_else(|| config.theme.clone());
+    let theme = match theme_name.as_deref().map(Theme::by_name) {
+        None => &theme::THEMES[0],
+        Some(Some(t)) => t,
+        Some(None) => usage_error(command, format!("Unknown theme: {}", theme_name.unwrap_or_default())),
     };
 
-    let output: Box<dyn std::io::Write> = match output_file {
-        Some(ref path) => {
-            let f = match File::create(path) {
-                Ok(f) => f,
-                Err(err) => {
-                    eprintln!("Failed to open file '{}' for writing: {}", path, err);
-                    std::process::exit(1);
-                }
-            };
-            Box::new(f)
+    let mut options = render::RenderOptions::default();
+    theme.apply(&mut options);
+    config.apply(&mut options);
+
+    let mut defaults = theme.defaults();
+    defaults.extend(config.defaults.iter().cloned());
+    (options, defaults)
+}
+
+fn load_config(path: Option<&str>) -> Result<config::Config, Failure> {
+    config::Config::find(path)
+        .map_err(|err| Failure::Usage(format!("Failed to load config: {}", err)))
+}
+
+/// Returns the time to show as when the output was generated: the time in
+/// `SOURCE_DATE_EPOCH` if set, so that builds can be reproduced, otherwise
+/// now.
+fn generation_time() -> SystemTime {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(secs) => match secs.trim().parse() {
+            Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+            Err(_) => usage_error("render", format!("Invalid SOURCE_DATE_EPOCH: {}", secs)),
+        },
+        Err(_) => SystemTime::now(),
+    }
+}
+
+/// Returns the input files to read, with any glob patterns expanded.
+fn input_files(args: &cli::InputArgs) -> Result<Vec<String>, Failure> {
+    input::expand(&args.paths()).map_err(Failure::Usage)
+}
+
+/// Prints diagnostics, failing if any are at least as severe as `fail_at`.
+/// Warnings aren't printed when quiet, unless they cause the failure.
+fn check(erd: &erd::ast::Erd, fail_at: Severity, options: &ValidateOptions, verbosity: Verbosity) -> Result<(), Failure> {
+    let diagnostics = erd.validate_with(options);
+    for d in &diagnostics {
+        if d.severity >= fail_at || verbosity > Verbosity::Quiet {
+            eprintln!("{}", d);
+        }
+    }
+    let failures = diagnostics.iter().filter(|d| d.severity >= fail_at).count();
+    if failures > 0 {
+        return Err(Failure::Validation(format!("Found {} {}(s) in the input", failures, fail_at)));
+    }
+    Ok(())
+}
+
+/// The files to read, and how to parse them.
+struct Inputs<'a> {
+    paths: &'a [String],
+    keep_going: bool,
+    /// Whether to replace `include` declarations with the files they name,
+    /// rather than keeping them as written.
+    resolve_includes: bool,
+    parse_options: parser::ParseOptions,
+    verbosity: Verbosity,
+    /// Number of files to read at once.
+    jobs: usize,
+    /// Whether large files may be memory-mapped rather than read. Watched
+    /// files aren't, as they're expected to change while in use.
+    mmap: bool,
+}
+
+impl<'a> Inputs<'a> {
+    /// Reads and parses the inputs, merging them into one diagram, or reads
+    /// stdin if there are none.
+    ///
+    /// Files are read in parallel, and are merged in the order given once
+    /// they've all been read. Failures to read any of them are reported
+    /// together.
+    fn load(&self) -> Result<erd::ast::Erd, Failure> {
+        if self.paths.is_empty() {
+            return self.read(None);
+        }
+
+        let mut erds = Vec::new();
+        let mut failures = Vec::new();
+        for result in parallel::map(self.paths, self.jobs, |path| self.read(Some(path))) {
+            match result {
+                Ok(erd) => erds.push(erd),
+                Err(err) => failures.push(err),
+            }
+        }
+        if let Some(failure) = Failure::combine(failures) {
+            return Err(failure);
+        }
+
+        let mut erd = erd::ast::Erd::default();
+        for (path, other) in self.paths.iter().zip(erds) {
+            erd.merge(other, ConflictPolicy::Error)
+                .map_err(|err| Failure::Validation(format!("Failed to merge '{}': {}", path, err)))?;
+        }
+        Ok(erd)
+    }
+
+    fn read(&self, path: Option<&str>) -> Result<erd::ast::Erd, Failure> {
+        let options = parser::ParseOptions {
+            path: path.filter(|_| self.resolve_includes).map(PathBuf::from),
+            ..self.parse_options.clone()
+        };
+        let erd = read_erd(path, self.keep_going, self.mmap, &options)?;
+        if self.verbosity == Verbosity::Verbose {
+            eprintln!(
+                "Read {}: {} entities, {} relationships",
+                path.unwrap_or("stdin"), erd.entities.len(), erd.relationships.len(),
+            );
+        }
+        Ok(erd)
+    }
+}
+
+/// Changes made to the diagram after it's loaded, before it's rendered.
+struct Transforms {
+    title: Title,
+    /// Entity to limit the diagram to, along with those within the given
+    /// number of relationships of it.
+    focus: Option<(String, usize)>,
+    sort_attributes: Option<AttributeOrder>,
+    sort_entities: bool,
+    expand_junctions: bool,
+    /// Layout given on the command line, overriding any set by a graph
+    /// directive in the input.
+    layout: GraphOptions,
+}
+
+impl Transforms {
+    fn apply(&self, erd: &mut erd::ast::Erd) -> Result<(), Failure> {
+        if self.expand_junctions {
+            erd.expand_junctions();
+        }
+        if let Some((entity, depth)) = &self.focus {
+            *erd = erd.neighborhood(entity, *depth)
+                .ok_or_else(|| Failure::Usage(format!("Unknown entity: {}", entity)))?;
+        }
+        match &self.title {
+            Title::FromInput => {},
+            Title::Set(t) => erd.title_options.label = Some(t.clone()),
+            Title::Hidden => erd.title_options.label = None,
+        }
+        let graph = &mut erd.graph_options;
+        graph.rankdir = self.layout.rankdir.clone().or_else(|| graph.rankdir.take());
+        graph.splines = self.layout.splines.clone().or_else(|| graph.splines.take());
+        graph.layout = self.layout.layout.clone().or_else(|| graph.layout.take());
+        if let Some(order) = self.sort_attributes {
+            erd.sort_attributes(order);
+        }
+        if self.sort_entities {
+            erd.sort_entities();
+        }
+        Ok(())
+    }
+}
+
+/// Returns the layout set by command line flags, which overrides any set by
+/// a graph directive in the input.
+fn flag_layout(args: &cli::RenderArgs) -> GraphOptions {
+    GraphOptions {
+        rankdir: args.rankdir.clone(),
+        splines: args.edge.as_deref().and_then(render::splines_for_edge_type).map(str::to_owned),
+        layout: args.layout.clone(),
+        ..Default::default()
+    }
+}
+
+/// Where the rendered diagram's title comes from.
+enum Title {
+    FromInput,
+    Set(String),
+    Hidden,
+}
+
+/// Everything needed to generate the output from the inputs, so that it can
+/// be repeated in watch mode.
+struct Job<'a> {
+    inputs: Inputs<'a>,
+    transforms: Transforms,
+    backend: &'a dyn render::Backend,
+    output: Option<&'a str>,
+    /// Directory to also write a diagram of each entity to.
+    out_dir: Option<&'a Path>,
+    /// Print stats about the diagram rather than rendering it.
+    stats: bool,
+    validate_options: ValidateOptions,
+}
+
+impl<'a> Job<'a> {
+    fn run(&self) -> Result<(), Failure> {
+        let mut erd = self.inputs.load()?;
+        check(&erd, Severity::Error, &self.validate_options, self.inputs.verbosity)?;
+        self.transforms.apply(&mut erd)?;
+        if self.stats {
+            print!("{}", erd.stats());
+            return Ok(());
+        }
+        write_output(&erd, self.backend, self.output, self.inputs.verbosity)?;
+        if let Some(dir) = self.out_dir {
+            self.write_entities(&erd, dir)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a diagram of each entity and its direct relationships to
+    /// `<dir>/<entity>.<format>`.
+    fn write_entities(&self, erd: &erd::ast::Erd, dir: &Path) -> Result<(), Failure> {
+        fs::create_dir_all(dir)
+            .map_err(|err| Failure::Io(format!("Failed to create directory '{}': {}", dir.display(), err)))?;
+        let written = parallel::map(&erd.entities, self.inputs.jobs, |e| {
+            let neighborhood = erd.neighborhood(&e.name, 1).expect("entity should exist");
+            // Quoted names may contain path separators.
+            let name = e.name.replace(|c| std::path::is_separator(c) || c == '\0', "_");
+            let path = dir.join(format!("{}.{}", name, self.backend.name()));
+            write_output(&neighborhood, self.backend, Some(&path.to_string_lossy()), self.inputs.verbosity)
+        });
+        match Failure::combine(written.into_iter().filter_map(Result::err)) {
+            Some(failure) => Err(failure),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Renders to the given file, or stdout if no path is given.
+fn write_output(erd: &erd::ast::Erd, backend: &dyn render::Backend, path: Option<&str>, verbosity: Verbosity) -> Result<(), Failure> {
+    let mut output: Box<dyn std::io::Write> = match path {
+        Some(path) => match File::create(path) {
+            Ok(f) => Box::new(f),
+            Err(err) => return Err(Failure::Io(format!("Failed to open file '{}' for writing: {}", path, err))),
         },
         None => Box::new(io::stdout()),
     };
 
-    let mut renderer = render::Renderer::new(output);
-    if let Err(err) = renderer.render_erd(&erd) {
-        eprintln!("Failed to render: {}", err);
-        std::process::exit(1);
+    backend.render(erd, &mut output)
+        .map_err(|err| Failure::Render(format!("Failed to render: {}", err)))?;
+    if verbosity == Verbosity::Verbose {
+        eprintln!("Wrote {} output to {}", backend.name(), path.unwrap_or("stdout"));
     }
+    Ok(())
 }
 
-fn print_usage(prog: &str, opts: getopts::Options) {
-    let brief = format!("Usage: {} [options]", prog);
-    print!("{}", opts.usage(&brief));
+/// Reads and parses a single input file, or stdin if no path is given.
+fn read_erd(path: Option<&str>, keep_going: bool, mmap: bool, options: &parser::ParseOptions) -> Result<erd::ast::Erd, Failure> {
+    let input = match path {
+        Some(path) if mmap => input::Input::from_file(path),
+        Some(path) => input::Input::from_file_unmapped(path),
+        None => input::Input::from_stdin(),
+    };
+    let source = path.unwrap_or("ERD file");
+    let input = input.map_err(|err| Failure::Io(format!("Failed to read {}: {}", path.unwrap_or("input"), err)))?;
+    let text = input.as_str()
+        .map_err(|err| Failure::Io(format!("Failed to read {}: {}", path.unwrap_or("input"), err)))?;
+
+    // The input (which may be a map of the output file) is dropped on
+    // return, before the output is opened.
+    if keep_going {
+        let (erd, errors) = parser::parse_erd_recover_with(text, options);
+        if !errors.is_empty() {
+            let messages: Vec<_> = errors.iter()
+                .map(|err| format!("Failed to parse {}: {}", source, err))
+                .collect();
+            return Err(Failure::Parse(messages.join("\n")));
+        }
+        Ok(erd)
+    } else {
+        parser::parse_erd_with(text, options)
+            .map_err(|err| Failure::Parse(format!("Failed to parse {}: {}", source, err)))
+    }
+}
+
+/// Exits with a usage error for a subcommand, for invalid arguments which
+/// can't be checked by the argument parser itself.
+fn usage_error(command: &str, message: impl std::fmt::Display) -> ! {
+    let mut cli = cli::Cli::command();
+    cli.build();
+    let cmd = cli.find_subcommand_mut(command).expect("unknown subcommand");
+    cmd.error(ErrorKind::InvalidValue, message).exit()
 }
 
-fn print_usage_fatal(prog: &str, opts: getopts::Options) -> ! {
-    let brief = format!("Usage: {} [options]", prog);
-    eprint!("{}", opts.usage(&brief));
-    std::process::exit(1);
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(erd: &erd::ast::Erd, options: render::RenderOptions) -> String {
+        let mut buf = Vec::new();
+        render::Backend::render(&render::Dot { options }, erd, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    fn transforms(layout: GraphOptions) -> Transforms {
+        Transforms {
+            title: Title::FromInput,
+            focus: None,
+            sort_attributes: None,
+            sort_entities: false,
+            expand_junctions: false,
+            layout,
+        }
+    }
+
+    fn inputs(paths: &[String]) -> Inputs<'_> {
+        Inputs {
+            paths,
+            keep_going: false,
+            resolve_includes: true,
+            parse_options: Default::default(),
+            verbosity: Verbosity::Quiet,
+            jobs: 2,
+            mmap: true,
+        }
+    }
+
+    #[test]
+    fn merge_inputs() {
+        let dir = env::temp_dir().join(format!("erd-inputs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str, contents: &str| {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let a = path("a.er", "[a]\n*id\n");
+        let b = path("b.er", "[b]\n*id\nb *--1 a\n");
+        let conflicting = path("c.er", "[a]\nid\n");
+
+        let erd = inputs(&[b.clone(), a.clone()]).load().unwrap();
+        let names: Vec<_> = erd.entities.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a"]);
+        assert_eq!(erd.relationships.len(), 1);
+
+        match inputs(&[a.clone(), conflicting.clone()]).load() {
+            Err(Failure::Validation(msg)) => assert!(msg.starts_with(&format!("Failed to merge '{}'", conflicting)), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match inputs(&[a, dir.join("missing.er").to_string_lossy().into_owned()]).load() {
+            Err(Failure::Io(_)) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn override_title() {
+        let title = |title: Title| {
+            let mut erd = parser::parse_erd("title {label: \"Schema\"}\n[a]\n").unwrap();
+            Transforms { title, ..transforms(GraphOptions::default()) }.apply(&mut erd).unwrap();
+            erd.title_options.label
+        };
+        assert_eq!(title(Title::FromInput).as_deref(), Some("Schema"));
+        assert_eq!(title(Title::Set("Orders".to_owned())).as_deref(), Some("Orders"));
+        assert_eq!(title(Title::Hidden), None);
+    }
+
+    #[test]
+    fn entity_diagrams() {
+        let dir = env::temp_dir().join(format!("erd-out-dir-{}", std::process::id()));
+        let job = Job {
+            inputs: inputs(&[]),
+            transforms: transforms(GraphOptions::default()),
+            backend: &render::Dot::default(),
+            output: None,
+            out_dir: Some(&dir),
+            stats: false,
+            validate_options: ValidateOptions::default(),
+        };
+        let erd = parser::parse_erd("[a]\n[b]\n[\"c/d\"]\na 1--* b\n").unwrap();
+        job.write_entities(&erd, &dir).unwrap();
+
+        let mut written: Vec<_> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        written.sort();
+        assert_eq!(written, vec!["a.dot", "b.dot", "c_d.dot"]);
+        let a = fs::read_to_string(dir.join("a.dot")).unwrap();
+        assert!(a.contains("\"a\" -- \"b\"") && !a.contains("c/d"), "{}", a);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn flags(args: &[&str]) -> GraphOptions {
+        match cli::Cli::parse_from(args).into_command() {
+            Command::Render(args) => flag_layout(&args),
+            c => panic!("unexpected command: {:?}", c),
+        }
+    }
+
+    #[test]
+    fn flags_override_graph_directive() {
+        let input = "graph {splines: \"curved\", layout: \"fdp\"}\n[a]\n";
+
+        let mut erd = parser::parse_erd(input).unwrap();
+        transforms(flags(&["erd", "-e", "ortho", "--layout", "neato"])).apply(&mut erd).unwrap();
+        let dot = render(&erd, Default::default());
+        assert!(dot.contains("        splines=ortho,\n        layout=neato,\n"), "{}", dot);
+
+        let mut erd = parser::parse_erd(input).unwrap();
+        transforms(flags(&["erd"])).apply(&mut erd).unwrap();
+        let dot = render(&erd, Default::default());
+        assert!(dot.contains("        splines=curved,\n        layout=fdp,\n"), "{}", dot);
+    }
+
+    #[test]
+    fn rankdir_flag() {
+        for input in &["[a]\n", "graph {rankdir: \"RL\"}\n[a]\n"] {
+            let mut erd = parser::parse_erd(input).unwrap();
+            transforms(flags(&["erd", "--rankdir", "TB"])).apply(&mut erd).unwrap();
+            let dot = render(&erd, Default::default());
+            assert!(dot.contains("        rankdir=TB,\n"), "{}", dot);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::ast::{Erd, Entity};
+use crate::error::ErdError;
+
+/// What to do when two models being merged define the same thing differently.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail the merge.
+    #[default]
+    Error,
+    /// Keep the definition from the model being merged into.
+    KeepExisting,
+    /// Replace with the definition from the model being merged in.
+    Overwrite,
+}
+
+impl Erd {
+    /// Merges another model into this one.
+    ///
+    /// Entities with the same name are combined, with attributes missing from
+    /// this model appended in order. Relationships, binary or n-ary, not
+    /// already present are appended, as are enums not already declared and
+    /// notes not already present.
+    /// Differing attribute, entity, enum or title definitions are resolved
+    /// according to `policy`; on error, this model is left unchanged.
+    pub fn merge(&mut self, other: Erd, policy: ConflictPolicy) -> Result<(), ErdError> {
+        let mut merged = self.clone();
+
+        merged.title_options = merge_directive(&self.title_options, other.title_options, policy, "title")?;
+        merged.graph_options = merge_directive(&self.graph_options, other.graph_options, policy, "graph")?;
+
+        for e in other.entities {
+            match merged.entity_mut(&e.name) {
+                Some(existing) => merge_entity(existing, e, policy)?,
+                None => merged.entities.push(e),
+            }
+        }
+
+        for r in other.relationships {
+            if !merged.relationships.contains(&r) {
+                merged.relationships.push(r);
+            }
+        }
+
+        for r in other.nary_relationships {
+            if !merged.nary_relationships.contains(&r) {
+                merged.nary_relationships.push(r);
+            }
+        }
+
+        for t in other.enums {
+            match merged.enums.iter_mut().find(|e| e.name == t.name) {
+                Some(existing) if *existing == t => {},
+                Some(existing) => {
+                    let name = t.name.clone();
+                    *existing = resolve(existing.clone(), t, policy, || {
+                        format!("conflicting values for enum '{}'", name)
+                    })?;
+                },
+                None => merged.enums.push(t),
+            }
+        }
+
+        for n in other.notes {
+            if !merged.notes.contains(&n) {
+                merged.notes.push(n);
+            }
+        }
+
+        for path in other.includes {
+            if !merged.includes.contains(&path) {
+                merged.includes.push(path);
+            }
+        }
+
+        for (name, value) in other.variables {
+            merged.variables.entry(name).or_insert(value);
+        }
+        merged.ignored_options.extend(other.ignored_options);
+
+        *self = merged;
+        Ok(())
+    }
+}
+
+fn merge_directive<T>(existing: &T, other: T, policy: ConflictPolicy, name: &str) -> Result<T, ErdError>
+where
+    T: Clone + Default + PartialEq,
+{
+    let default = T::default();
+    if *existing == other || other == default {
+        return Ok(existing.clone());
+    }
+    if *existing == default {
+        return Ok(other);
+    }
+
+    resolve(existing.clone(), other, policy, || format!("conflicting {} options", name))
+}
+
+fn merge_entity(existing: &mut Entity, other: Entity, policy: ConflictPolicy) -> Result<(), ErdError> {
+    let name = existing.name.clone();
+
+    if existing.definition() != other.definition() {
+        let resolved = resolve(existing.definition(), other.definition(), policy, || {
+            format!("conflicting options for entity '{}'", name)
+        })?;
+        *existing = Entity { attribs: std::mem::take(&mut existing.attribs), ..resolved };
+    }
+
+    for a in other.attribs {
+        match existing.attribs.iter_mut().find(|e| e.field == a.field) {
+            Some(ea) if *ea == a => {},
+            Some(ea) => {
+                let field = a.field.clone();
+                *ea = resolve(ea.clone(), a, policy, || {
+                    format!("conflicting definitions of attribute '{}.{}'", name, field)
+                })?;
+            },
+            None => existing.add_attribute(a),
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve<T, F>(existing: T, other: T, policy: ConflictPolicy, msg: F) -> Result<T, ErdError>
+where
+    F: FnOnce() -> String,
+{
+    match policy {
+        ConflictPolicy::Error => Err(ErdError::Semantic(msg())),
+        ConflictPolicy::KeepExisting => Ok(existing),
+        ConflictPolicy::Overwrite => Ok(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_erd;
+    use super::*;
+
+    #[test]
+    fn merge_disjoint() {
+        let mut a = parse_erd("title {label: \"A\"}\n[a]\n*id\n").unwrap();
+        let b = parse_erd("[b]\n*id\na 1--* b\n").unwrap();
+        a.merge(b, ConflictPolicy::Error).unwrap();
+        assert_eq!(a, parse_erd("title {label: \"A\"}\n[a]\n*id\n[b]\n*id\na 1--* b\n").unwrap());
+    }
+
+    #[test]
+    fn merge_same_entity() {
+        let mut a = parse_erd("[a]\n*id\nname\n[b]\na 1--* b\n").unwrap();
+        let b = parse_erd("[a]\n*id\nemail\n[b]\na 1--* b\n").unwrap();
+        a.merge(b, ConflictPolicy::Error).unwrap();
+        assert_eq!(a, parse_erd("[a]\n*id\nname\nemail\n[b]\na 1--* b\n").unwrap());
+    }
+
+    #[test]
+    fn merge_enums() {
+        let mut a = parse_erd("enum status {values: \"open, closed\"}\n").unwrap();
+        let b = parse_erd("enum status {values: \"open, closed\"}\nenum role {values: \"admin\"}\n").unwrap();
+        a.merge(b, ConflictPolicy::Error).unwrap();
+        assert_eq!(a.enums.len(), 2);
+
+        let c = parse_erd("enum status {values: \"open\"}\n").unwrap();
+        let err = a.merge(c.clone(), ConflictPolicy::Error).unwrap_err();
+        assert_eq!(err, ErdError::Semantic("conflicting values for enum 'status'".to_owned()));
+        a.merge(c, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(a.enumeration("status").unwrap().values, vec!["open"]);
+    }
+
+    #[test]
+    fn merge_conflicts() {
+        let base = parse_erd("[a] {color: \"red\"}\n*id\nname\n").unwrap();
+        let other = parse_erd("[a] {color: \"blue\"}\n*id\n+name\n").unwrap();
+
+        let mut erd = base.clone();
+        let err = erd.merge(other.clone(), ConflictPolicy::Error).unwrap_err();
+        assert_eq!(err, ErdError::Semantic("conflicting options for entity 'a'".to_owned()));
+        assert_eq!(erd, base);
+
+        let mut erd = base.clone();
+        erd.merge(other.clone(), ConflictPolicy::KeepExisting).unwrap();
+        assert_eq!(erd, base);
+
+        let mut erd = base.clone();
+        erd.merge(other.clone(), ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(erd, other);
+    }
+
+    #[test]
+    fn merge_graph_options() {
+        let mut erd = parse_erd("graph {rankdir: \"TB\"}\n[a]\n").unwrap();
+        erd.merge(parse_erd("[b]\n").unwrap(), ConflictPolicy::Error).unwrap();
+        assert_eq!(erd.graph_options.rankdir.as_deref(), Some("TB"));
+
+        let other = parse_erd("graph {rankdir: \"LR\"}\n").unwrap();
+        let err = erd.merge(other, ConflictPolicy::Error).unwrap_err();
+        assert_eq!(err, ErdError::Semantic("conflicting graph options".to_owned()));
+    }
+
+    #[test]
+    fn merge_attribute_conflict() {
+        let mut erd = parse_erd("[a]\n*id\nname\n").unwrap();
+        let other = parse_erd("[a]\n*id\n+name\n").unwrap();
+        let err = erd.merge(other, ConflictPolicy::Error).unwrap_err();
+        assert_eq!(err, ErdError::Semantic("conflicting definitions of attribute 'a.name'".to_owned()));
+    }
+}
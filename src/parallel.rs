@@ -0,0 +1,51 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, Mutex};
+use std::thread;
+
+/// Applies `f` to each item on up to `jobs` threads, returning the results
+/// in the same order as the items.
+pub fn map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|s| {
+        for _ in 0..jobs {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(i) {
+                    Some(item) => item,
+                    None => break,
+                };
+                let result = f(item);
+                results.lock().expect("no worker panics while holding the lock")[i] = Some(result);
+            });
+        }
+    });
+    results.into_inner()
+        .expect("no worker panics while holding the lock")
+        .into_iter()
+        .map(|r| r.expect("every item is processed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_order() {
+        let items: Vec<u64> = (0..100).collect();
+        for jobs in [0, 1, 4, 200] {
+            assert_eq!(map(&items, jobs, |n| n * 2), items.iter().map(|n| n * 2).collect::<Vec<_>>());
+        }
+        assert!(map(&[] as &[u64], 4, |n| *n).is_empty());
+    }
+}
@@ -1,117 +1,928 @@
-use crate::ast::{self, EntityOptions, GlobalOption, GlobalOptionType, HeaderOptions};
-use std::collections::HashMap;
+use crate::ast::{self, GlobalOption, GlobalOptionType};
+use crate::borrowed;
+use crate::error::ErdError;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use indexmap::IndexMap;
 use nom::{IResult, branch::alt, InputTakeAtPosition, AsChar,
     error::{ParseError, ErrorKind},
     bytes::{
-        complete::{is_not, tag, take_while, take_while1},
+        complete::{tag, take_while, take_while1},
     },
     character::{
         complete::{
-            alphanumeric1,
             char,
+            digit1,
             line_ending,
             one_of,
             space0,
+            space1,
             not_line_ending,
             multispace0,
             multispace1,
         },
-        is_alphanumeric
     }, combinator::{
         value,
         map,
+        map_opt,
         opt,
+        recognize,
         all_consuming,
         eof,
     }, multi::{
         many0,
         separated_list0,
+        separated_list1,
     },
     sequence::{
         delimited,
+        pair,
         separated_pair,
         terminated,
         preceded,
+        tuple,
     }};
 
-pub fn parse_erd<'a>(i: &'a str) -> Result<ast::Erd, String> {
-    let a = match parse::<'a, ErdParseError<&str>>(i) {
-        Ok((_m, a)) => a,
-        Err(err) => return Err(err.to_string()),
+/// Settings which affect how `.er` source is interpreted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Global directives giving default options, such as those of a theme
+    /// or config file. Unlike `directives`, these only fill in what
+    /// entities and relationships leave unset, so the input's own options
+    /// and directives take precedence.
+    pub defaults: Vec<GlobalOption>,
+    /// Global directives applied ahead of any in the input, as though they
+    /// were written at the top of it.
+    pub directives: Vec<GlobalOption>,
+    /// Path of the file the input was read from. When given, `include`
+    /// declarations are resolved relative to its directory, otherwise
+    /// they're left in [`ast::Erd::includes`].
+    pub path: Option<PathBuf>,
+    /// Characters allowed in unquoted identifiers besides letters, digits
+    /// and `_`, e.g. `.-$` to allow names like `auth.users`. See
+    /// [`invalid_ident_char`] for those which can't be allowed.
+    pub ident_chars: String,
+    /// What to do when an entity is declared more than once.
+    pub duplicate_entities: DuplicateEntityPolicy,
+    /// Skip options with unknown keys rather than failing, recording them in
+    /// [`ast::Erd::ignored_options`].
+    pub lenient: bool,
+}
+
+impl ParseOptions {
+    /// Returns the items to resolve ahead of the input: title and graph
+    /// defaults, which apply to the whole diagram and so are overridden by
+    /// any directives that follow, then `directives`.
+    pub(crate) fn leading_items(&self) -> Vec<ast::Ast> {
+        self.defaults.iter()
+            .filter(|d| matches!(d.option_type, GlobalOptionType::Title | GlobalOptionType::Graph))
+            .chain(&self.directives)
+            .cloned()
+            .map(ast::Ast::GlobalOption)
+            .collect()
+    }
+
+    /// Resolves the header, entity and relationship options of `defaults`.
+    /// Options with unknown keys are recorded in `ignored`, if given, rather
+    /// than failing.
+    pub(crate) fn resolve_defaults(&self, mut ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<Defaults, ErdError> {
+        let mut defaults = Defaults::default();
+        for d in &self.defaults {
+            let mut options = d.options.clone();
+            let ignored = ignored.as_deref_mut();
+            let span = d.span;
+            match d.option_type {
+                GlobalOptionType::Header => defaults.header = ast::resolve_options(&mut options, ignored, span, |m| {
+                    ast::merged(&defaults.header, m, ast::HeaderOptions::merge_hashmap)
+                })?,
+                GlobalOptionType::Entity => defaults.entity = ast::resolve_options(&mut options, ignored, span, |m| {
+                    ast::merged(&defaults.entity, m, ast::EntityOptions::merge_hashmap)
+                })?,
+                GlobalOptionType::Relationship => defaults.relationship = ast::resolve_options(&mut options, ignored, span, |m| {
+                    ast::merged(&defaults.relationship, m, ast::RelationshipOptions::merge_hashmap)
+                })?,
+                GlobalOptionType::Title | GlobalOptionType::Graph => (),
+            }
+        }
+        Ok(defaults)
+    }
+}
+
+/// Options which entities and relationships start from, before their own
+/// are merged over them, from [`ParseOptions::defaults`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Defaults {
+    pub header: ast::HeaderOptions,
+    pub entity: ast::EntityOptions,
+    pub relationship: ast::RelationshipOptions,
+}
+
+/// What to do when an entity is declared more than once.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateEntityPolicy {
+    /// Keep every declaration, leaving them to be reported when the diagram
+    /// is validated.
+    #[default]
+    Warn,
+    /// Fail, reporting where both declarations are.
+    Error,
+    /// Combine them into the first declaration, appending the attributes
+    /// and indexes of later ones. Options of later declarations are
+    /// ignored.
+    Merge,
+}
+
+/// Characters which have a meaning of their own where identifiers are
+/// written, so can't be allowed in unquoted ones.
+const RESERVED_IDENT_CHARS: &str = "[](){}\"'`:,#*+?\\";
+
+/// Returns the first of `chars` which can't be allowed in unquoted
+/// identifiers with [`ParseOptions::ident_chars`]: whitespace, or a
+/// character with a meaning of its own, such as a bracket or quote.
+pub fn invalid_ident_char(chars: &str) -> Option<char> {
+    chars.chars().find(|&c| c.is_whitespace() || RESERVED_IDENT_CHARS.contains(c))
+}
+
+pub fn parse_erd(i: &str) -> Result<ast::Erd, ErdError> {
+    parse_erd_with(i, &ParseOptions::default())
+}
+
+/// Parses an ERD using the given options.
+pub fn parse_erd_with(i: &str, options: &ParseOptions) -> Result<ast::Erd, ErdError> {
+    parse_erd_borrowed_with(i, options)?.into_owned_with(options)
+}
+
+/// Parses an ERD without copying names or option values out of the input.
+///
+/// Variables are substituted into option values, which are only copied when
+/// they refer to one. Options aren't validated and global directives aren't
+/// applied until the result is converted with [`borrowed::Erd::into_owned`].
+pub fn parse_erd_borrowed(i: &str) -> Result<borrowed::Erd<'_>, ErdError> {
+    parse_erd_borrowed_with(i, &ParseOptions::default())
+}
+
+/// As [`parse_erd_borrowed`], using the given options. Only those affecting
+/// syntax are used, the rest apply when the result is converted with
+/// [`borrowed::Erd::into_owned_with`].
+pub fn parse_erd_borrowed_with<'a>(i: &'a str, options: &ParseOptions) -> Result<borrowed::Erd<'a>, ErdError> {
+    let items = match parse_borrowed(i, &options.ident_chars) {
+        Ok((_, items)) => items,
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => return Err(e.into_erd_error(i)),
+        Err(nom::Err::Incomplete(_)) => return Err(parse_error(i, "", "unexpected end of input".to_owned())),
     };
 
-    let mut entities = Vec::new();
+    let mut erd = borrowed::Erd::default();
+    // Whether the last entity's attributes were given in a block, so no more
+    // can follow.
+    let mut closed = false;
+    for mut item in items {
+        item.substitute(&erd.variables)?;
+        match item {
+            Decl::Include(path) => erd.includes.push(path),
+            Decl::Set(name, value) => {
+                erd.variables.insert(name, value);
+            },
+            Decl::Directive(d) => erd.directives.push(d),
+            Decl::Entity(e, block) => {
+                erd.entities.push(e);
+                closed = block;
+            },
+            Decl::Attribute(_) | Decl::Index(_) if closed => {
+                let entity = &erd.entities.last().expect("closed by an entity").name;
+                return Err(after_block_error(entity));
+            },
+            Decl::Relation(r) => erd.relationships.push(r),
+            Decl::NaryRelation(r) => erd.nary_relationships.push(r),
+            Decl::Enum(e) => erd.enums.push(e),
+            Decl::Note(n) => erd.notes.push(n),
+            Decl::Attribute(a) => match erd.entities.last_mut() {
+                Some(e) => e.attribs.push(a),
+                None => return Err(ErdError::Semantic(String::from("found attribute without a preceding entity to attach it to"))),
+            },
+            Decl::Index(index) => match erd.entities.last_mut() {
+                Some(e) => e.indexes.push(index),
+                None => return Err(ErdError::Semantic(String::from("found index without a preceding entity to attach it to"))),
+            },
+        }
+    }
+    Ok(erd)
+}
+
+/// Parses an ERD, continuing past any errors rather than stopping at the
+/// first one.
+///
+/// When a declaration fails to parse, it's skipped up to the start of the
+/// next line (or past the end of its option block) and parsing resumes
+/// there. Returns whatever could be parsed, along with every error found.
+pub fn parse_erd_recover(i: &str) -> (ast::Erd, Vec<ErdError>) {
+    parse_erd_recover_with(i, &ParseOptions::default())
+}
+
+/// As [`parse_erd_recover`], using the given options.
+pub fn parse_erd_recover_with(i: &str, options: &ParseOptions) -> (ast::Erd, Vec<ErdError>) {
+    let mut ignored = Vec::new();
+    let mut included = Vec::new();
+    let defaults = options.resolve_defaults(options.lenient.then_some(&mut included));
+    let (includes, variables, a, mut errors) = parse_recover(
+        i,
+        &options.ident_chars,
+        defaults.as_ref().unwrap_or(&Defaults::default()),
+        options.lenient.then_some(&mut ignored),
+    );
+    let defaults = match defaults {
+        Ok(defaults) => defaults,
+        Err(err) => {
+            errors.insert(0, err);
+            Defaults::default()
+        },
+    };
+    let mut items = options.leading_items();
+    if let Some(path) = &options.path {
+        items.extend(include_files(&includes, path, options, &defaults, &mut included, &mut errors));
+    }
+    items.extend(a);
+    // Included items come first, so options ignored in them do too.
+    included.append(&mut ignored);
+
+    let mut erd = build_erd(items, options, included, &mut errors);
+    if options.path.is_none() {
+        erd.includes = includes.iter().map(|p| p.to_string()).collect();
+    }
+    erd.variables = variables.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    (erd, errors)
+}
+
+/// Parses an ERD lazily, returning an iterator over its items in order.
+///
+/// Nothing is parsed until the iterator is advanced, and each call parses
+/// only the next declaration, so items can be processed as they're read
+/// without building the whole diagram. Entities are yielded without their
+/// attributes and indexes when given one per line, as those follow as items
+/// of their own. Global directives are yielded as they appear, rather than
+/// applied to the items after them.
+pub fn parse_erd_items(i: &str) -> ErdItems<'_> {
+    parse_erd_items_with(i, ParseOptions::default())
+}
+
+/// As [`parse_erd_items`], using the given options. Defaults and directives
+/// given in `options` are yielded first, as directives, and includes are resolved if `options`
+/// gives the path of the input, otherwise they're skipped.
+pub fn parse_erd_items_with(i: &str, options: ParseOptions) -> ErdItems<'_> {
+    ErdItems {
+        input: i,
+        rest: i,
+        pending: options.defaults.iter()
+            .chain(&options.directives)
+            .cloned()
+            .map(ast::Ast::GlobalOption)
+            .collect(),
+        options,
+        variables: Variables::new(),
+        stage: Stage::Includes,
+        entity: None,
+        ignored: Vec::new(),
+        done: false,
+    }
+}
+
+/// Iterator over the items of an ERD, parsing each as it's needed.
+///
+/// Returned by [`parse_erd_items`]. Stops after yielding the first error.
+pub struct ErdItems<'a> {
+    input: &'a str,
+    /// Input not yet parsed.
+    rest: &'a str,
+    options: ParseOptions,
+    /// Items parsed but not yet yielded.
+    pending: VecDeque<ast::Ast>,
+    variables: Variables<'a>,
+    stage: Stage,
+    /// Name of the last entity, and whether its attributes were given in a
+    /// block, so no more can follow.
+    entity: Option<(String, bool)>,
+    ignored: Vec<ast::IgnoredOption>,
+    done: bool,
+}
+
+/// Which declarations may come next.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Stage {
+    /// Anything, including `include`.
+    Includes,
+    /// Anything but `include`, including `set` and directives without an
+    /// option block.
+    Preamble,
+    /// Only entities, relationships and the like.
+    Body,
+}
+
+impl<'a> ErdItems<'a> {
+    /// Options with unknown keys skipped so far, when parsing leniently.
+    pub fn ignored_options(&self) -> &[ast::IgnoredOption] {
+        &self.ignored
+    }
+
+    /// Parses the next declaration, returning `None` at the end of the
+    /// input.
+    fn parse_next(&mut self) -> Option<Result<ast::Ast, ErdError>> {
+        loop {
+            if let Ok((rest, _)) = blank_or_comment(self.rest) {
+                self.rest = rest;
+            }
+            if self.rest.is_empty() {
+                return None;
+            }
+
+            let (input, i) = (self.input, self.rest);
+            if self.stage == Stage::Includes {
+                if let Ok((rest, path)) = include(i) {
+                    self.rest = rest;
+                    if let Some(from) = &self.options.path {
+                        let mut errors = Vec::new();
+                        let items = include_files(&[path], from, &self.options, &Defaults::default(), &mut self.ignored, &mut errors);
+                        if let Some(err) = errors.into_iter().next() {
+                            return Some(Err(err));
+                        }
+                        self.pending.extend(items);
+                        if let Some(item) = self.pending.pop_front() {
+                            return Some(Ok(item));
+                        }
+                    }
+                    continue;
+                }
+                self.stage = Stage::Preamble;
+            }
+
+            let ident_chars = &self.options.ident_chars;
+            let decl = if self.stage == Stage::Preamble {
+                alt((
+                    map(set, |(name, value)| Decl::Set(name, value)),
+                    map(spanned(input, directive), |(span, d)| Decl::Directive(borrowed::Directive { span, ..d })),
+                    declaration(input, ident_chars),
+                ))(i)
+            } else {
+                declaration(input, ident_chars)(i)
+            };
+            let mut decl = match decl {
+                Ok((rest, decl)) => {
+                    self.rest = rest;
+                    decl
+                },
+                Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => return Some(Err(e.into_erd_error(input))),
+                Err(nom::Err::Incomplete(_)) => return Some(Err(parse_error(input, "", "unexpected end of input".to_owned()))),
+            };
+
+            match &decl {
+                Decl::Set(name, value) => {
+                    self.variables.insert(name, value.clone());
+                    continue;
+                },
+                Decl::Directive(_) => (),
+                Decl::Entity(e, block) => {
+                    self.entity = Some((e.name.to_string(), *block));
+                    self.stage = Stage::Body;
+                },
+                Decl::Attribute(_) | Decl::Index(_) => match &self.entity {
+                    Some((name, true)) => return Some(Err(after_block_error(name))),
+                    Some(_) => (),
+                    None => return Some(Err(ErdError::Semantic(format!(
+                        "found {} without a preceding entity to attach it to",
+                        if matches!(decl, Decl::Index(_)) { "index" } else { "attribute" },
+                    )))),
+                },
+                _ => self.stage = Stage::Body,
+            }
+            let ignored = self.options.lenient.then_some(&mut self.ignored);
+            return Some(decl.substitute(&self.variables)
+                .and_then(|_| decl.into_item(&Defaults::default(), ignored))
+                .map(|(item, _)| item));
+        }
+    }
+}
+
+impl<'a> Iterator for ErdItems<'a> {
+    type Item = Result<ast::Ast, ErdError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.pending.pop_front() {
+            return Some(Ok(item));
+        }
+        if self.done {
+            return None;
+        }
+        let item = self.parse_next();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+impl<'a> std::iter::FusedIterator for ErdItems<'a> {}
+
+/// Reads and parses the files named by `include` declarations in the file at
+/// `from`, returning their items in order, preceded by those of any files
+/// they include in turn.
+///
+/// Included items have default spans, as they aren't part of the including
+/// file's source. Files which can't be read or parsed, or which include
+/// themselves, are skipped and their errors recorded. When parsing
+/// leniently, options with unknown keys are recorded in `ignored`.
+pub(crate) fn include_files<S: AsRef<str>>(
+    includes: &[S],
+    from: &Path,
+    options: &ParseOptions,
+    defaults: &Defaults,
+    ignored: &mut Vec<ast::IgnoredOption>,
+    errors: &mut Vec<ErdError>,
+) -> Vec<ast::Ast> {
+    let mut stack = vec![from.canonicalize().unwrap_or_else(|_| from.to_owned())];
+    include_files_from(includes, options, defaults, &mut stack, ignored, errors)
+}
+
+fn include_files_from<S: AsRef<str>>(
+    includes: &[S],
+    options: &ParseOptions,
+    defaults: &Defaults,
+    stack: &mut Vec<PathBuf>,
+    ignored: &mut Vec<ast::IgnoredOption>,
+    errors: &mut Vec<ErdError>,
+) -> Vec<ast::Ast> {
+    let dir = stack.last().and_then(|p| p.parent()).map(Path::to_owned).unwrap_or_default();
+    let mut items = Vec::new();
+
+    for include in includes {
+        let path = dir.join(include.as_ref());
+        let error = |message: String| ErdError::Include { path: path.display().to_string(), message };
+        let (path, text) = match path.canonicalize().and_then(|p| fs::read_to_string(&p).map(|text| (p, text))) {
+            Ok(file) => file,
+            Err(err) => {
+                errors.push(error(err.to_string()));
+                continue;
+            },
+        };
+        if stack.contains(&path) {
+            errors.push(error("file includes itself".to_owned()));
+            continue;
+        }
+        let erd = match parse_erd_borrowed_with(&text, options) {
+            Ok(erd) => erd,
+            Err(err) => {
+                errors.push(error(err.to_string()));
+                continue;
+            },
+        };
+
+        stack.push(path);
+        items.extend(include_files_from(&erd.includes, options, defaults, stack, ignored, errors));
+        stack.pop();
+        match erd.into_items(defaults, options.lenient.then_some(&mut *ignored)) {
+            Ok(included) => items.extend(included.into_iter().map(without_spans)),
+            Err(err) => errors.push(error(err.to_string())),
+        }
+    }
+    items
+}
+
+/// Resets the spans of an item and anything in it.
+fn without_spans(item: ast::Ast) -> ast::Ast {
+    let span = ast::Span::default();
+    match item {
+        ast::Ast::Entity(e) => ast::Ast::Entity(ast::Entity {
+            attribs: e.attribs.into_iter().map(|a| ast::Attribute { span, ..a }).collect(),
+            indexes: e.indexes.into_iter().map(|x| ast::Index { span, ..x }).collect(),
+            span,
+            ..e
+        }),
+        ast::Ast::Attribute(a) => ast::Ast::Attribute(ast::Attribute { span, ..a }),
+        ast::Ast::Index(x) => ast::Ast::Index(ast::Index { span, ..x }),
+        ast::Ast::Relation(r) => ast::Ast::Relation(ast::Relation { span, ..r }),
+        ast::Ast::NaryRelation(r) => ast::Ast::NaryRelation(ast::NaryRelation { span, ..r }),
+        ast::Ast::Enum(e) => ast::Ast::Enum(ast::Enum { span, ..e }),
+        ast::Ast::Note(n) => ast::Ast::Note(ast::Note { span, ..n }),
+        ast::Ast::GlobalOption(o) => ast::Ast::GlobalOption(ast::GlobalOption { span, ..o }),
+    }
+}
+
+/// Resolves parsed items into an `ast::Erd`, applying global directives and
+/// handling entities declared more than once as `options` says to. Items
+/// which can't be resolved are skipped and their errors recorded.
+///
+/// `ignored` holds options already skipped while converting the items, to
+/// which any unknown options in directives are added when parsing
+/// leniently.
+pub(crate) fn build_erd(a: Vec<ast::Ast>, options: &ParseOptions, mut ignored: Vec<ast::IgnoredOption>, errors: &mut Vec<ErdError>) -> ast::Erd {
+    let duplicates = options.duplicate_entities;
+    let lenient = options.lenient;
+    let mut entities: Vec<ast::Entity> = Vec::new();
+    // Index of the entity which attributes and indexes are added to.
+    let mut current = None;
     let mut relationships = Vec::new();
-    let mut title_directive = HashMap::new();
-    let mut header_directive = HashMap::new();
-    let mut entity_directive = HashMap::new();
-    let mut relationship_directive = HashMap::new();
+    let mut nary_relationships = Vec::new();
+    let mut enums = Vec::new();
+    let mut notes = Vec::new();
+    let mut title_directive = ast::OptionMap::new();
+    let mut header_directive = ast::OptionMap::new();
+    let mut entity_directive = ast::OptionMap::new();
+    let mut relationship_directive = ast::OptionMap::new();
+    let mut graph_directive = ast::OptionMap::new();
 
     for o in a.into_iter() {
         match o {
             ast::Ast::Entity(mut e) => {
-                e.options.merge_hashmap(&entity_directive)?;
-                e.header_options.merge_hashmap(&header_directive)?;
-                entities.push(e);
+                if let Err(err) = e.options.merge_hashmap(&entity_directive) {
+                    errors.push(err);
+                }
+                if let Err(err) = e.header_options.merge_hashmap(&header_directive) {
+                    errors.push(err);
+                }
+                let first = match duplicates {
+                    DuplicateEntityPolicy::Warn => None,
+                    _ => entities.iter().position(|d| d.name == e.name),
+                };
+                match first {
+                    Some(i) if duplicates == DuplicateEntityPolicy::Merge => {
+                        let existing = &mut entities[i];
+                        existing.attribs.extend(e.attribs);
+                        existing.indexes.extend(e.indexes);
+                        current = Some(i);
+                    },
+                    Some(i) => {
+                        errors.push(ErdError::DuplicateEntity {
+                            name: e.name.into(),
+                            first_line: entities[i].span.line,
+                            line: e.span.line,
+                        });
+                        current = Some(i);
+                    },
+                    None => {
+                        current = Some(entities.len());
+                        entities.push(e);
+                    },
+                }
             },
             ast::Ast::Relation(mut r) => {
-                r.options.merge_hashmap(&relationship_directive)?;
+                if let Err(err) = r.options.merge_hashmap(&relationship_directive) {
+                    errors.push(err);
+                }
                 relationships.push(r);
             },
+            ast::Ast::NaryRelation(mut r) => {
+                if let Err(err) = r.options.merge_hashmap(&relationship_directive) {
+                    errors.push(err);
+                }
+                nary_relationships.push(r);
+            },
+            ast::Ast::Enum(e) => enums.push(e),
+            ast::Ast::Note(n) => notes.push(n),
             ast::Ast::Attribute(a) => {
-                match entities.last_mut() {
+                match current.map(|i| &mut entities[i]) {
                     Some(e) => e.add_attribute(a),
-                    None => return Err(String::from("found attribute without a preceding entity to attach it to")),
+                    None => errors.push(ErdError::Semantic(String::from("found attribute without a preceding entity to attach it to"))),
+                }
+            },
+            ast::Ast::Index(index) => {
+                match current.map(|i| &mut entities[i]) {
+                    Some(e) => e.indexes.push(index),
+                    None => errors.push(ErdError::Semantic(String::from("found index without a preceding entity to attach it to"))),
                 }
             },
-            ast::Ast::GlobalOption(ast::GlobalOption { option_type, options }) => {
+            ast::Ast::GlobalOption(ast::GlobalOption { option_type, mut options, span }) => {
                 use ast::GlobalOptionType::*;
+                if lenient {
+                    // Only unknown keys are removed here. Invalid values are
+                    // reported when the directive is applied.
+                    let _ = ast::resolve_options(&mut options, Some(&mut ignored), span, |m| match option_type {
+                        Title => ast::TitleOptions::default().merge_hashmap(m),
+                        Header => ast::HeaderOptions::default().merge_hashmap(m),
+                        Entity => ast::EntityOptions::default().merge_hashmap(m),
+                        Relationship => ast::RelationshipOptions::default().merge_hashmap(m),
+                        Graph => ast::GraphOptions::default().merge_hashmap(m),
+                    });
+                }
                 match option_type {
                     Title => title_directive.extend(options),
                     Header => header_directive.extend(options),
                     Entity => entity_directive.extend(options),
                     Relationship => relationship_directive.extend(options),
+                    Graph => graph_directive.extend(options),
                 }
             }
         }
     }
 
     let mut title_options = ast::TitleOptions::default();
-    title_options.merge_hashmap(&title_directive)?;
-    Ok(ast::Erd { entities, relationships, title_options })
+    if let Err(err) = title_options.merge_hashmap(&title_directive) {
+        errors.push(err);
+    }
+    let mut graph_options = ast::GraphOptions::default();
+    if let Err(err) = graph_options.merge_hashmap(&graph_directive) {
+        errors.push(err);
+    }
+    let mut erd = ast::Erd {
+        entities,
+        relationships,
+        nary_relationships,
+        enums,
+        notes,
+        title_options,
+        graph_options,
+        includes: Vec::new(),
+        variables: IndexMap::new(),
+        ignored_options: ignored,
+    };
+    erd.intern();
+    erd
+}
+
+/// Error for an attribute or index following an entity whose attributes
+/// were given in a block.
+fn after_block_error(entity: &str) -> ErdError {
+    ErdError::Semantic(format!("found attribute after the closing brace of entity '{}'", entity))
+}
+
+/// A single top level declaration, borrowed from the input.
+enum Decl<'a> {
+    Include(Cow<'a, str>),
+    /// A variable's name and value.
+    Set(&'a str, Cow<'a, str>),
+    Directive(borrowed::Directive<'a>),
+    /// An entity, and whether its attributes were given in a block.
+    Entity(borrowed::Entity<'a>, bool),
+    Relation(borrowed::Relation<'a>),
+    NaryRelation(borrowed::NaryRelation<'a>),
+    Enum(borrowed::Enum<'a>),
+    Note(borrowed::Note<'a>),
+    Attribute(borrowed::Attribute<'a>),
+    Index(borrowed::Index<'a>),
+}
+
+/// Values of the variables `set` so far, by name.
+type Variables<'a> = IndexMap<&'a str, Cow<'a, str>>;
+
+impl<'a> Decl<'a> {
+    /// Replaces variable references in the declaration's option values.
+    fn substitute(&mut self, variables: &Variables<'a>) -> Result<(), ErdError> {
+        match self {
+            Decl::Include(_) | Decl::Set(..) => Ok(()),
+            Decl::Directive(d) => substitute_options(&mut d.options, variables),
+            Decl::Entity(e, _) => {
+                substitute_options(&mut e.options, variables)?;
+                for a in &mut e.attribs {
+                    substitute_options(&mut a.options, variables)?;
+                }
+                for x in &mut e.indexes {
+                    substitute_options(&mut x.options, variables)?;
+                }
+                Ok(())
+            },
+            Decl::Relation(r) => substitute_options(&mut r.options, variables),
+            Decl::NaryRelation(r) => substitute_options(&mut r.options, variables),
+            Decl::Enum(e) => substitute_options(&mut e.options, variables),
+            Decl::Note(n) => substitute_options(&mut n.options, variables),
+            Decl::Attribute(a) => substitute_options(&mut a.options, variables),
+            Decl::Index(x) => substitute_options(&mut x.options, variables),
+        }
+    }
+
+    /// Converts the declaration into an item, also returning whether it was
+    /// an entity with its attributes given in a block. Includes and
+    /// variables aren't items, so must be handled before this. Options with
+    /// unknown keys are recorded in `ignored`, if given, rather than failing.
+    fn into_item(self, defaults: &Defaults, ignored: Option<&mut Vec<ast::IgnoredOption>>) -> Result<(ast::Ast, bool), ErdError> {
+        Ok(match self {
+            Decl::Directive(d) => (ast::Ast::GlobalOption(d.into_owned()), false),
+            Decl::Entity(e, block) => (ast::Ast::Entity(e.resolve(defaults, ignored)?), block),
+            Decl::Relation(r) => (ast::Ast::Relation(r.resolve(defaults, ignored)?), false),
+            Decl::NaryRelation(r) => (ast::Ast::NaryRelation(r.resolve(defaults, ignored)?), false),
+            Decl::Enum(e) => (ast::Ast::Enum(e.resolve(ignored)?), false),
+            Decl::Note(n) => (ast::Ast::Note(n.resolve(ignored)?), false),
+            Decl::Attribute(a) => (ast::Ast::Attribute(a.resolve(ignored)?), false),
+            Decl::Index(x) => (ast::Ast::Index(x.resolve(ignored)?), false),
+            Decl::Include(_) | Decl::Set(..) => unreachable!("includes and variables aren't items"),
+        })
+    }
+}
+
+fn substitute_options<'a>(options: &mut borrowed::Options<'a>, variables: &Variables<'a>) -> Result<(), ErdError> {
+    for (_, value) in options.iter_mut() {
+        *value = substitute(std::mem::take(value), variables)?;
+    }
+    Ok(())
 }
 
-fn parse<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&str, Vec<ast::Ast>, ErdParseError<&str>> {
-    let (i, mut global_opts) = many0(
+/// Replaces each `$name` in an option value with the value of that
+/// variable. `$$` is a literal `$`, as is a `$` not followed by a name.
+fn substitute<'a>(value: Cow<'a, str>, variables: &Variables<'a>) -> Result<Cow<'a, str>, ErdError> {
+    if !value.contains('$') {
+        return Ok(value);
+    }
+
+    let mut out = String::with_capacity(value.len());
+    let mut rest = &*value;
+    while let Some(p) = rest.find('$') {
+        out.push_str(&rest[..p]);
+        rest = &rest[p + 1..];
+        if let Some(r) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = r;
+            continue;
+        }
+        let name = match variable_name(rest) {
+            Ok((r, name)) => {
+                rest = r;
+                name
+            },
+            Err(_) => {
+                out.push('$');
+                continue;
+            },
+        };
+        match variables.get(name) {
+            Some(v) => out.push_str(v),
+            None => return Err(ErdError::UndefinedVariable { name: name.to_owned() }),
+        }
+    }
+    out.push_str(rest);
+    Ok(Cow::Owned(out))
+}
+
+fn parse_borrowed<'a>(input: &'a str, ident_chars: &str) -> IResult<&'a str, Vec<Decl<'a>>, ErdParseError<&'a str>> {
+    let i = input;
+    let (i, mut includes) = many0(
         delimited(
             blank_or_comment,
-            map(global_option, |g| ast::Ast::GlobalOption(g)),
+            map(include, Decl::Include),
             blank_or_comment,
         )
     )(i)?;
-
-    let (_, mut era) = all_consuming(
+    let (i, mut directives) = many0(
         delimited(
             blank_or_comment,
+            alt((
+                map(set, |(name, value)| Decl::Set(name, value)),
+                map(spanned(input, directive), |(span, d)| Decl::Directive(borrowed::Directive { span, ..d })),
+            )),
+            blank_or_comment,
+        )
+    )(i)?;
 
+    let (_, mut decls) = all_consuming(
+        delimited(
+            blank_or_comment,
             many0(
                 delimited(
                     blank_or_comment,
-                    alt((
-                        map(entity, |e| ast::Ast::Entity(e)),
-                        map(relation, |r| ast::Ast::Relation(r)),
-                        map(attribute, |a| ast::Ast::Attribute(a)),
-                    )),
+                    declaration(input, ident_chars),
                     blank_or_comment,
                 )
             ),
-
             blank_or_comment,
         )
     )(i)?;
 
-    global_opts.append(&mut era);
-    Ok((i, global_opts))
+    includes.append(&mut directives);
+    includes.append(&mut decls);
+    Ok((i, includes))
+}
+
+/// Parses as much of the input as possible, returning the paths of any
+/// includes, the variables set, the items parsed, and errors for anything
+/// that couldn't be. Options with unknown keys are recorded in `ignored`,
+/// if given, rather than being errors.
+fn parse_recover<'a>(
+    input: &'a str,
+    ident_chars: &str,
+    defaults: &Defaults,
+    mut ignored: Option<&mut Vec<ast::IgnoredOption>>,
+) -> (Vec<Cow<'a, str>>, Variables<'a>, Vec<ast::Ast>, Vec<ErdError>) {
+    let mut includes = Vec::new();
+    let mut variables = Variables::new();
+    let mut items = Vec::new();
+    let mut errors = Vec::new();
+    let mut i = input;
+    // Name of the last entity, if its attributes were given in a block.
+    let mut closed: Option<String> = None;
+
+    loop {
+        i = match blank_or_comment(i) {
+            Ok((rest, _)) => rest,
+            Err(_) => i,
+        };
+        if i.is_empty() {
+            break;
+        }
+
+        // Includes are only recognised before anything else.
+        if items.is_empty() && variables.is_empty() {
+            if let Ok((rest, path)) = include(i) {
+                includes.push(path);
+                i = rest;
+                continue;
+            }
+        }
+
+        // Variables, and directives without an option block, are only
+        // recognised before any other declaration.
+        let decl = if items.iter().all(|a| matches!(a, ast::Ast::GlobalOption(_))) {
+            alt((
+                map(set, |(name, value)| Decl::Set(name, value)),
+                map(spanned(input, directive), |(span, d)| Decl::Directive(borrowed::Directive { span, ..d })),
+                declaration(input, ident_chars),
+            ))(i)
+        } else {
+            declaration(input, ident_chars)(i)
+        };
+
+        match decl {
+            Ok((rest, mut decl)) => {
+                i = rest;
+                if let Decl::Set(name, value) = decl {
+                    variables.insert(name, value);
+                    continue;
+                }
+                let (a, block) = match decl.substitute(&variables).and_then(|_| decl.into_item(defaults, ignored.as_deref_mut())) {
+                    Ok(item) => item,
+                    Err(err) => {
+                        errors.push(err);
+                        continue;
+                    },
+                };
+                match (&a, &closed) {
+                    (ast::Ast::Attribute(_), Some(entity)) | (ast::Ast::Index(_), Some(entity)) => {
+                        errors.push(after_block_error(entity));
+                    },
+                    _ => {
+                        if let ast::Ast::Entity(e) = &a {
+                            closed = if block { Some(e.name.to_string()) } else { None };
+                        }
+                        items.push(a);
+                    },
+                }
+            },
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                errors.push(e.into_erd_error(input));
+                i = skip_declaration(i);
+            },
+            Err(nom::Err::Incomplete(_)) => break,
+        }
+    }
+
+    (includes, variables, items, errors)
+}
+
+/// Parses an entity, relationship, enum, note, attribute or index declaration, or a
+/// directive applying to the declarations after it.
+fn declaration<'a, 'b>(input: &'a str, ident_chars: &'b str) -> impl FnMut(&'a str) -> IResult<&'a str, Decl<'a>, ErdParseError<&'a str>> + 'b
+where
+    'a: 'b,
+{
+    alt((
+        map(spanned(input, directive_block), |(span, d)| Decl::Directive(borrowed::Directive { span, ..d })),
+        map(spanned(input, entity_decl(input, ident_chars)), |(span, (e, block))| Decl::Entity(borrowed::Entity { span, ..e }, block)),
+        map(spanned(input, relation_decl(ident_chars)), |(span, r)| Decl::Relation(borrowed::Relation { span, ..r })),
+        map(spanned(input, enum_decl(ident_chars)), |(span, e)| Decl::Enum(borrowed::Enum { span, ..e })),
+        map(spanned(input, note_decl), |(span, n)| Decl::Note(borrowed::Note { span, ..n })),
+        map(spanned(input, nary_relation_decl(ident_chars)), |(span, r)| Decl::NaryRelation(borrowed::NaryRelation { span, ..r })),
+        map(spanned(input, index_decl), |(span, x)| Decl::Index(borrowed::Index { span, ..x })),
+        map(spanned(input, attribute_decl(ident_chars)), |(span, a)| Decl::Attribute(borrowed::Attribute { span, ..a })),
+    ))
+}
+
+/// Wraps a parser to also return the span of `input` that it consumed, less
+/// any trailing whitespace.
+fn spanned<'a, O, F>(input: &'a str, mut f: F) -> impl FnMut(&'a str) -> IResult<&'a str, (ast::Span, O), ErdParseError<&'a str>>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O, ErdParseError<&'a str>>,
+{
+    move |i: &'a str| {
+        let (rest, o) = f(i)?;
+        let start = input.len() - i.len();
+        let end = start + i[..i.len() - rest.len()].trim_end().len();
+        let (line, column) = line_column(input, start);
+        Ok((rest, (ast::Span { start, end, line, column }, o)))
+    }
+}
+
+/// Skips past the rest of the current line, or if it opens an option block
+/// which isn't closed on the same line, past the line the block closes on.
+fn skip_declaration(i: &str) -> &str {
+    let line_end = |s: &str| s.find('\n').map(|p| p + 1).unwrap_or(s.len());
+    let end = line_end(i);
+    let line = &i[..end];
+
+    match (line.find('{'), line.find('}')) {
+        (Some(_), None) => match i[end..].find('}') {
+            Some(close) => {
+                let rest = &i[end + close..];
+                &rest[line_end(rest)..]
+            },
+            None => "",
+        },
+        _ => &i[end..],
+    }
 }
 
 fn comment(i: &str) -> IResult<&str, &str, ErdParseError<&str>> {
@@ -144,79 +955,194 @@ fn multispace0_comment(i: &str) -> IResult<&str, (), ErdParseError<&str>> {
     )(i)
 }
 
-fn entity(i: &str) -> IResult<&str, ast::Entity, ErdParseError<&str>> {
-    let (i, name) = delimited(char('['), ident, char(']'))(i)?;
-    let (i, opts) = trailing_options(i)?;
+/// Parses an entity, along with its attributes if they're given in a block,
+/// e.g. `[orders] { *id customer_id }`. Also returns whether they were.
+///
+/// Spans of attributes in a block are located within `input`.
+fn entity_decl<'a, 'b>(input: &'a str, ident_chars: &'b str) -> impl FnMut(&'a str) -> IResult<&'a str, (borrowed::Entity<'a>, bool), ErdParseError<&'a str>> + 'b
+where
+    'a: 'b,
+{
+    move |i: &'a str| {
+        let (i, (name, weak)) = alt((
+            map(delimited(tag("[["), ident(ident_chars), tag("]]")), |name| (name, true)),
+            map(delimited(char('['), ident(ident_chars), char(']')), |name| (name, false)),
+        ))(i)?;
+        let (i, options) = trailing_options(i)?;
+        let (i, block) = opt(terminated(attribute_block(input, ident_chars), space0))(i)?;
+
+        let closed = block.is_some();
+        let (mut attribs, mut indexes) = (Vec::new(), Vec::new());
+        for decl in block.unwrap_or_default() {
+            match decl {
+                Decl::Attribute(a) => attribs.push(a),
+                Decl::Index(x) => indexes.push(x),
+                _ => unreachable!("blocks only contain attributes and indexes"),
+            }
+        }
+        Ok((i, (borrowed::Entity {
+            name,
+            weak,
+            attribs,
+            indexes,
+            options,
+            span: ast::Span::default(),
+        }, closed)))
+    }
+}
 
-    let entity_options = match EntityOptions::from_hashmap(&opts) {
-        Ok(o) => o,
-        Err(e) => return Err(nom::Err::Error(ErdParseError::InvalidOption(e))),
-    };
+/// Parses a brace enclosed block of attribute and index declarations.
+fn attribute_block<'a, 'b>(input: &'a str, ident_chars: &'b str) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<Decl<'a>>, ErdParseError<&'a str>> + 'b
+where
+    'a: 'b,
+{
+    move |i: &'a str| {
+        delimited(
+            char('{'),
+            many0(delimited(
+                blank_or_comment,
+                alt((
+                    map(spanned(input, index_decl), |(span, x)| Decl::Index(borrowed::Index { span, ..x })),
+                    map(spanned(input, attribute_decl(ident_chars)), |(span, a)| Decl::Attribute(borrowed::Attribute { span, ..a })),
+                )),
+                blank_or_comment,
+            )),
+            char('}'),
+        )(i)
+    }
+}
 
-    let header_options = match HeaderOptions::from_hashmap(&opts) {
-        Ok(o) => o,
-        Err(e) => return Err(nom::Err::Error(ErdParseError::InvalidOption(e))),
-    };
+fn attribute_decl<'a>(ident_chars: &str) -> impl FnMut(&'a str) -> IResult<&'a str, borrowed::Attribute<'a>, ErdParseError<&'a str>> + '_ {
+    move |i: &'a str| {
+        let (i, key_types) = many0(one_of("*+ \t"))(i)?;
+
+        let (i, field) = ident(ident_chars)(i)?;
+        let (mut pk, mut fk) = (false, false);
+        for key_type in key_types {
+            match key_type {
+                '*' => pk = true,
+                '+' => fk = true,
+                ' ' | '\t' => {},
+                _   => panic!("unhandled key type: {:?}", key_type)
+            }
+        }
 
-    Ok((i, ast::Entity {
-        name: name.to_owned(),
-        attribs: Vec::new(),
-        options: entity_options,
-        header_options: header_options,
-     }))
-}
-
-fn attribute(i: &str) -> IResult<&str, ast::Attribute, ErdParseError<&str>> {
-    let (i, key_types) = many0(one_of("*+ \t"))(i)?;
-
-    let (i, field) = ident(i)?;
-    let mut attr = ast::Attribute::with_field(field);
-    for key_type in key_types {
-        match key_type {
-            '*' => attr.pk = true,
-            '+' => attr.fk = true,
-            ' ' | '\t' => {},
-            _   => panic!("unhandled key type: {:?}", key_type)
+        // A foreign key's target can follow an arrow, e.g.
+        // `+customer_id -> customers.id`, ahead of any other options. When
+        // dots are allowed in identifiers, the last one separates the field.
+        let (i, references) = opt(preceded(
+            tag("->"),
+            map_opt(
+                pair(ident(ident_chars), opt(preceded(char('.'), ident(ident_chars)))),
+                |(entity, field)| match field {
+                    Some(field) => Some(format!("{}.{}", entity, field)),
+                    None if entity.contains('.') => Some(entity.into_owned()),
+                    None => None,
+                },
+            ),
+        ))(i)?;
+        let (i, mut options) = trailing_options(i)?;
+        if let Some(reference) = references {
+            options.insert(0, (ast::OPT_REFERENCES, Cow::Owned(reference)));
         }
+        Ok((i, borrowed::Attribute { field, pk, fk, options, span: ast::Span::default() }))
     }
+}
 
-    let (i, opts) = trailing_options(i)?;
+/// Parses an `index {columns: "..."}` line. Without a `columns` option, the
+/// line is left to be parsed as an attribute called `index`.
+fn index_decl(i: &str) -> IResult<&str, borrowed::Index<'_>, ErdParseError<&str>> {
+    let (rest, _) = preceded(space0, tag("index"))(i)?;
+    let (rest, options) = trailing_options(rest)?;
+    if !options.iter().any(|(k, _)| ast::canonical_key(k) == ast::OPT_COLUMNS) {
+        return Err(nom::Err::Error(ErdParseError::Nom(i, ErrorKind::Tag)));
+    }
+    Ok((rest, borrowed::Index { options, span: ast::Span::default() }))
+}
 
-    let options = match ast::AttributeOptions::from_hashmap(&opts) {
-        Ok(o) => o,
-        Err(e) => return Err(nom::Err::Error(ErdParseError::InvalidOption(e))),
-    };
+fn relation_decl<'a>(ident_chars: &str) -> impl FnMut(&'a str) -> IResult<&'a str, borrowed::Relation<'a>, ErdParseError<&'a str>> + '_ {
+    move |i: &'a str| {
+        let (i, name) = opt(delimited(terminated(tag("rel"), space1), ident(ident_chars), char(':')))(i)?;
+        let (i, entity1) = ident(ident_chars)(i)?;
+        // `==` rather than `--` marks an identifying relationship.
+        let (i, (kind, card1, identifying, card2)) = alt((
+            map(tag("^--"), |_| (ast::RelationKind::Inheritance, ast::Cardinality::One, false, ast::Cardinality::One)),
+            map(
+                tuple((cardinality, alt((tag("--"), tag("=="))), cardinality)),
+                |(card1, op, card2)| (ast::RelationKind::Association, card1, op == "==", card2),
+            ),
+        ))(i)?;
+        let (i, entity2) = ident(ident_chars)(i)?;
+        // A label can be given after a colon, e.g. `a 1--* b : "owns"`, ahead
+        // of any other options.
+        let (i, label) = opt(preceded(terminated(char(':'), space0), quoted))(i)?;
+        let (i, mut options) = trailing_options(i)?;
+        if let Some(label) = label {
+            options.insert(0, (ast::OPT_LABEL, label));
+        }
+        if identifying {
+            options.insert(0, (ast::OPT_IDENTIFYING, Cow::Borrowed("true")));
+        }
 
-    attr.options = options;
-    Ok((i, attr))
+        let rel = borrowed::Relation {
+            name,
+            entity1,
+            entity2,
+            card1,
+            card2,
+            kind,
+            options,
+            span: ast::Span::default(),
+        };
+        Ok((i, rel))
+    }
 }
 
-fn relation(i: &str) -> IResult<&str, ast::Relation, ErdParseError<&str>> {
-    let (i, entity1) = ident(i)?;
-    let (i, (card1, card2)) = separated_pair(
-        cardinality,
-        tag("--"),
-        cardinality,
-    )(i)?;
-    let (i, entity2) = ident(i)?;
-    let (i, opts) = trailing_options(i)?;
+/// Parses a relationship among several entities, e.g.
+/// `supplies(supplier, part, project)`. At least two entities are needed.
+fn nary_relation_decl<'a>(ident_chars: &str) -> impl FnMut(&'a str) -> IResult<&'a str, borrowed::NaryRelation<'a>, ErdParseError<&'a str>> + '_ {
+    move |i: &'a str| {
+        let (i, name) = ident(ident_chars)(i)?;
+        let (i, entities) = delimited(
+            char('('),
+            separated_list1(char(','), ident(ident_chars)),
+            char(')'),
+        )(i)?;
+        if entities.len() < 2 {
+            return Err(nom::Err::Error(ErdParseError::Nom(i, ErrorKind::SeparatedList)));
+        }
+        let (i, options) = trailing_options(i)?;
+
+        Ok((i, borrowed::NaryRelation {
+            name,
+            entities,
+            options,
+            span: ast::Span::default(),
+        }))
+    }
+}
 
-    let options = match ast::RelationshipOptions::from_hashmap(&opts) {
-        Ok(o) => o,
-        Err(e) => return Err(nom::Err::Error(ErdParseError::InvalidOption(e))),
-    };
+/// Parses an enumerated type, e.g. `enum status {values: "active, archived"}`.
+fn enum_decl<'a>(ident_chars: &str) -> impl FnMut(&'a str) -> IResult<&'a str, borrowed::Enum<'a>, ErdParseError<&'a str>> + '_ {
+    move |i: &'a str| {
+        let (i, name) = preceded(terminated(tag("enum"), space1), ident(ident_chars))(i)?;
+        let (i, options) = trailing_options(i)?;
+        Ok((i, borrowed::Enum { name, options, span: ast::Span::default() }))
+    }
+}
 
-    let rel = ast::Relation {
-        entity1: entity1.to_owned(), 
-        entity2: entity2.to_owned(), 
-        card1: card1.to_owned(), 
-        card2: card2.to_owned(), 
-        options,
-    };
-    Ok((i, rel))
+/// Parses a note, e.g. `note "Rows are never deleted" {attached: "orders"}`.
+fn note_decl(i: &str) -> IResult<&str, borrowed::Note<'_>, ErdParseError<&str>> {
+    let (i, text) = preceded(terminated(tag("note"), space1), quoted)(i)?;
+    let (i, options) = trailing_options(i)?;
+    Ok((i, borrowed::Note { text, options, span: ast::Span::default() }))
 }
 
 fn cardinality(i: &str) -> IResult<&str, ast::Cardinality, ErdParseError<&str>> {
+    alt((cardinality_range, cardinality_op))(i)
+}
+
+fn cardinality_op(i: &str) -> IResult<&str, ast::Cardinality, ErdParseError<&str>> {
     let (i, op) = one_of("?1*+")(i)?;
     let c = match op {
         '?' => ast::Cardinality::ZeroOne,
@@ -228,12 +1154,81 @@ fn cardinality(i: &str) -> IResult<&str, ast::Cardinality, ErdParseError<&str>>
     Ok((i, c))
 }
 
-fn global_option(i: &str) -> IResult<&str, GlobalOption, ErdParseError<&str>> {
+/// Parses an explicit cardinality, either an exact number such as `(3)` or
+/// a range such as `(0,5)`, where a maximum of `*` means there's no limit.
+fn cardinality_range(i: &str) -> IResult<&str, ast::Cardinality, ErdParseError<&str>> {
+    let (rest, (min, max)) = delimited(
+        terminated(char('('), space0),
+        pair(
+            terminated(number, space0),
+            opt(delimited(
+                terminated(char(','), space0),
+                alt((map(char('*'), |_| None), map(number, Some))),
+                space0,
+            )),
+        ),
+        char(')'),
+    )(i)?;
+
+    let max = max.unwrap_or(Some(min));
+    if let Some(max) = max.filter(|max| *max < min) {
+        let message = format!("cardinality ({},{}) has a minimum greater than its maximum", min, max);
+        return Err(nom::Err::Failure(ErdParseError::InvalidOption(ErdError::Semantic(message))));
+    }
+    Ok((rest, ast::Cardinality::Range { min, max }))
+}
+
+fn number(i: &str) -> IResult<&str, u32, ErdParseError<&str>> {
+    let (rest, digits) = digit1(i)?;
+    match digits.parse() {
+        Ok(n) => Ok((rest, n)),
+        Err(_) => Err(nom::Err::Error(ErdParseError::Nom(i, ErrorKind::Digit))),
+    }
+}
+
+/// Parses an `include "path"` declaration, returning the path.
+fn include(i: &str) -> IResult<&str, Cow<'_, str>, ErdParseError<&str>> {
+    delimited(terminated(tag("include"), space1), quoted, space0)(i)
+}
+
+/// Parses a `set name = "value"` declaration, returning the variable's name
+/// and value.
+fn set(i: &str) -> IResult<&str, (&str, Cow<'_, str>), ErdParseError<&str>> {
+    delimited(
+        terminated(tag("set"), space1),
+        separated_pair(variable_name, delimited(space0, char('='), space0), quoted),
+        space0,
+    )(i)
+}
+
+fn variable_name(i: &str) -> IResult<&str, &str, ErdParseError<&str>> {
+    recognize(pair(
+        take_while1(|c: char| c.is_ascii_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+    ))(i)
+}
+
+fn directive(i: &str) -> IResult<&str, borrowed::Directive<'_>, ErdParseError<&str>> {
+    let (i, option_type) = directive_type(i)?;
+    let (i, options) = trailing_options(i)?;
+    Ok((i, borrowed::Directive { option_type, options, span: ast::Span::default() }))
+}
+
+/// Parses a directive after the first declaration, where its options must be
+/// given in braces so that it isn't mistaken for an attribute.
+fn directive_block(i: &str) -> IResult<&str, borrowed::Directive<'_>, ErdParseError<&str>> {
+    let (i, option_type) = terminated(directive_type, space0)(i)?;
+    let (i, options) = terminated(options, space0)(i)?;
+    Ok((i, borrowed::Directive { option_type, options, span: ast::Span::default() }))
+}
+
+fn directive_type(i: &str) -> IResult<&str, GlobalOptionType, ErdParseError<&str>> {
     let (i, name) = alt((
         tag("title"),
         tag("header"),
         tag("entity"),
         tag("relationship"),
+        tag("graph"),
     ))(i)?;
 
     let option_type = match name {
@@ -241,32 +1236,30 @@ fn global_option(i: &str) -> IResult<&str, GlobalOption, ErdParseError<&str>> {
         "header" => GlobalOptionType::Header,
         "entity" => GlobalOptionType::Entity,
         "relationship" => GlobalOptionType::Relationship,
+        "graph" => GlobalOptionType::Graph,
         _ => panic!("unhandled global optional type"),
     };
-
-    let (i, options) = trailing_options(i)?;
-    Ok((i, GlobalOption { option_type, options }))
+    Ok((i, option_type))
 }
 
-fn option(i: &str) -> IResult<&str, (&str, &str), ErdParseError<&str>> {
+fn option(i: &str) -> IResult<&str, (&str, Cow<'_, str>), ErdParseError<&str>> {
     separated_pair(
-        alphanumeric1, 
+        option_key,
         delimited(space0, char(':'), space0),
         quoted
     )(i)
 }
 
-fn trailing_options(i: &str) ->IResult<&str, HashMap<String, String>, ErdParseError<&str>> {
+fn option_key(i: &str) -> IResult<&str, &str, ErdParseError<&str>> {
+    take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_')(i)
+}
+
+fn trailing_options(i: &str) -> IResult<&str, borrowed::Options<'_>, ErdParseError<&str>> {
     let (i, opts) = delimited(multispace0, opt(options), space0)(i)?;
-    let opts: HashMap<String, String> = if let Some(o) = opts {
-        o.into_iter().map(|(k, v)| (k.to_owned(), v.to_owned())).collect()
-    } else {
-        HashMap::new()
-    };
-    Ok((i, opts))
+    Ok((i, opts.unwrap_or_default()))
 }
 
-fn options(i: &str) -> IResult<&str, Vec<(&str, &str)>, ErdParseError<&str>> {
+fn options(i: &str) -> IResult<&str, borrowed::Options<'_>, ErdParseError<&str>> {
     delimited(
         preceded(char('{'), multispace0),
 
@@ -290,37 +1283,124 @@ fn options(i: &str) -> IResult<&str, Vec<(&str, &str)>, ErdParseError<&str>> {
     )(i)
 }
 
-fn quoted(i: &str) -> IResult<&str, &str, ErdParseError<&str>> {
-    delimited(char('"'), is_not("\""), char('"'))(i)
+/// Parses a double quoted string, such as an option value.
+fn quoted(i: &str) -> IResult<&str, Cow<'_, str>, ErdParseError<&str>> {
+    quoted_by('"', |_| true)(i)
 }
 
-fn ident(i: &str) -> IResult<&str, &str, ErdParseError<&str>> {
-    let (i, id) = delimited(space0, alt((
-        ident_quoted,
-        ident_no_space,
-    )), space0)(i)?;
-    Ok((i, id))
+/// Parses a string delimited by `quote`, made up of characters allowed by
+/// `allowed`. Within it, a backslash escapes `quote` or another backslash,
+/// and `\n` is a newline. Any other backslash is kept as written.
+///
+/// The result is only copied from the input if it contains an escape.
+fn quoted_by<'a>(quote: char, allowed: impl Fn(char) -> bool) -> impl FnMut(&'a str) -> IResult<&'a str, Cow<'a, str>, ErdParseError<&'a str>> {
+    move |i: &'a str| {
+        let (body, _) = char(quote)(i)?;
+        let mut escaped = false;
+        let mut chars = body.char_indices();
+        while let Some((p, c)) = chars.next() {
+            if c == quote {
+                let s = &body[..p];
+                let value = if escaped { Cow::Owned(unescape(s, quote)) } else { Cow::Borrowed(s) };
+                return Ok((&body[p + 1..], value));
+            }
+            if !allowed(c) {
+                break;
+            }
+            if c == '\\' {
+                match chars.next() {
+                    Some((_, c)) if c == quote || c == '\\' || c == 'n' => escaped = true,
+                    Some((_, c)) if allowed(c) => {},
+                    _ => break,
+                }
+            }
+        }
+        Err(nom::Err::Error(ErdParseError::Nom(i, ErrorKind::Char)))
+    }
 }
 
-fn ident_quoted(i: &str) -> IResult<&str, &str, ErdParseError<&str>> {
-    let (i, id) = alt((
-        delimited(char('"'), take_while(|c: char| !c.is_control() && c != '"'), char('"')),
-        delimited(char('\''), take_while(|c: char| !c.is_control() && c != '\''), char('\'')),
-        delimited(char('`'), take_while(|c: char| !c.is_control() && c != '`'), char('`')),
-    ))(i)?;
-    Ok((i, id))
+/// Replaces the escapes in a string delimited by `quote`, as described for
+/// [`quoted_by`].
+fn unescape(s: &str, quote: char) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(c) if c == quote || c == '\\' => out.push(c),
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            },
+            None => out.push('\\'),
+        }
+    }
+    out
 }
 
-fn ident_no_space(i: &str) -> IResult<&str, &str, ErdParseError<&str>> {
-    take_while1(|c| is_alphanumeric(c as u8) || c == '_')(i)
+fn ident<'a>(ident_chars: &str) -> impl FnMut(&'a str) -> IResult<&'a str, Cow<'a, str>, ErdParseError<&'a str>> + '_ {
+    move |i: &'a str| {
+        delimited(space0, alt((
+            ident_quoted,
+            map(ident_no_space(ident_chars), Cow::Borrowed),
+        )), space0)(i)
+    }
+}
+
+fn ident_quoted(i: &str) -> IResult<&str, Cow<'_, str>, ErdParseError<&str>> {
+    let allowed = |c: char| !c.is_control();
+    alt((
+        quoted_by('"', allowed),
+        quoted_by('\'', allowed),
+        quoted_by('`', allowed),
+    ))(i)
+}
+
+/// Parses an unquoted identifier, made up of letters, digits, `_` and any of
+/// `ident_chars`.
+fn ident_no_space<'a>(ident_chars: &str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, ErdParseError<&'a str>> + '_ {
+    move |i: &'a str| take_while1(|c: char| c.is_alphanumeric() || c == '_' || ident_chars.contains(c))(i)
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ErdParseError<I> {
-    InvalidOption(String),
+    InvalidOption(ErdError),
     Nom(I, ErrorKind),
 }
 
+impl ErdParseError<&str> {
+    /// Converts into an `ErdError`, using the original input to work out
+    /// where in the source the error occurred.
+    fn into_erd_error(self, input: &str) -> ErdError {
+        match self {
+            ErdParseError::InvalidOption(e) => e,
+            ErdParseError::Nom(rest, _) => {
+                let found = rest.lines().next().unwrap_or("").trim();
+                parse_error(input, rest, format!("unexpected input: {}", found))
+            },
+        }
+    }
+}
+
+/// Builds a parse error located at the start of `rest`, which must be a
+/// suffix of `input`.
+fn parse_error(input: &str, rest: &str, message: String) -> ErdError {
+    let (line, column) = line_column(input, input.len() - rest.len());
+    ErdError::Parse { line, column, message }
+}
+
+/// Returns the 1-based line and byte column of an offset into `input`.
+pub(crate) fn line_column(input: &str, offset: usize) -> (usize, usize) {
+    let consumed = &input[..offset];
+    let line = consumed.matches('\n').count() + 1;
+    let column = consumed.len() - consumed.rfind('\n').map(|p| p + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
 impl<I> nom::error::ParseError<I> for ErdParseError<I> {
     fn from_error_kind(input: I, kind: ErrorKind) -> Self {
         ErdParseError::Nom(input, kind)
@@ -335,9 +1415,39 @@ impl<I> nom::error::ParseError<I> for ErdParseError<I> {
 mod tests {
     use std::include_str;
 
-    use maplit::hashmap;
+    use indexmap::indexmap;
 
     use super::*;
+    use crate::ast::{EntityOptions, HeaderOptions};
+
+    /// Converts an option resolution error into a parse failure.
+    fn resolve<T>(r: Result<T, ErdError>) -> Result<T, nom::Err<ErdParseError<&'static str>>> {
+        r.map_err(|e| nom::Err::Failure(ErdParseError::InvalidOption(e)))
+    }
+
+    fn entity(i: &str) -> IResult<&str, ast::Entity, ErdParseError<&str>> {
+        let (i, (e, _)) = entity_decl(i, "")(i)?;
+        Ok((i, resolve(e.into_owned())?))
+    }
+
+    fn attribute(i: &str) -> IResult<&str, ast::Attribute, ErdParseError<&str>> {
+        let (i, a) = attribute_decl("")(i)?;
+        Ok((i, resolve(a.into_owned())?))
+    }
+
+    fn relation(i: &str) -> IResult<&str, ast::Relation, ErdParseError<&str>> {
+        let (i, r) = relation_decl("")(i)?;
+        Ok((i, resolve(r.into_owned())?))
+    }
+
+    fn nary_relation(i: &str) -> IResult<&str, ast::NaryRelation, ErdParseError<&str>> {
+        let (i, r) = nary_relation_decl("")(i)?;
+        Ok((i, resolve(r.into_owned())?))
+    }
+
+    fn global_option(i: &str) -> IResult<&str, GlobalOption, ErdParseError<&str>> {
+        map(directive, borrowed::Directive::into_owned)(i)
+    }
 
     #[test]
     fn test_parse_empty() {
@@ -371,6 +1481,299 @@ mod tests {
         assert_eq!(e.relationships.len(), 13);
     }
 
+    #[test]
+    fn test_parse_error_location() {
+        let err = parse_erd("[a]\n*id\n[b\n").unwrap_err();
+        assert_eq!(err, ErdError::Parse {
+            line: 3,
+            column: 1,
+            message: "unexpected input: [b".to_owned(),
+        });
+    }
+
+    #[test]
+    fn test_parse_invalid_option() {
+        let err = parse_erd("[a]\n*id {size: \"1\"}\n").unwrap_err();
+        assert_eq!(err, ErdError::UnknownOption { scope: "attribute", key: "size".to_owned() });
+
+        let err = parse_erd("[a] {border: \"thick\"}\n").unwrap_err();
+        assert_eq!(err, ErdError::InvalidOptionValue {
+            key: "border".to_owned(),
+            value: "thick".to_owned(),
+            expected: "integer",
+        });
+    }
+
+    #[test]
+    fn test_parse_option_order() {
+        // The first invalid option in declaration order is the one reported.
+        let err = parse_erd("[a] {zz: \"1\", size: \"x\", aa: \"2\"}\n").unwrap_err();
+        assert_eq!(err, ErdError::UnknownOption { scope: "entity", key: "zz".to_owned() });
+
+        let erd = parse_erd("entity {color: \"red\"}\nentity {color: \"blue\"}\n[a]\n").unwrap();
+        assert_eq!(erd.entities[0].options.color.as_deref(), Some("blue"));
+    }
+
+    #[test]
+    fn test_parse_spans() {
+        let s = "title {}\n\n[a] {color: \"red\"}\n  *id\nname   # comment\na 1--* a\n";
+        let spans = |erd: &ast::Erd| {
+            let e = &erd.entities[0];
+            vec![e.span, e.attribs[0].span, e.attribs[1].span, erd.relationships[0].span]
+        };
+        let expected = vec![
+            ("[a] {color: \"red\"}", 3, 1),
+            ("*id", 4, 3),
+            ("name", 5, 1),
+            ("a 1--* a", 6, 1),
+        ];
+
+        for erd in [parse_erd(s).unwrap(), parse_erd_recover(s).0].iter() {
+            let actual: Vec<_> = spans(erd).into_iter()
+                .map(|sp| (&s[sp.start..sp.end], sp.line, sp.column))
+                .collect();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_with_directives() {
+        let options = ParseOptions {
+            directives: vec![GlobalOption {
+                option_type: GlobalOptionType::Entity,
+                options: indexmap!{
+                    "color".to_owned() => "red".to_owned(),
+                    "font".to_owned() => "Courier".to_owned(),
+                },
+                span: ast::Span::default(),
+            }],
+            ..Default::default()
+        };
+        let s = "entity {color: \"blue\"}\n[a]\n";
+
+        let erd = parse_erd_with(s, &options).unwrap();
+        assert_eq!(erd.entities[0].options.color.as_deref(), Some("blue"));
+        assert_eq!(erd.entities[0].options.font.as_deref(), Some("Courier"));
+        assert_eq!(parse_erd_recover_with(s, &options), (erd, vec![]));
+    }
+
+    #[test]
+    fn test_parse_includes() {
+        let dir = std::env::temp_dir().join(format!("erd-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::write(dir.join("shared/style.er"), "entity {bgcolor: \"blue\"}\n").unwrap();
+        fs::write(dir.join("shared/common.er"), "include \"style.er\"\n[user]\n*id\n").unwrap();
+        fs::write(dir.join("cycle.er"), "include \"main.er\"\n").unwrap();
+        let main = "include \"shared/common.er\"\n[post]\n*id\n+user_id\npost *--1 user\n";
+        fs::write(dir.join("main.er"), main).unwrap();
+        let options = ParseOptions { path: Some(dir.join("main.er")), ..Default::default() };
+
+        let erd = parse_erd_with(main, &options).unwrap();
+        let names: Vec<_> = erd.entities.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["user", "post"]);
+        assert_eq!(erd.entities[1].options.background_color.as_deref(), Some("blue"));
+        assert_eq!(erd.entities[0].span.line, 0);
+        assert_eq!(erd.entities[1].span.line, 2);
+        assert!(erd.includes.is_empty());
+        assert_eq!(parse_erd_recover_with(main, &options), (erd, vec![]));
+
+        // Without a path, includes are kept as written.
+        let erd = parse_erd(main).unwrap();
+        assert_eq!(erd.includes, vec!["shared/common.er"]);
+        assert_eq!(erd.entities.len(), 1);
+        assert!(crate::format_erd(&erd).starts_with("include \"shared/common.er\"\n\n[post]\n"));
+
+        for (s, message) in &[
+            ("include \"missing.er\"\n", "No such file or directory"),
+            ("include \"cycle.er\"\n", "main.er: file includes itself"),
+        ] {
+            let err = parse_erd_with(s, &options).unwrap_err();
+            assert!(matches!(err, ErdError::Include { .. }), "{}", s);
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_ident_chars() {
+        let s = "[Straße]\n*größe\n[auth.users]\n*id\nStraße 1--* auth.users\n";
+        assert!(parse_erd(s).is_err());
+
+        let options = ParseOptions { ident_chars: ".".to_owned(), ..Default::default() };
+        let erd = parse_erd_with(s, &options).unwrap();
+        assert_eq!(erd.entities[0].name, "Straße");
+        assert_eq!(erd.entities[0].attribs[0].field, "größe");
+        assert_eq!(erd.relationships[0].entity2, "auth.users");
+        assert_eq!(parse_erd_recover_with(s, &options), (erd.clone(), vec![]));
+        assert!(crate::format_erd(&erd).starts_with("[Straße]\n  *größe\n\n[\"auth.users\"]\n"));
+
+        let s = "[auth.users]\n*id\n[legacy-orders]\n+user$id -> auth.users.id\n+b -> x.y\n";
+        let options = ParseOptions { ident_chars: ".-$".to_owned(), ..Default::default() };
+        let erd = parse_erd_with(s, &options).unwrap();
+        let a = &erd.entities[1].attribs[0];
+        assert_eq!((erd.entities[1].name.as_str(), a.field.as_str()), ("legacy-orders", "user$id"));
+        assert_eq!(a.references, Some(ast::Reference { entity: "auth.users".to_owned(), field: "id".to_owned() }));
+        assert_eq!(erd.entities[1].attribs[1].references.as_ref().unwrap().entity, "x");
+
+        assert_eq!(invalid_ident_char(".-$"), None);
+        assert_eq!(invalid_ident_char(".{"), Some('{'));
+        assert_eq!(invalid_ident_char("- "), Some(' '));
+    }
+
+    #[test]
+    fn test_parse_duplicate_entities() {
+        let s = "[a]\nx\n[b]\ny\n[a]\nz\nw\n";
+        let erd = parse_erd(s).unwrap();
+        assert_eq!(erd.entities.len(), 3);
+        assert_eq!(erd.entities[2].attribs.len(), 2);
+
+        let options = ParseOptions { duplicate_entities: DuplicateEntityPolicy::Error, ..Default::default() };
+        let err = parse_erd_with(s, &options).unwrap_err();
+        assert_eq!(err, ErdError::DuplicateEntity { name: "a".to_owned(), first_line: 1, line: 5 });
+        assert_eq!(err.to_string(), "entity 'a' is declared more than once, on line 1 and line 5");
+        assert_eq!(parse_erd_recover_with(s, &options).1, vec![err]);
+
+        let options = ParseOptions { duplicate_entities: DuplicateEntityPolicy::Merge, ..Default::default() };
+        let erd = parse_erd_with(s, &options).unwrap();
+        let fields: Vec<_> = erd.entities[0].attribs.iter().map(|a| a.field.as_str()).collect();
+        assert_eq!(fields, vec!["x", "z", "w"]);
+        assert_eq!(erd.entities[1].attribs.len(), 1);
+        assert_eq!(parse_erd_recover_with(s, &options), (erd, vec![]));
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let s = r##"set primary = "#336699"
+title {label: "$$5 plans"}
+set size = "12"
+[a] {bgcolor: "$primary", size: "$size"} {
+  *id {label: "id ($primary)", type: "int"}
+}
+a 1--* a {color: "$primary", label: "costs $ 5"}
+"##;
+        let erd = parse_erd(s).unwrap();
+        assert_eq!(erd.title_options.label.as_deref(), Some("$5 plans"));
+        assert_eq!(erd.entities[0].options.background_color.as_deref(), Some("#336699"));
+        assert_eq!(erd.entities[0].options.size, Some(12));
+        assert_eq!(erd.entities[0].attribs[0].options.label.as_deref(), Some("id (#336699)"));
+        assert_eq!(erd.relationships[0].options.color.as_deref(), Some("#336699"));
+        assert_eq!(erd.relationships[0].options.label.as_deref(), Some("costs $ 5"));
+        assert_eq!(erd.variables, indexmap! {
+            "primary".to_owned() => "#336699".to_owned(),
+            "size".to_owned() => "12".to_owned(),
+        });
+        assert!(crate::format_erd(&erd).starts_with("set primary = \"#336699\"\nset size = \"12\"\n\ntitle"));
+        assert_eq!(parse_erd_recover(s), (erd, vec![]));
+
+        // Option values without variables are still borrowed.
+        let erd = parse_erd_borrowed(s).unwrap();
+        assert!(matches!(erd.entities[0].attribs[0].options[0].1, Cow::Owned(_)));
+        assert!(matches!(erd.entities[0].attribs[0].options[1].1, Cow::Borrowed(_)));
+
+        let err = ErdError::UndefinedVariable { name: "primary".to_owned() };
+        assert_eq!(parse_erd("[a] {color: \"$primary\"}\n").unwrap_err(), err);
+        assert_eq!(err.to_string(), "undefined variable: $primary");
+        let (erd, errors) = parse_erd_recover("[a] {color: \"$primary\"}\n[b]\n");
+        assert_eq!(erd.entities.len(), 1);
+        assert_eq!(errors, vec![err]);
+
+        // Variables must be set before any entity.
+        assert!(parse_erd("[a]\nset x = \"1\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_scoped_directives() {
+        let s = r#"entity {color: "red"}
+[a]
+entity_id
+title
+entity {color: "blue"}
+relationship {color: "green"}
+[b]
+"entity" {label: "e"}
+a 1--* b
+"#;
+        for erd in [parse_erd(s).unwrap(), parse_erd_recover(s).0].iter() {
+            let colors: Vec<_> = erd.entities.iter().map(|e| e.options.color.as_deref()).collect();
+            assert_eq!(colors, vec![Some("red"), Some("blue")]);
+            let fields: Vec<_> = erd.entities.iter()
+                .flat_map(|e| e.attribs.iter().map(|a| a.field.as_str()))
+                .collect();
+            assert_eq!(fields, vec!["entity_id", "title", "entity"]);
+            assert_eq!(erd.relationships[0].options.color.as_deref(), Some("green"));
+        }
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        let s = "entity {shadow: \"1\"}\n[a] {color: \"red\", glow: \"x\"}\n*id {width: \"2\"}\nindex {columns: \"id\", kind: \"btree\"}\na 1--* a {weight: \"3\"}\n";
+        assert_eq!(parse_erd(s), Err(ErdError::UnknownOption { scope: "entity", key: "glow".to_owned() }));
+
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        for erd in [parse_erd_with(s, &options).unwrap(), parse_erd_recover_with(s, &options).0].iter() {
+            assert_eq!(erd.entities[0].options.color.as_deref(), Some("red"));
+            assert_eq!(erd.entities[0].indexes.len(), 1);
+            assert_eq!(erd.relationships.len(), 1);
+            let ignored: Vec<_> = erd.ignored_options.iter()
+                .map(|o| (o.scope, o.key.as_str(), o.span.line))
+                .collect();
+            assert_eq!(ignored, vec![
+                ("entity", "glow", 2),
+                ("attribute", "width", 3),
+                ("index", "kind", 4),
+                ("relationship", "weight", 5),
+                ("entity", "shadow", 1),
+            ]);
+        }
+
+        let err = parse_erd_with("[a] {size: \"big\", glow: \"x\"}\n", &options).unwrap_err();
+        assert_eq!(err, ErdError::invalid_integer("size", "big"));
+    }
+
+    #[test]
+    fn test_parse_attribute_without_entity() {
+        let err = parse_erd("title {}\n*id\n").unwrap_err();
+        assert!(matches!(err, ErdError::Semantic(_)));
+    }
+
+    #[test]
+    fn test_parse_recover() {
+        let s = r##"title {label: "t"}
+[a]
+*id
+[b
+*name {size: "1"}
+[c] {border: "x"}
+c 1--* a
+[d] {color: "#ff0000",
+  nonsense: "1" }
+d 1--? a
+"##;
+        let (erd, errors) = parse_erd_recover(s);
+        assert_eq!(erd.title_options.label, Some("t".to_owned()));
+        assert_eq!(
+            erd.entities.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(),
+            vec!["a"],
+        );
+        assert_eq!(erd.entities[0].attribs.len(), 1);
+        assert_eq!(erd.relationships.len(), 2);
+        assert_eq!(errors, vec![
+            ErdError::Parse { line: 4, column: 1, message: "unexpected input: [b".to_owned() },
+            ErdError::UnknownOption { scope: "attribute", key: "size".to_owned() },
+            ErdError::InvalidOptionValue { key: "border".to_owned(), value: "x".to_owned(), expected: "integer" },
+            ErdError::UnknownOption { scope: "entity", key: "nonsense".to_owned() },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_recover_no_errors() {
+        let s = include_str!("../examples/nfldb.er");
+        let (erd, errors) = parse_erd_recover(s);
+        assert!(errors.is_empty());
+        assert_eq!(erd, parse_erd(s).unwrap());
+    }
+
     #[test]
     fn test_blank_or_comment_empty() {
         blank_or_comment("").unwrap();
@@ -415,7 +1818,7 @@ mod tests {
         let (i, e) = entity("[foo] {color: \"#1234AA\"}").unwrap();
         assert!(i.is_empty());
         let mut expected = new_entity("foo");
-        let o = &hashmap!{"color".to_owned() => "#1234AA".to_owned()};
+        let o = &indexmap!{"color".to_owned() => "#1234AA".to_owned()};
         expected.options = EntityOptions::from_hashmap(o).unwrap();
         expected.header_options = HeaderOptions::from_hashmap(o).unwrap();
         assert_eq!(e, expected);
@@ -426,7 +1829,7 @@ mod tests {
         let (i, e) = entity("[`foo - bar`] {size: \"10\", font: \"Equity\"}").unwrap();
         assert!(i.is_empty());
         let mut expected = new_entity("foo - bar");
-        let o = &hashmap!{
+        let o = &indexmap!{
             "size".to_owned() => "10".to_owned(),
             "font".to_owned() => "Equity".to_owned(),
         };
@@ -446,7 +1849,7 @@ mod tests {
     fn test_attribute_pk() {
         let (i, attr) = attribute("*foo").unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             pk: true,
             ..Default::default()
         });
@@ -457,7 +1860,7 @@ mod tests {
     fn test_attribute_multiple_pk() {
         let (i, attr) = attribute("***foo").unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             pk: true,
             ..Default::default()
         });
@@ -468,7 +1871,7 @@ mod tests {
     fn test_attribute_fk() {
         let (i, attr) = attribute("+foo").unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             fk: true,
             ..Default::default()
         });
@@ -479,7 +1882,7 @@ mod tests {
     fn test_attribute_pk_fk() {
         let (i, attr) = attribute("+*foo").unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             pk: true,
             fk: true,
             ..Default::default()
@@ -491,7 +1894,7 @@ mod tests {
     fn test_attribute_multiple_pk_fk() {
         let (i, attr) = attribute("***++*foo").unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             pk: true,
             fk: true,
             ..Default::default()
@@ -503,7 +1906,7 @@ mod tests {
     fn test_attribute_whitespace() {
         let (i, attr) = attribute("  \t foo").unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             ..Default::default()
         });
         assert!(i.is_empty());
@@ -513,17 +1916,120 @@ mod tests {
     fn test_attribute_with_options() {
         let (i, attr) = attribute("*foo {label:\"b\", border : \"3\"}").unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             pk: true,
             fk: false,
-            options: ast::AttributeOptions::from_hashmap(&hashmap!{
+            unique: false,
+            nullable: false,
+            datatype: None,
+            description: None,
+            references: None,
+            options: ast::AttributeOptions::from_hashmap(&indexmap!{
                 "label".to_owned() => "b".to_owned(),
                 "border".to_owned() => "3".to_owned(),
             }).unwrap(),
+            span: ast::Span::default(),
         });
         assert!(i.is_empty());
     }
 
+    #[test]
+    fn test_attribute_with_type() {
+        let (i, attr) = attribute("*foo {type: \"varchar(255)\", label: \"b\"}").unwrap();
+        assert_eq!(attr, ast::Attribute {
+            field: "foo".into(),
+            pk: true,
+            fk: false,
+            unique: false,
+            nullable: false,
+            datatype: Some("varchar(255)".to_owned()),
+            description: None,
+            references: None,
+            options: ast::AttributeOptions::from_hashmap(&indexmap!{
+                "label".to_owned() => "b".to_owned(),
+            }).unwrap(),
+            span: ast::Span::default(),
+        });
+        assert!(i.is_empty());
+    }
+
+    #[test]
+    fn test_attribute_unique() {
+        let (_, attr) = attribute("email {unique: \"true\"}").unwrap();
+        assert!(attr.unique);
+        assert_eq!(attr.options, ast::AttributeOptions::default());
+
+        let (_, attr) = attribute("email {unique: \"false\"}").unwrap();
+        assert!(!attr.unique);
+
+        let err = parse_erd("[a]\nemail {unique: \"yes\"}\n").unwrap_err();
+        assert_eq!(err, ErdError::InvalidOptionValue {
+            key: "unique".to_owned(),
+            value: "yes".to_owned(),
+            expected: "true or false",
+        });
+    }
+
+    #[test]
+    fn test_graph_directive() {
+        let erd = parse_erd("[a]\ngraph {rankdir: \"tb\", splines: \"ortho\"}\n[b]\n").unwrap();
+        assert_eq!(erd.graph_options, ast::GraphOptions {
+            rankdir: Some("TB".to_owned()),
+            splines: Some("ortho".to_owned()),
+            layout: None,
+            background_color: None,
+            attributes: None,
+        });
+        assert_eq!(erd.entities.len(), 2);
+
+        assert_eq!(parse_erd("graph {splines: \"wiggly\"}\n").unwrap_err(), ErdError::InvalidOptionValue {
+            key: "splines".to_owned(),
+            value: "wiggly".to_owned(),
+            expected: "spline, ortho, polyline, line, curved, compound or none",
+        });
+        assert_eq!(parse_erd("graph {rankdir: \"up\"}\n").unwrap_err(), ErdError::InvalidOptionValue {
+            key: "rankdir".to_owned(),
+            value: "up".to_owned(),
+            expected: "LR, TB, RL or BT",
+        });
+        assert!(parse_erd("graph {layout: \"graphviz\"}\n").is_err());
+        assert_eq!(parse_erd("graph {attributes: \"keys\"}\n").unwrap().graph_options.attributes, Some(ast::AttributeFilter::Keys));
+        assert!(parse_erd("graph {attributes: \"some\"}\n").is_err());
+        assert!(parse_erd("graph {color: \"red\"}\n").is_err());
+    }
+
+    #[test]
+    fn test_attribute_text_alignment() {
+        let (_, attr) = attribute("total {text-alignment: \"right\"}").unwrap();
+        assert_eq!(attr.options.text_alignment, "RIGHT");
+
+        let err = parse_erd("[a]\ntotal {text-alignment: \"justify\"}\n").unwrap_err();
+        assert_eq!(err, ErdError::InvalidOptionValue {
+            key: "text-alignment".to_owned(),
+            value: "justify".to_owned(),
+            expected: "left, center or right",
+        });
+    }
+
+    #[test]
+    fn test_index() {
+        let s = "[a]\nid\nindex {columns: \"x, y\", unique: \"true\"}\nindex\nindex_no {label: \"n\"}\n";
+        let erd = parse_erd(s).unwrap();
+        let fields: Vec<_> = erd.entities[0].attribs.iter().map(|a| a.field.as_str()).collect();
+        assert_eq!(fields, vec!["id", "index", "index_no"]);
+        assert_eq!(erd.entities[0].indexes, vec![ast::Index {
+            columns: vec!["x".to_owned(), "y".to_owned()],
+            unique: true,
+            span: ast::Span::default(),
+        }]);
+        assert_eq!(erd.entities[0].indexes[0].span.line, 3);
+        assert_eq!(parse_erd_recover(s).0, erd);
+
+        let err = parse_erd("[a]\nindex {columns: \"x\", label: \"l\"}\n").unwrap_err();
+        assert_eq!(err, ErdError::UnknownOption { scope: "index", key: "label".to_owned() });
+        assert!(parse_erd("index {columns: \"x\"}\n").is_err());
+    }
+
     #[test]
     fn test_attribute_with_multiline_options() {
         let (i, attr) = attribute(r#"*foo {
@@ -531,13 +2037,19 @@ mod tests {
             border : "3"
         }"#).unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             pk: true,
             fk: false,
-            options: ast::AttributeOptions::from_hashmap(&hashmap!{
+            unique: false,
+            nullable: false,
+            datatype: None,
+            description: None,
+            references: None,
+            options: ast::AttributeOptions::from_hashmap(&indexmap!{
                 "label".to_owned() => "b".to_owned(),
                 "border".to_owned() => "3".to_owned(),
             }).unwrap(),
+            span: ast::Span::default(),
         });
         assert!(i.is_empty());
     }
@@ -549,27 +2061,54 @@ mod tests {
             border : "3", # comment
         }"#).unwrap();
         assert_eq!(attr, ast::Attribute {
-            field: "foo".to_owned(),
+            field: "foo".into(),
             pk: true,
             fk: false,
-            options: ast::AttributeOptions::from_hashmap(&hashmap!{
+            unique: false,
+            nullable: false,
+            datatype: None,
+            description: None,
+            references: None,
+            options: ast::AttributeOptions::from_hashmap(&indexmap!{
                 "label".to_owned() => "b".to_owned(),
                 "border".to_owned() => "3".to_owned(),
             }).unwrap(),
+            span: ast::Span::default(),
         });
         assert!(i.is_empty());
     }
 
+    #[test]
+    fn test_attribute_references() {
+        let (i, attr) = attribute("+customer_id -> customers.id {type: \"int\"}").unwrap();
+        assert!(i.is_empty());
+        assert!(attr.fk);
+        assert_eq!(attr.datatype.as_deref(), Some("int"));
+        assert_eq!(attr.references, Some(ast::Reference { entity: "customers".to_owned(), field: "id".to_owned() }));
+
+        let (_, attr) = attribute("owner->'app user'.id").unwrap();
+        assert!(attr.fk);
+        assert_eq!(attr.references, Some(ast::Reference { entity: "app user".to_owned(), field: "id".to_owned() }));
+
+        assert!(parse_erd("[a]\nb -> c\n").is_err());
+        assert!(parse_erd("[a]\nb {references: \"c\"}\n").is_err());
+    }
+
     #[test]
     fn test_relation_one_oneplus() {
         let (i, rel) = relation("E1 1--+ E2").unwrap();
         assert!(i.is_empty());
         assert_eq!(rel, ast::Relation {
-            entity1: "E1".to_owned(),
-            entity2: "E2".to_owned(),
+            name: None,
+            entity1: "E1".into(),
+            entity2: "E2".into(),
             card1: ast::Cardinality::One,
             card2: ast::Cardinality::OnePlus,
+            kind: ast::RelationKind::Association,
+            identifying: false,
+            attribs: Vec::new(),
             options: ast::RelationshipOptions::default(),
+            span: ast::Span::default(),
         });
     }
 
@@ -578,11 +2117,16 @@ mod tests {
         let (i, rel) = relation("`Entity 1` *--? 'Entity 2'").unwrap();
         assert!(i.is_empty());
         assert_eq!(rel, ast::Relation {
-            entity1: "Entity 1".to_owned(),
-            entity2: "Entity 2".to_owned(),
+            name: None,
+            entity1: "Entity 1".into(),
+            entity2: "Entity 2".into(),
             card1: ast::Cardinality::ZeroPlus,
             card2: ast::Cardinality::ZeroOne,
+            kind: ast::RelationKind::Association,
+            identifying: false,
+            attribs: Vec::new(),
             options: ast::RelationshipOptions::default(),
+            span: ast::Span::default(),
         });
     }
 
@@ -591,26 +2135,222 @@ mod tests {
         let (i, rel) = relation(r##"E1 1--1 E2 {color:"#000000", size: "1"}"##).unwrap();
         assert!(i.is_empty());
         assert_eq!(rel, ast::Relation {
-            entity1: "E1".to_owned(),
-            entity2: "E2".to_owned(),
+            name: None,
+            entity1: "E1".into(),
+            entity2: "E2".into(),
             card1: ast::Cardinality::One,
             card2: ast::Cardinality::One,
-            options: ast::RelationshipOptions::from_hashmap(&hashmap!{
+            kind: ast::RelationKind::Association,
+            identifying: false,
+            attribs: Vec::new(),
+            options: ast::RelationshipOptions::from_hashmap(&indexmap!{
                 "color".to_owned() => "#000000".to_owned(),
                 "size".to_owned() => "1".to_owned(),
             }).unwrap(),
+            span: ast::Span::default(),
         });
     }
 
+    #[test]
+    fn test_relation_ranges() {
+        let (_, rel) = relation("orders (0,5)--1 customers").unwrap();
+        assert_eq!(rel.card1, ast::Cardinality::Range { min: 0, max: Some(5) });
+        assert_eq!(rel.card2, ast::Cardinality::One);
+
+        let (_, rel) = relation("a ( 2 , * )--(3) b").unwrap();
+        assert_eq!(rel.card1, ast::Cardinality::Range { min: 2, max: None });
+        assert_eq!(rel.card2, ast::Cardinality::Range { min: 3, max: Some(3) });
+        assert_eq!(rel.card1.to_string(), "(2,*)");
+        assert_eq!(rel.card2.to_string(), "(3)");
+
+        assert_eq!(parse_erd("[a]\na (5,2)--1 a\n"), Err(ErdError::Semantic(
+            "cardinality (5,2) has a minimum greater than its maximum".to_owned(),
+        )));
+    }
+
+    #[test]
+    fn test_relation_attributes() {
+        let (_, rel) = relation(r#"user *--* group {attributes: "joined_at, role", label: "member"}"#).unwrap();
+        let fields: Vec<_> = rel.attribs.iter().map(|a| a.field.as_str()).collect();
+        assert_eq!(fields, vec!["joined_at", "role"]);
+        assert_eq!(rel.options.label.as_deref(), Some("member"));
+    }
+
+    #[test]
+    fn test_entity_block() {
+        let s = r#"
+[orders] {color: "red"} {
+  *id
+  +customer_id {type: "int"}  # who placed it
+  index {columns: "customer_id"}
+}
+[customers] { *id name }
+[empty] {
+}
+orders *--1 customers
+"#;
+        let erd = parse_erd(s).unwrap();
+        assert_eq!(erd, parse_erd(r#"
+[orders] {color: "red"}
+*id
++customer_id {type: "int"}
+index {columns: "customer_id"}
+[customers]
+*id
+name
+[empty]
+orders *--1 customers
+"#).unwrap());
+        assert_eq!(erd.entities[0].attribs[1].span.line, 4);
+        assert_eq!(erd.entities[0].indexes[0].span.line, 5);
+        assert_eq!(parse_erd_recover(s), (erd, vec![]));
+
+        let s = "[a] {\n  *id\n}\nname\n";
+        let expected = ErdError::Semantic("found attribute after the closing brace of entity 'a'".to_owned());
+        assert_eq!(parse_erd(s), Err(expected.clone()));
+        assert_eq!(parse_erd_recover(s).1, vec![expected]);
+        assert!(parse_erd("[a] {\n  *id\n").is_err());
+    }
+
+    #[test]
+    fn test_weak_entity() {
+        let (i, e) = entity(r#"[[order_item]] {pk: "order_id, line"}"#).unwrap();
+        assert!(i.is_empty());
+        assert_eq!(e.name, "order_item");
+        assert!(e.weak);
+        assert_eq!(e.primary_key, vec!["order_id", "line"]);
+
+        let (_, e) = entity("[['order item']]").unwrap();
+        assert_eq!(e.name, "order item");
+        assert!(!entity("[order]").unwrap().1.weak);
+        assert!(entity("[[order]").is_err());
+
+        let (_, rel) = relation(r#"order 1--* order_item {identifying: "true"}"#).unwrap();
+        assert!(rel.identifying);
+        assert_eq!(rel.options, ast::RelationshipOptions::default());
+        assert!(relation(r#"a 1--* b {identifying: "yes"}"#).is_err());
+        assert_eq!(relation("order 1==* order_item").unwrap().1, rel);
+        assert!(relation("person ^== employee").is_err());
+    }
+
+    #[test]
+    fn test_relation_inheritance() {
+        let (i, rel) = relation(r#"person ^-- 'hourly employee' {label: "is a"}"#).unwrap();
+        assert!(i.is_empty());
+        assert_eq!(rel.kind, ast::RelationKind::Inheritance);
+        assert_eq!(rel.entity1, "person");
+        assert_eq!(rel.entity2, "hourly employee");
+        assert_eq!((rel.card1, rel.card2), (ast::Cardinality::One, ast::Cardinality::One));
+        assert_eq!(rel.options.label.as_deref(), Some("is a"));
+
+        assert!(relation("person ^--^ employee").is_err());
+        assert!(relation("person 1^-- employee").is_err());
+    }
+
+    #[test]
+    fn test_relation_inline_label() {
+        let (i, rel) = relation(r#"orders *--1 customers : "placed by""#).unwrap();
+        assert!(i.is_empty());
+        assert_eq!(rel.entity2, "customers");
+        assert_eq!(rel.options.label.as_deref(), Some("placed by"));
+
+        let (_, rel) = relation(r#"a 1--1 'b c':"x" {color: "red"}"#).unwrap();
+        assert_eq!(rel.entity2, "b c");
+        assert_eq!(rel.options.label.as_deref(), Some("x"));
+        assert_eq!(rel.options.color.as_deref(), Some("red"));
+
+        let (_, rel) = relation(r#"a 1--1 b : "x" {label: "y"}"#).unwrap();
+        assert_eq!(rel.options.label.as_deref(), Some("y"));
+
+        assert!(parse_erd("[a]\n[b]\na 1--1 b : owns\n").is_err());
+    }
+
+    #[test]
+    fn test_relation_named() {
+        let (i, rel) = relation("rel order_customer: orders *--1 customers").unwrap();
+        assert!(i.is_empty());
+        assert_eq!(rel.name.as_deref(), Some("order_customer"));
+        assert_eq!(rel.entity1, "orders");
+
+        let (_, rel) = relation("rel 'placed by' :a 1--1 b").unwrap();
+        assert_eq!(rel.name.as_deref(), Some("placed by"));
+
+        for s in &["rel 1--* b", "relx 1--* b"] {
+            let (_, rel) = relation(s).unwrap();
+            assert_eq!(rel.name, None, "{}", s);
+        }
+
+        let erd = parse_erd("[a]\n[b]\nrel owns: a 1--* b\n").unwrap();
+        assert_eq!(erd.relationship("owns").unwrap().entity2, "b");
+        assert!(erd.relationship("has").is_none());
+    }
+
+    #[test]
+    fn test_nary_relation() {
+        let (i, rel) = nary_relation(r#"supplies(supplier, part, 'project x') {label: "supplies to"}"#).unwrap();
+        assert!(i.is_empty());
+        assert_eq!(rel.name, "supplies");
+        assert_eq!(rel.entities, vec!["supplier", "part", "project x"]);
+        assert_eq!(rel.options.label.as_deref(), Some("supplies to"));
+
+        assert!(nary_relation("supplies(supplier)").is_err());
+        assert!(nary_relation("supplies()").is_err());
+
+        let erd = parse_erd("[a]\n[b]\n[c]\nr(a, b, c)\na 1--1 b\n").unwrap();
+        assert_eq!(erd.nary_relationships.len(), 1);
+        assert_eq!(erd.relationships.len(), 1);
+        assert!(parse_erd("[a]\nx\nr(a, b\n").is_err());
+    }
+
+    #[test]
+    fn test_enum() {
+        let erd = parse_erd("[a]\nstate {type: \"status\"}\nenum status {Values: \"active, on hold\"}\nx\n").unwrap();
+        assert_eq!(erd.enums, vec![ast::Enum {
+            name: "status".into(),
+            values: vec!["active".to_owned(), "on hold".to_owned()],
+            span: ast::Span::default(),
+        }]);
+        assert_eq!(erd.enums[0].span.line, 3);
+        // Attributes after an enum still belong to the entity before it.
+        assert_eq!(erd.entities[0].attribs.len(), 2);
+
+        assert_eq!(parse_erd("enum status {}\n"), Err(ErdError::Semantic("enum 'status' has no values".to_owned())));
+        assert!(parse_erd("enum status {values: \"a\", color: \"red\"}\n").is_err());
+        assert_eq!(parse_erd("[enum]\n*id\nenum 1--* enum\n").unwrap().relationships.len(), 1);
+    }
+
+    #[test]
+    fn test_note() {
+        let erd = parse_erd("[a]\nx\nnote \"Soft\\ndeleted\" {attached: \"a\"}\ny\nnote \"Draft\"\n").unwrap();
+        assert_eq!(erd.notes, vec![
+            ast::Note { text: "Soft\ndeleted".to_owned(), attached: Some("a".to_owned()), ..Default::default() },
+            ast::Note { text: "Draft".to_owned(), ..Default::default() },
+        ]);
+        assert_eq!(erd.entities[0].attribs.len(), 2);
+        assert!(parse_erd("note \"x\" {size: \"2\"}\n").is_err());
+    }
+
     #[test]
     fn test_ident_no_space() {
-        let (i, id) = ident_no_space("foo").unwrap();
+        let (i, id) = ident_no_space("")("foo").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo");
 
-        let (i, id) = ident_no_space("foo_BAR").unwrap();
+        let (i, id) = ident_no_space("")("foo_BAR").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo_BAR");
+
+        let (i, id) = ident_no_space("")("größe_2 x").unwrap();
+        assert_eq!(i, " x");
+        assert_eq!(id, "größe_2");
+
+        let (i, id) = ident_no_space("")("auth.users").unwrap();
+        assert_eq!(i, ".users");
+        assert_eq!(id, "auth");
+
+        let (i, id) = ident_no_space(".-")("auth.legacy-users").unwrap();
+        assert!(i.is_empty());
+        assert_eq!(id, "auth.legacy-users");
     }
 
     #[test]
@@ -638,35 +2378,35 @@ mod tests {
 
     #[test]
     fn test_ident() {
-        let (i, id) = ident("\"foo\"").unwrap();
+        let (i, id) = ident("")("\"foo\"").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo");
 
-        let (i, id) = ident("'foo'").unwrap();
+        let (i, id) = ident("")("'foo'").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo");
 
-        let (i, id) = ident("`foo`").unwrap();
+        let (i, id) = ident("")("`foo`").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo");
 
-        let (i, id) = ident("\"foo_BAR\"").unwrap();
+        let (i, id) = ident("")("\"foo_BAR\"").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo_BAR");
 
-        let (i, id) = ident("\"foo - 'foo@bar' BAR\"").unwrap();
+        let (i, id) = ident("")("\"foo - 'foo@bar' BAR\"").unwrap();
         assert_eq!(i, "");
         assert_eq!(id, "foo - 'foo@bar' BAR");
 
-        let (i, id) = ident(" foo ").unwrap();
+        let (i, id) = ident("")(" foo ").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo");
 
-        let (i, id) = ident(" \t'foo'\t ").unwrap();
+        let (i, id) = ident("")(" \t'foo'\t ").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo");
 
-        let (i, id) = ident(" \t `foo \"and\" bar` \t ").unwrap();
+        let (i, id) = ident("")(" \t `foo \"and\" bar` \t ").unwrap();
         assert!(i.is_empty());
         assert_eq!(id, "foo \"and\" bar");
     }
@@ -675,49 +2415,71 @@ mod tests {
     fn test_option() {
         let (i, (key, value)) = option(r#"foo: "bar""#).unwrap();
         assert!(i.is_empty());
-        assert_eq!((key, value), ("foo", "bar"));
+        assert_eq!((key, value), ("foo", Cow::from("bar")));
 
         let (i, (key, value)) = option(r#"foo:"A longer value?""#).unwrap();
         assert!(i.is_empty());
-        assert_eq!((key, value), ("foo", "A longer value?"));
+        assert_eq!((key, value), ("foo", Cow::from("A longer value?")));
+
+        let (i, (key, value)) = option(r#"border-color: "red""#).unwrap();
+        assert!(i.is_empty());
+        assert_eq!((key, value), ("border-color", Cow::from("red")));
     }
 
     #[test]
     fn test_options() {
         let (i, opts) = options(r#"{k:"v"}"#).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("k", "v")]);
+        assert_eq!(opts, vec![("k", Cow::from("v"))]);
 
         let (i, opts) = options(r#"{ k:"v" }"#).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("k", "v")]);
+        assert_eq!(opts, vec![("k", Cow::from("v"))]);
 
         let (i, opts) = options(r#"{k1:"v1",k2:"v2"}"#).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("k1", "v1"), ("k2", "v2")]);
+        assert_eq!(opts, vec![("k1", Cow::from("v1")), ("k2", Cow::from("v2"))]);
 
         let (i, opts) = options(r#"{k:"v1",k:"v2",k:"v3"}"#).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("k", "v1"), ("k", "v2"), ("k", "v3")]);
+        assert_eq!(opts, vec![("k", Cow::from("v1")), ("k", Cow::from("v2")), ("k", Cow::from("v3"))]);
 
         let (i, opts) = options(r#"{  k1:"v1", k2:"v2" ,  k3:"v3"}"#).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("k1", "v1"), ("k2", "v2"), ("k3", "v3")]);
+        assert_eq!(opts, vec![("k1", Cow::from("v1")), ("k2", Cow::from("v2")), ("k3", Cow::from("v3"))]);
     }
 
     #[test]
     fn test_options_trailing_comma() {
         let (i, opts) = options(r#"{k1:"v1",}"#).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("k1", "v1")]);
+        assert_eq!(opts, vec![("k1", Cow::from("v1"))]);
 
         let (i, opts) = options(r#"{k1:"v1", }"#).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("k1", "v1")]);
+        assert_eq!(opts, vec![("k1", Cow::from("v1"))]);
 
         let (i, opts) = options(r#"{k1:"v1" , }"#).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("k1", "v1")]);
+        assert_eq!(opts, vec![("k1", Cow::from("v1"))]);
+    }
+
+    #[test]
+    fn test_quoted_escapes() {
+        let (i, s) = quoted(r#""a \"b\" \\ \n \d" x"#).unwrap();
+        assert_eq!(i, " x");
+        assert_eq!(s, "a \"b\" \\ \n \\d");
+
+        let (_, s) = quoted(r#""plain\d""#).unwrap();
+        assert!(matches!(s, Cow::Borrowed("plain\\d")));
+        let (_, s) = quoted(r#""""#).unwrap();
+        assert_eq!(s, "");
+        assert!(quoted(r#""unterminated\""#).is_err());
+
+        let (i, id) = ident_quoted(r#"'it\'s' x"#).unwrap();
+        assert_eq!(i, " x");
+        assert_eq!(id, "it's");
+        assert!(ident_quoted("\"a\nb\"").is_err());
     }
 
     #[test]
@@ -729,7 +2491,7 @@ mod tests {
 
         let (i, opts) = options(i).unwrap();
         assert!(i.is_empty());
-        assert_eq!(opts, vec![("label", "string"), ("color", "#3366ff")]);
+        assert_eq!(opts, vec![("label", Cow::from("string")), ("color", Cow::from("#3366ff"))]);
     }
 
     #[test]
@@ -752,6 +2514,11 @@ mod tests {
         assert_eq!(go.options["k1"], "v1");
         assert_eq!(go.options["k2"], "v2");
 
+        let (i, go) = global_option(r#"graph {rankdir: "TB"}"#).unwrap();
+        assert!(i.is_empty());
+        assert_eq!(go.option_type, GlobalOptionType::Graph);
+        assert_eq!(go.options["rankdir"], "TB");
+
         let (i, go) = global_option(r#"relationship{ k1:"X" , k2 :   "v2", k1:"v1" }"#).unwrap();
         println!("{}", i);
         assert!(i.is_empty());
@@ -763,12 +2530,69 @@ mod tests {
         assert!(global_option(r#"something {}"#).is_err());
     }
 
-    fn new_entity<S: Into<String>>(name: S) -> ast::Entity {
+    #[test]
+    fn test_items() {
+        let s = r##"set c = "red"
+title {label: "t"}
+entity {bgcolor: "#eee"}
+[a] {color: "$c"}
+*id
+name
+[b] {
+  *id
+  +a_id
+}
+a 1--* b
+"##;
+        let items: Vec<_> = parse_erd_items(s).collect::<Result<_, _>>().unwrap();
+        assert_eq!(items.len(), 7);
+        assert!(matches!(&items[2], ast::Ast::Entity(e) if e.name == "a" && e.attribs.is_empty()));
+        assert!(matches!(&items[3], ast::Ast::Attribute(a) if a.field == "id" && a.pk));
+        assert!(matches!(&items[5], ast::Ast::Entity(e) if e.name == "b" && e.attribs.len() == 2));
+
+        let mut errors = Vec::new();
+        let erd = build_erd(items, &ParseOptions::default(), Vec::new(), &mut errors);
+        assert!(errors.is_empty());
+        let mut expected = parse_erd(s).unwrap();
+        expected.variables.clear();
+        assert_eq!(erd, expected);
+
+        let s = include_str!("../examples/nfldb.er");
+        let items = parse_erd_items(s).collect::<Result<_, _>>().unwrap();
+        assert_eq!(build_erd(items, &ParseOptions::default(), Vec::new(), &mut errors), parse_erd(s).unwrap());
+    }
+
+    #[test]
+    fn test_items_stop_at_error() {
+        let mut items = parse_erd_items("[a]\nx\n[b] {\n  z\n}\ny\n[c]\n");
+        assert!(matches!(items.next(), Some(Ok(ast::Ast::Entity(_)))));
+        assert!(matches!(items.next(), Some(Ok(ast::Ast::Attribute(_)))));
+        assert!(matches!(items.next(), Some(Ok(ast::Ast::Entity(_)))));
+        assert!(matches!(items.next(), Some(Err(ErdError::Semantic(_)))));
+        assert!(items.next().is_none());
+
+        let mut items = parse_erd_items("x\n");
+        assert!(matches!(items.next(), Some(Err(ErdError::Semantic(_)))));
+        assert!(items.next().is_none());
+
+        let mut items = parse_erd_items("[a]\n[b\n");
+        assert!(items.next().unwrap().is_ok());
+        assert!(items.next().unwrap().is_err());
+    }
+
+    fn new_entity<S: Into<ast::Name>>(name: S) -> ast::Entity {
         ast::Entity {
             name: name.into(),
             attribs: Vec::default(),
             options: ast::EntityOptions::default(),
             header_options: ast::HeaderOptions::default(),
+            primary_key: Vec::new(),
+            indexes: Vec::new(),
+            description: None,
+            group: None,
+            url: None,
+            weak: false,
+            span: ast::Span::default(),
         }
     }
 }
@@ -1,13 +1,427 @@
-use std::io::{Write, Result};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
 use crate::ast;
+use crate::chen::Chen;
+use crate::format;
+use crate::style::{StyleResolver, DEFAULT_COLOR, NOTE_COLOR};
+use crate::uml::Uml;
 
-pub struct Renderer<W: Write> {
-    w: W
+/// An output format which diagrams can be rendered to.
+pub trait Backend: Send + Sync {
+    /// Name used to select the backend, e.g. `dot`.
+    fn name(&self) -> &'static str;
+
+    fn render(&self, erd: &ast::Erd, w: &mut dyn io::Write) -> Result<()>;
+}
+
+/// Destination for rendered output: anything implementing `io::Write`, or
+/// a `fmt::Write` such as `String` when wrapped in [`Fmt`].
+pub trait Output {
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<()>;
+}
+
+impl<W: io::Write + ?Sized> Output for W {
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<()> {
+        io::Write::write_fmt(self, args)
+    }
+}
+
+/// Adapts a `fmt::Write` to be rendered into, avoiding a round trip
+/// through bytes when the output is wanted as a string.
+///
+/// ```
+/// let erd = erd::parse_erd("[Person]\n*name\n").unwrap();
+///
+/// let mut dot = String::new();
+/// erd::render::Renderer::new(erd::render::Fmt(&mut dot)).render_erd(&erd).unwrap();
+/// assert!(dot.starts_with("graph {"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Fmt<W: fmt::Write>(pub W);
+
+impl<W: fmt::Write> Output for Fmt<W> {
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<()> {
+        fmt::Write::write_fmt(&mut self.0, args).map_err(|_| io::Error::other("formatter error"))
+    }
+}
+
+/// Backend producing Graphviz DOT output.
+#[derive(Clone, Debug, Default)]
+pub struct Dot {
+    pub options: RenderOptions,
+}
+
+impl Backend for Dot {
+    fn name(&self) -> &'static str {
+        "dot"
+    }
+
+    fn render(&self, erd: &ast::Erd, w: &mut dyn io::Write) -> Result<()> {
+        Renderer::with_options(w, self.options.clone()).render_erd(erd)
+    }
+}
+
+/// Set of backends which output can be selected from by name.
+pub struct Backends {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Backends {
+    /// Creates an empty set of backends.
+    pub fn new() -> Self {
+        Self { backends: Vec::new() }
+    }
+
+    /// Adds a backend, replacing any existing backend with the same name.
+    pub fn register(&mut self, backend: Box<dyn Backend>) {
+        self.backends.retain(|b| b.name() != backend.name());
+        self.backends.push(backend);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Backend> {
+        self.backends.iter().find(|b| b.name() == name).map(|b| b.as_ref())
+    }
+
+    /// Names of all registered backends, in registration order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.backends.iter().map(|b| b.name()).collect()
+    }
+}
+
+impl Default for Backends {
+    /// All built-in backends.
+    fn default() -> Self {
+        let mut b = Self::new();
+        b.register(Box::new(Dot::default()));
+        b.register(Box::new(format::Er));
+        b.register(Box::new(Chen::default()));
+        b.register(Box::new(Uml::default()));
+        b
+    }
+}
+
+/// Controls aspects of DOT output which aren't set by the diagram itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Graph layout direction, one of `LR`, `TB`, `RL` or `BT`.
+    pub rankdir: String,
+    /// How edges are drawn, e.g. `spline` or `ortho`.
+    pub splines: String,
+    /// Graphviz layout engine to ask for, one of [`LAYOUTS`], or `None` to
+    /// leave it to whatever renders the output, usually `dot`.
+    pub layout: Option<String>,
+    /// Color of the diagram's background, if not the default of white.
+    pub background_color: Option<String>,
+    pub edge_color: String,
+    pub edge_style: String,
+    /// Color of the cardinalities and labels along edges, if not black.
+    pub edge_font_color: Option<String>,
+    pub edge_minlen: u8,
+    /// Font used for entities which don't set one.
+    pub font: String,
+    /// Indentation used for each level of nesting in the output.
+    pub indent: String,
+    /// Summary line to add below the title, if any.
+    pub summary: Option<Summary>,
+    /// Whether to list each entity's indexes below its attributes.
+    pub indexes: bool,
+    /// Whether to add a column listing which keys each attribute is part of.
+    pub constraints: bool,
+    /// Whether to mark attributes which may be null.
+    pub nullable: bool,
+    /// Whether to add a column showing attribute descriptions.
+    pub descriptions: bool,
+    /// Whether to give attributes and relationships tooltips, and entities
+    /// without a description a tooltip summarising them.
+    pub tooltips: bool,
+    /// How attributes of relationships are drawn.
+    pub relationship_attributes: RelationshipAttributes,
+    /// How attributes which are keys are marked.
+    pub keys: KeyStyle,
+    /// Which attributes entities show, unless a `graph` directive says.
+    pub attributes: ast::AttributeFilter,
+    /// Text of each attribute's row.
+    pub row_template: RowTemplate,
+    /// How cardinalities are drawn, unless a relationship says otherwise.
+    pub notation: ast::Notation,
+    /// Whether to add a table explaining the notation used.
+    pub legend: bool,
+    /// Whether entities not in a group are grouped by the schema their name
+    /// is qualified with, such as `billing` for `billing.invoices`.
+    pub schema_clusters: bool,
+    /// Style of the boxes drawn around groups, e.g. `rounded` or `dashed`.
+    pub cluster_style: String,
+    pub cluster_color: String,
+    pub cluster_background: Option<String>,
+}
+
+/// How attributes of relationships are drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RelationshipAttributes {
+    /// Listed in the edge's label.
+    #[default]
+    Label,
+    /// In a box between the two entities, like an associative entity.
+    /// Inheritance relationships are still labelled.
+    Entity,
+}
+
+/// How attributes which are keys are marked.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum KeyStyle {
+    /// Primary keys are underlined and foreign keys italic.
+    #[default]
+    Markup,
+    /// With a `PK` or `FK` badge in a column before their names, which
+    /// stays legible in small images.
+    Badges,
+    /// With both markup and badges.
+    Both,
+}
+
+impl KeyStyle {
+    fn markup(self) -> bool {
+        self != KeyStyle::Badges
+    }
+
+    fn badges(self) -> bool {
+        self != KeyStyle::Markup
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            rankdir: "LR".to_owned(),
+            splines: "spline".to_owned(),
+            layout: None,
+            background_color: None,
+            edge_color: "gray50".to_owned(),
+            edge_style: "dashed".to_owned(),
+            edge_font_color: None,
+            edge_minlen: 2,
+            font: "Helvetica".to_owned(),
+            indent: "    ".to_owned(),
+            summary: None,
+            indexes: false,
+            constraints: false,
+            nullable: true,
+            descriptions: false,
+            tooltips: false,
+            relationship_attributes: RelationshipAttributes::default(),
+            keys: KeyStyle::default(),
+            attributes: ast::AttributeFilter::default(),
+            row_template: RowTemplate::default(),
+            notation: ast::Notation::default(),
+            legend: false,
+            schema_clusters: false,
+            cluster_style: "rounded".to_owned(),
+            cluster_color: "gray70".to_owned(),
+            cluster_background: None,
+        }
+    }
+}
+
+/// Edge types accepted by the original `erd`'s `-e` option, with the
+/// Graphviz `splines` value each corresponds to.
+pub const EDGE_TYPES: &[(&str, &str)] = &[
+    ("compound", "compound"),
+    ("curved", "curved"),
+    ("line", "line"),
+    ("noedge", "none"),
+    ("ortho", "ortho"),
+    ("poly", "polyline"),
+    ("spline", "spline"),
+];
+
+/// Layout engines accepted for [`RenderOptions::layout`].
+pub const LAYOUTS: &[&str] = ast::LAYOUTS;
+
+/// Graph layout directions accepted for [`RenderOptions::rankdir`].
+pub const RANKDIRS: &[&str] = ast::RANKDIRS;
+
+/// Returns the `splines` value for an `erd` edge type.
+pub fn splines_for_edge_type(edge_type: &str) -> Option<&'static str> {
+    EDGE_TYPES.iter().find(|(name, _)| *name == edge_type).map(|(_, splines)| *splines)
+}
+
+/// Added to the rows of attributes which may be null.
+const NULL_MARK: &str = " <FONT COLOR=\"gray50\"><I>NULL</I></FONT>";
+
+/// Template used for attribute rows unless another is given.
+pub const DEFAULT_ROW_TEMPLATE: &str = "{name}{ [label]}";
+
+/// How the text of each attribute's row is built, e.g. `{key }{field}`.
+///
+/// Placeholders are written in braces:
+///
+/// - `{name}` is the attribute's name, underlined if it's part of the
+///   primary key and italic if it's a foreign key.
+/// - `{field}` is the name without that styling.
+/// - `{key}` is `PK`, `FK` or `PK FK`.
+/// - `{type}` and `{label}` are the attribute's type and label.
+///
+/// Any other text inside a placeholder's braces is only written if the
+/// placeholder has a value, so `{ [label]}` gives ` [ID]` for an attribute
+/// labelled `ID`, and nothing for one without a label. `{{` and `}}` are
+/// literal braces. Values are escaped, but the template's own text is
+/// written as is, so it can contain markup such as `<I>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RowTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Text(String),
+    Placeholder { prefix: String, value: RowValue, suffix: String },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RowValue {
+    Name,
+    Field,
+    Key,
+    Type,
+    Label,
+}
+
+impl RowTemplate {
+    /// Returns the row text for an attribute, whose name has already been
+    /// escaped and styled to show whether it's a key.
+    fn expand(&self, a: &ast::Attribute, pk: bool, name: &str) -> String {
+        let key = key_text(a, pk);
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(s) => out.push_str(s),
+                TemplatePart::Placeholder { prefix, value, suffix } => {
+                    let value = match value {
+                        RowValue::Name => name.to_owned(),
+                        RowValue::Field => escape_html(&a.field),
+                        RowValue::Key => key.to_owned(),
+                        RowValue::Type => escape_html(a.datatype.as_deref().unwrap_or("")),
+                        RowValue::Label => escape_html(a.options.label.as_deref().unwrap_or("")),
+                    };
+                    if !value.is_empty() {
+                        out.push_str(prefix);
+                        out.push_str(&value);
+                        out.push_str(suffix);
+                    }
+                },
+            }
+        }
+        out
+    }
+}
+
+impl Default for RowTemplate {
+    fn default() -> Self {
+        DEFAULT_ROW_TEMPLATE.parse().expect("the default template is valid")
+    }
+}
+
+impl std::str::FromStr for RowTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => { chars.next(); text.push('{') },
+                '}' if chars.peek() == Some(&'}') => { chars.next(); text.push('}') },
+                '}' => return Err("unmatched } in row template, write }} for a literal brace".to_owned()),
+                '{' => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => inner.push(c),
+                            None => return Err("unclosed { in row template".to_owned()),
+                        }
+                    }
+                    let start = inner.find(|c: char| c.is_ascii_alphabetic())
+                        .ok_or_else(|| format!("no placeholder in {{{}}}", inner))?;
+                    let end = inner[start..].find(|c: char| !c.is_ascii_alphabetic())
+                        .map_or(inner.len(), |n| start + n);
+                    let value = match &inner[start..end] {
+                        "name" => RowValue::Name,
+                        "field" => RowValue::Field,
+                        "key" => RowValue::Key,
+                        "type" => RowValue::Type,
+                        "label" => RowValue::Label,
+                        other => return Err(format!(
+                            "unknown placeholder {{{}}}, expected one of name, field, key, type or label",
+                            other,
+                        )),
+                    };
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Placeholder {
+                        prefix: inner[..start].to_owned(),
+                        value,
+                        suffix: inner[end..].to_owned(),
+                    });
+                },
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        Ok(Self { parts })
+    }
+}
+
+/// Which optional columns an entity's table has, after attribute names.
+#[derive(Copy, Clone, Debug)]
+struct Columns {
+    keys: bool,
+    types: bool,
+    constraints: bool,
+    descriptions: bool,
+}
+
+impl Columns {
+    /// Total number of columns, including attribute names.
+    fn count(&self) -> usize {
+        1 + self.keys as usize + self.types as usize + self.constraints as usize + self.descriptions as usize
+    }
+}
+
+pub struct Renderer<W: Output> {
+    w: W,
+    options: RenderOptions,
+}
+
+/// Describes the generated summary line added below the diagram title.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Name of the file the diagram was generated from, if any.
+    pub source: Option<String>,
+    /// Time at which the diagram was generated, if it should be shown.
+    pub generated: Option<SystemTime>,
 }
 
-impl<W: Write> Renderer<W> {
+impl<W: Output> Renderer<W> {
     pub fn new(w: W) -> Self {
-        Self { w }
+        Self::with_options(w, RenderOptions::default())
+    }
+
+    pub fn with_options(w: W, options: RenderOptions) -> Self {
+        Self { w, options }
+    }
+
+    /// Appends a summary line (entity/relationship counts, generation time
+    /// and source file) below the title.
+    pub fn set_summary(&mut self, summary: Summary) {
+        self.options.summary = Some(summary);
     }
 
     pub fn render_erd(&mut self, erd: &ast::Erd) -> Result<()> {
@@ -15,17 +429,26 @@ impl<W: Write> Renderer<W> {
 
         let mut graph_attrs = Vec::new();
 
+        let mut title = Vec::new();
         if let Some(label) = &erd.title_options.label {
-            graph_attrs.push((
-                "label",
-                format!("<<FONT POINT-SIZE=\"{}\">{}</FONT>>", erd.title_options.size, label),
+            title.push(format!("<FONT POINT-SIZE=\"{}\">{}</FONT>", erd.title_options.size, title_markup(label)));
+        }
+        if let Some(summary) = &self.options.summary {
+            title.push(format!(
+                "<FONT POINT-SIZE=\"{}\">{}</FONT>",
+                erd.title_options.size / 2,
+                escape_html(&summary_text(erd, summary)),
             ));
+        }
+
+        if !title.is_empty() {
+            graph_attrs.push(("label", format!("<{}>", title.join("<BR ALIGN=\"LEFT\"/>"))));
             graph_attrs.push(("labeljust", "l".to_owned()));
             graph_attrs.push(("labelloc", "t".to_owned()));
         }
 
-        graph_attrs.push(("rankdir", "LR".to_owned()));
-        graph_attrs.push(("splines", "spline".to_owned()));
+        graph_attrs.extend(graph_style_attrs(erd, &self.options));
+        graph_attrs.extend(layout_attrs(&erd.graph_options, &self.options));
 
         self.graph_attributes(&graph_attrs)?;
 
@@ -34,44 +457,266 @@ impl<W: Write> Renderer<W> {
             ("shape", "plaintext".to_owned()),
         ])?;
 
-        self.edge_attributes(&vec![
-            ("color", "gray50".to_owned()),
-            ("minlen", "2".to_owned()),
-            ("style", "dashed".to_owned()),
-        ])?;
+        let mut edge_attrs = vec![
+            ("color", quote(&self.options.edge_color)),
+            ("minlen", self.options.edge_minlen.to_string()),
+            ("style", quote(&self.options.edge_style)),
+        ];
+        if let Some(color) = &self.options.edge_font_color {
+            edge_attrs.push(("fontcolor", quote(color)));
+        }
+        self.edge_attributes(&edge_attrs)?;
+
+        // Without any attributes shown, there are no columns for edges to
+        // attach to.
+        let filter = erd.graph_options.attributes.unwrap_or(self.options.attributes);
+        let hidden = filter == ast::AttributeFilter::Hidden;
+        let relationships: Vec<Cow<ast::Relation>> = erd.relationships.iter()
+            .map(|r| if self.associative(r) || hidden { Cow::Borrowed(r) } else { column_ports(erd, r) })
+            .collect();
+        let mut implied = erd.implied_relationships();
+        if hidden {
+            for r in &mut implied {
+                r.options.tail_port = None;
+                r.options.head_port = None;
+            }
+        }
+        let ported: Vec<&ast::Relation> = relationships.iter()
+            .filter_map(|r| match r {
+                Cow::Owned(r) => Some(r),
+                Cow::Borrowed(_) => None,
+            })
+            .chain(&implied)
+            .collect();
+        let entities: Vec<Cow<ast::Entity>> = erd.entities.iter()
+            .map(|e| filter_attributes(e, filter, &ported))
+            .collect();
+        // Each group is drawn where its first entity is declared.
+        let mut drawn = HashSet::new();
+        for e in &entities {
+            match self.group(e) {
+                None => self.entity(e, &ported, 1)?,
+                Some(group) => if drawn.insert(group) {
+                    let members: Vec<&ast::Entity> = entities.iter()
+                        .map(|other| other.as_ref())
+                        .filter(|other| self.group(other) == Some(group))
+                        .collect();
+                    self.cluster(group, &members, &ported)?;
+                },
+            }
+        }
 
-        for e in &erd.entities {
-            self.entity(e)?;
+        for (i, r) in relationships.iter().enumerate() {
+            if self.associative(r) {
+                self.associative_entity(r, i)?;
+            } else {
+                self.relationship(r)?;
+            }
         }
 
-        for r in &erd.relationships {
+        for r in &implied {
             self.relationship(r)?;
         }
 
+        for r in &erd.nary_relationships {
+            self.nary_relationship(r)?;
+        }
+
+        for t in &erd.enums {
+            self.enumeration(t, &erd.entities)?;
+        }
+
+        for (i, n) in erd.notes.iter().enumerate() {
+            self.note(n, i)?;
+        }
+
+        if self.options.legend {
+            self.legend(erd)?;
+        }
+
         self.graph_footer()
     }
 
+    /// Draws a table explaining how keys and cardinalities are shown, in
+    /// the notations the diagram's relationships use. It's placed in the
+    /// last rank, so it ends up along one side of the diagram.
+    fn legend(&mut self, erd: &ast::Erd) -> Result<()> {
+        let notations: Vec<ast::Notation> = erd.relationships.iter()
+            .filter(|r| r.kind == ast::RelationKind::Association)
+            .map(|r| r.options.notation.unwrap_or(self.options.notation))
+            .collect();
+        let mut rows = match self.options.keys {
+            KeyStyle::Markup => vec![("<U>id</U>", "primary key"), ("<I>id</I>", "foreign key")],
+            _ => vec![("<B>PK</B>", "primary key"), ("<B>FK</B>", "foreign key")],
+        };
+        rows.push(("<B>id</B>", "unique"));
+        if notations.contains(&ast::Notation::Labels) {
+            rows.extend([
+                ("1", "exactly one"),
+                ("{0,1}", "zero or one"),
+                ("0..N", "zero or more"),
+                ("1..N", "one or more"),
+                ("(m,n)", "from m to n"),
+            ]);
+        }
+        if notations.contains(&ast::Notation::CrowsFoot) {
+            rows.extend([
+                ("bar", "one"),
+                ("circle", "zero"),
+                ("crow's foot", "many"),
+            ]);
+        }
+
+        let indent = self.options.indent.clone();
+        writeln!(self.w, "{}\"erd-legend\" [", indent)?;
+        writeln!(self.w, "{i}{i}label=<", i=indent)?;
+        self.open_tag_attrs("FONT", &[("FACE", self.options.font.clone())])?;
+        write!(self.w, "\n  ")?;
+        self.open_tag_attrs("TABLE", &[
+            ("BORDER", "0".to_owned()),
+            ("CELLBORDER", "1".to_owned()),
+            ("CELLPADDING", "4".to_owned()),
+            ("CELLSPACING", "0".to_owned()),
+        ])?;
+        write!(self.w, "\n    <TR><TD COLSPAN=\"2\"><B>Legend</B></TD></TR>\n")?;
+        for (shown, meaning) in rows {
+            // Only the key examples are markup.
+            let shown = if shown.starts_with('<') { shown.to_owned() } else { escape_html(shown) };
+            writeln!(self.w, "    <TR><TD>{}</TD><TD ALIGN=\"LEFT\">{}</TD></TR>", shown, meaning)?;
+        }
+        write!(self.w, "  </TABLE>\n</FONT>\n>];\n")?;
+        writeln!(self.w, "{}{{ rank=sink; \"erd-legend\"; }}", indent)
+    }
+
+    /// Returns the name of the group `e` is drawn in, if any.
+    fn group<'a>(&self, e: &'a ast::Entity) -> Option<&'a str> {
+        e.group.as_deref().or_else(|| match self.options.schema_clusters {
+            true => e.name.rsplit_once('.').map(|(schema, _)| schema),
+            false => None,
+        })
+    }
+
+    /// Draws the entities in a group inside a box labelled with its name.
+    fn cluster(&mut self, name: &str, entities: &[&ast::Entity], ported: &[&ast::Relation]) -> Result<()> {
+        let indent = self.options.indent.clone();
+        writeln!(self.w, "{}subgraph {} {{", indent, quote(&format!("cluster_{}", name)))?;
+        let mut attrs = vec![
+            ("label", quote(name)),
+            ("style", quote(&self.options.cluster_style)),
+            ("color", quote(&self.options.cluster_color)),
+        ];
+        if let Some(c) = &self.options.cluster_background {
+            attrs.push(("bgcolor", quote(c)));
+        }
+        for (k, v) in attrs {
+            writeln!(self.w, "{i}{i}{}={};", k, v, i=indent)?;
+        }
+        for e in entities {
+            self.entity(e, ported, 2)?;
+        }
+        writeln!(self.w, "{}}}", indent)
+    }
+
+    /// Returns whether `r` is drawn as a box of its attributes rather than
+    /// as a single edge.
+    fn associative(&self, r: &ast::Relation) -> bool {
+        !r.attribs.is_empty()
+            && r.kind == ast::RelationKind::Association
+            && self.options.relationship_attributes == RelationshipAttributes::Entity
+    }
+
     fn graph_header(&mut self) -> Result<()> {
-        write!(self.w, "graph {{\n")
+        writeln!(self.w, "graph {{")
     }
 
-    fn render_attribute(&mut self, a: &ast::Attribute) -> Result<()> {
-        let field = match (a.pk, a.fk) {
-            (true, true)    => format!("<I><U>{}</U></I>", a.field),
-            (true, false)   => format!("<U>{}</U>", a.field),
-            (false, true)   => format!("<I>{}</I>", a.field),
-            (false, false)  => a.field.clone(),
+    fn render_attribute(&mut self, a: &ast::Attribute, pk: bool, port: bool, columns: Columns) -> Result<()> {
+        let name = escape_html(&a.field);
+        let name = if a.unique { format!("<B>{}</B>", name) } else { name };
+        let name = match (pk && self.options.keys.markup(), a.fk && self.options.keys.markup()) {
+            (true, true)    => format!("<I><U>{}</U></I>", name),
+            (true, false)   => format!("<U>{}</U>", name),
+            (false, true)   => format!("<I>{}</I>", name),
+            (false, false)  => name,
         };
+        let mut label = self.options.row_template.expand(a, pk, &name);
+        if a.nullable && self.options.nullable {
+            label.push_str(NULL_MARK);
+        }
+
+        // The entity's font and color are already set around the table, so
+        // only what the attribute sets itself needs adding to its cells.
+        let mut cell = vec![("ALIGN", a.options.text_alignment.clone())];
+        if let Some(c) = &a.options.background_color {
+            cell.push(("BGCOLOR", c.clone()));
+        }
+        if let Some(b) = a.options.border {
+            cell.push(("BORDER", b.to_string()));
+        }
+        if let Some(c) = &a.options.border_color {
+            cell.push(("COLOR", c.clone()));
+        }
+        let mut font = Vec::new();
+        if let Some(f) = &a.options.font {
+            font.push(("FACE", f.clone()));
+        }
+        if let Some(c) = &a.options.color {
+            font.push(("COLOR", c.clone()));
+        }
+
         write!(self.w, "    ")?;
         self.open_tag("TR")?;
-        self.open_tag_attrs("TD", &[("ALIGN", "LEFT".to_owned())])?;
-        match &a.options.label {
-            Some(l) => write!(self.w, "{} [{}]", field, l)?,
-            None => write!(self.w, "{}", a.field)?,
+        if columns.keys {
+            let key = key_text(a, pk);
+            let badge = if key.is_empty() { String::new() } else { format!("<B>{}</B>", key) };
+            self.attribute_cell(&cell, &font, &badge)?;
         }
+        let mut first = cell.clone();
+        if port {
+            first.push(("PORT", a.field.to_string()));
+        }
+        if self.options.tooltips {
+            let tooltip = a.description.clone().unwrap_or_else(|| attribute_summary(a, pk));
+            first.push(("TOOLTIP", tooltip));
+        }
+        self.attribute_cell(&first, &font, &label)?;
+        if columns.types {
+            self.attribute_cell(&cell, &font, &escape_html(a.datatype.as_deref().unwrap_or("")))?;
+        }
+        if columns.constraints {
+            self.attribute_cell(&cell, &font, &constraint_names(a, pk).join(", "))?;
+        }
+        if columns.descriptions {
+            self.attribute_cell(&cell, &font, &escape_html(a.description.as_deref().unwrap_or("")))?;
+        }
+        self.close_tag("TR")?;
+        writeln!(self.w)
+    }
+
+    fn attribute_cell(&mut self, cell: &[(&str, String)], font: &[(&str, String)], text: &str) -> Result<()> {
+        self.open_tag_attrs("TD", cell)?;
+        if font.is_empty() {
+            write!(self.w, "{}", text)?;
+        } else {
+            self.open_tag_attrs("FONT", font)?;
+            write!(self.w, "{}", text)?;
+            self.close_tag("FONT")?;
+        }
+        self.close_tag("TD")
+    }
+
+    fn render_index(&mut self, index: &ast::Index, columns: Columns) -> Result<()> {
+        let mut attrs = vec![("ALIGN", "LEFT".to_owned())];
+        if columns.count() > 1 {
+            attrs.push(("COLSPAN", columns.count().to_string()));
+        }
+        write!(self.w, "    ")?;
+        self.open_tag("TR")?;
+        self.open_tag_attrs("TD", &attrs)?;
+        let kind = if index.unique { "unique index" } else { "index" };
+        write!(self.w, "<I>{} ({})</I>", kind, escape_html(&index.columns.join(", ")))?;
         self.close_tag("TD")?;
         self.close_tag("TR")?;
-        write!(self.w, "\n")
+        writeln!(self.w)
     }
 
     fn open_tag(&mut self, tag: &str) -> Result<()> {
@@ -81,7 +726,7 @@ impl<W: Write> Renderer<W> {
     fn open_tag_attrs(&mut self, tag: &str, attrs: &[(&str, String)]) -> Result<()> {
         write!(self.w, "<{}", tag)?;
         for (k, v) in attrs {
-            write!(self.w, " {}=\"{}\"", k, v)?;
+            write!(self.w, " {}=\"{}\"", k, escape_html(v))?;
         }
         write!(self.w, ">")
     }
@@ -91,56 +736,310 @@ impl<W: Write> Renderer<W> {
     }
 
     fn relationship(&mut self, r: &ast::Relation) -> Result<()> {
-        let head_card = match r.card2 {
-            ast::Cardinality::ZeroOne => "{0,1}",
-            ast::Cardinality::One => "1",
-            ast::Cardinality::ZeroPlus => "0..N",
-            ast::Cardinality::OnePlus => "1..N",
-        };
-        let tail_card = match r.card1 {
-            ast::Cardinality::ZeroOne => "{0,1}",
-            ast::Cardinality::One => "1",
-            ast::Cardinality::ZeroPlus => "0..N",
-            ast::Cardinality::OnePlus => "1..N",
+        let notation = r.options.notation.unwrap_or(self.options.notation);
+        let mut attrs = match r.kind {
+            ast::RelationKind::Association => {
+                let mut attrs = cardinality_attrs(r.card2, true, notation);
+                attrs.extend(cardinality_attrs(r.card1, false, notation));
+                if notation == ast::Notation::CrowsFoot {
+                    attrs.push(("dir", "both".to_owned()));
+                }
+                if r.identifying && r.options.style.is_none() {
+                    attrs.push(("style", "solid".to_owned()));
+                }
+                attrs
+            },
+            // A hollow triangle pointing at the more general entity.
+            ast::RelationKind::Inheritance if r.options.style.is_some() => vec![
+                ("dir", "back".to_owned()),
+                ("arrowtail", "empty".to_owned()),
+            ],
+            ast::RelationKind::Inheritance => vec![
+                ("dir", "back".to_owned()),
+                ("arrowtail", "empty".to_owned()),
+                ("style", "solid".to_owned()),
+            ],
         };
-        write!(self.w, r#"    "{}" -- "{}" [ headlabel="{}", taillabel="{}" ];
-"#, r.entity1, r.entity2, head_card, tail_card)
+        attrs.extend(edge_style_attrs(&r.options));
+        // Attributes are listed below the label, one per line.
+        let lines: Vec<&str> = r.options.label.iter()
+            .map(String::as_str)
+            .chain(r.attribs.iter().map(|a| a.field.as_str()))
+            .collect();
+        if !lines.is_empty() {
+            let lines: Vec<String> = lines.iter().map(|l| escape_string(l)).collect();
+            attrs.push(("label", format!("\"{}\"", lines.join("\\n"))));
+        }
+        if let Some(color) = &r.options.color {
+            attrs.push(("color", quote(color)));
+        }
+        if let Some(size) = r.options.size {
+            attrs.push(("fontsize", size.to_string()));
+        }
+        if let Some(font) = &r.options.font {
+            attrs.push(("fontname", quote(font)));
+        }
+        if let Some(name) = &r.name {
+            attrs.push(("id", quote(name)));
+        }
+        if self.options.tooltips {
+            attrs.push(("tooltip", quote(&relationship_summary(r))));
+        }
+
+        // Without ports, both ends of a self-loop meet the entity at the same
+        // point, with their cardinalities drawn over each other.
+        let self_loop = r.entity1 == r.entity2;
+        let tail_port = r.options.tail_port.as_deref().or(self_loop.then_some("ne"));
+        let head_port = r.options.head_port.as_deref().or(self_loop.then_some("se"));
+        if let Some(port) = tail_port {
+            attrs.push(("tailport", port_id(port)));
+        }
+        if let Some(port) = head_port {
+            attrs.push(("headport", port_id(port)));
+        }
+
+        let attrs: Vec<String> = attrs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        writeln!(self.w, "{}{} -- {} [ {} ];",
+            self.options.indent, quote(&r.entity1), quote(&r.entity2), attrs.join(", "))
     }
 
-    fn entity(&mut self, e: &ast::Entity) -> Result<()> {
-        write!(self.w, r#"    "{name}" [
-        label=<
-"#, name=e.name)?;
+    /// Draws a relationship as a box listing its attributes, under its
+    /// label if it has one, with an edge to each of its entities. The box is
+    /// named after the relationship, or if it has no name, its entities and
+    /// `index` among the diagram's relationships.
+    fn associative_entity(&mut self, r: &ast::Relation, index: usize) -> Result<()> {
+        let id = quote(&match &r.name {
+            Some(name) => name.clone(),
+            None => format!("{}--{}#{}", r.entity1, r.entity2, index),
+        });
+        let indent = &self.options.indent;
+        writeln!(self.w, "{indent}{id} [", indent=indent, id=id)?;
+        writeln!(self.w, "{indent}{indent}label=<", indent=indent)?;
 
-        self.open_tag_attrs("FONT", &[("FACE", e.header_options.font.clone())])?;
+        let font = r.options.font.clone().unwrap_or_else(|| self.options.font.clone());
+        self.open_tag_attrs("FONT", &[("FACE", font)])?;
         write!(self.w, "\n  ")?;
+        self.open_tag_attrs("TABLE", &[
+            ("BORDER", "0".to_owned()),
+            ("CELLBORDER", "1".to_owned()),
+            ("CELLPADDING", "4".to_owned()),
+            ("CELLSPACING", "0".to_owned()),
+            ("STYLE", "rounded".to_owned()),
+        ])?;
+        writeln!(self.w)?;
+        if let Some(label) = &r.options.label {
+            writeln!(self.w, "    <TR><TD><I>{}</I></TD></TR>", escape_html(label))?;
+        }
+        let columns = Columns { keys: false, types: false, constraints: false, descriptions: false };
+        for a in &r.attribs {
+            self.render_attribute(a, false, false, columns)?;
+        }
+        write!(self.w, "  </TABLE>\n</FONT>\n>];\n")?;
 
+        let notation = r.options.notation.unwrap_or(self.options.notation);
+        let mut tail = cardinality_attrs(r.card1, false, notation);
+        let mut head = cardinality_attrs(r.card2, true, notation);
+        if notation == ast::Notation::CrowsFoot {
+            tail.push(("dir", "back".to_owned()));
+            head.push(("dir", "forward".to_owned()));
+        }
         let mut attrs = Vec::new();
-        attrs.push(("BORDER", e.header_options.border.to_string()));
-        attrs.push(("CELLBORDER", e.header_options.cell_border.to_string()));
-        attrs.push(("CELLPADDING", e.header_options.cell_padding.to_string()));
-        attrs.push(("CELLSPACING", e.header_options.cell_spacing.to_string()));
+        if r.identifying && r.options.style.is_none() {
+            attrs.push(("style", "solid".to_owned()));
+        }
+        attrs.extend(edge_style_attrs(&r.options));
+        if let Some(color) = &r.options.color {
+            attrs.push(("color", quote(color)));
+        }
+        // The cardinalities are drawn with the relationship's font.
+        if let Some(size) = r.options.size {
+            attrs.push(("fontsize", size.to_string()));
+        }
+        if let Some(font) = &r.options.font {
+            attrs.push(("fontname", quote(font)));
+        }
+        if self.options.tooltips {
+            attrs.push(("tooltip", quote(&relationship_summary(r))));
+        }
+        let join = |end: Vec<(&str, String)>| -> String {
+            end.iter().chain(&attrs).map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(", ")
+        };
+        writeln!(self.w, "{}{} -- {} [ {} ];",
+            self.options.indent, quote(&r.entity1), id, join(tail))?;
+        writeln!(self.w, "{}{} -- {} [ {} ];",
+            self.options.indent, id, quote(&r.entity2), join(head))
+    }
+
+    /// Draws an n-ary relationship as a diamond, with an edge to each of its
+    /// entities.
+    fn nary_relationship(&mut self, r: &ast::NaryRelation) -> Result<()> {
+        let font = r.options.font.as_ref().unwrap_or(&self.options.font);
+        let mut attrs = vec![
+            ("shape", "diamond".to_owned()),
+            ("fontname", quote(font)),
+        ];
+        if let Some(label) = &r.options.label {
+            attrs.push(("label", quote(label)));
+        }
+        if let Some(color) = &r.options.color {
+            attrs.push(("color", quote(color)));
+        }
+        if let Some(size) = r.options.size {
+            attrs.push(("fontsize", size.to_string()));
+        }
+        let attrs: Vec<String> = attrs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        writeln!(self.w, "{}{} [ {} ];", self.options.indent, quote(&r.name), attrs.join(", "))?;
+
+        let edge_attrs = match &r.options.color {
+            Some(color) => format!(" [ color={} ]", quote(color)),
+            None => String::new(),
+        };
+        for e in &r.entities {
+            writeln!(self.w, "{}{} -- {}{};", self.options.indent, quote(e), quote(&r.name), edge_attrs)?;
+        }
+        Ok(())
+    }
+
+    /// Draws an enum as a small table listing its values, with a dotted edge
+    /// to each entity having an attribute of its type.
+    fn enumeration(&mut self, t: &ast::Enum, entities: &[ast::Entity]) -> Result<()> {
+        let values: String = t.values.iter()
+            .map(|v| format!(r#"<TR><TD ALIGN="LEFT">{}</TD></TR>"#, escape_html(v)))
+            .collect();
+        writeln!(
+            self.w,
+            r#"{}{} [ label=<<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4"><TR><TD><I>enum</I> <B>{}</B></TD></TR>{}</TABLE>>, fontname={}, fontsize=10 ];"#,
+            self.options.indent, quote(&t.name), escape_html(&t.name), values, quote(&self.options.font),
+        )?;
+
+        for e in entities.iter().filter(|e| e.attribs.iter().any(|a| a.datatype.as_ref() == Some(&t.name))) {
+            writeln!(self.w, "{}{} -- {} [ style=dotted ];", self.options.indent, quote(&e.name), quote(&t.name))?;
+        }
+        Ok(())
+    }
+
+    /// Draws a note as a sticky note, with a dotted edge to the entity it's
+    /// attached to, if any.
+    fn note(&mut self, n: &ast::Note, index: usize) -> Result<()> {
+        let id = format!("note:{}", index);
+        writeln!(
+            self.w,
+            r#"{}"{}" [ shape=note, style=filled, fillcolor={}, fontname={}, label="{}" ];"#,
+            self.options.indent,
+            id,
+            quote(n.background_color.as_deref().unwrap_or(NOTE_COLOR)),
+            quote(&self.options.font),
+            escape_string(&n.text).replace('\n', "\\n"),
+        )?;
+        if let Some(entity) = &n.attached {
+            writeln!(self.w, r#"{}"{}" -- {} [ style=dotted ];"#, self.options.indent, id, quote(entity))?;
+        }
+        Ok(())
+    }
+
+    /// Draws an entity as a table. Attributes at either end of a
+    /// relationship attached at columns are given ports for its edge to
+    /// attach to. The node is indented `level` times, as it's nested in a
+    /// cluster when its entity is in a group.
+    fn entity(&mut self, e: &ast::Entity, ported: &[&ast::Relation], level: usize) -> Result<()> {
+        let indent = self.options.indent.repeat(level);
+        let inner = self.options.indent.repeat(level + 1);
+        writeln!(self.w, "{}{} [", indent, quote(&e.name))?;
+        let tooltip = e.description.clone().or_else(|| self.options.tooltips.then(|| entity_summary(e)));
+        if let Some(tooltip) = tooltip {
+            writeln!(self.w, "{}tooltip={},", inner, quote(&tooltip))?;
+        }
+        if let Some(url) = &e.url {
+            writeln!(self.w, "{}URL={},", inner, quote(url))?;
+        }
+        writeln!(self.w, "{}label=<", inner)?;
 
-        if let Some(c) = &e.options.background_color {
+        // The entity's font applies to the whole table, with the header
+        // only overriding what it sets itself.
+        let resolver = StyleResolver::new(&self.options);
+        let style = resolver.entity(e);
+        let header = resolver.header(e);
+        let mut font = vec![("FACE", style.font.clone())];
+        if let Some(size) = e.options.size {
+            font.push(("POINT-SIZE", size.to_string()));
+        }
+        if style.color != DEFAULT_COLOR {
+            font.push(("COLOR", style.color.clone()));
+        }
+        self.open_tag_attrs("FONT", &font)?;
+        write!(self.w, "\n  ")?;
+
+        let mut attrs = vec![
+            ("BORDER", style.border.to_string()),
+            ("CELLBORDER", e.options.cell_border.to_string()),
+            ("CELLPADDING", e.options.cell_padding.to_string()),
+            ("CELLSPACING", e.options.cell_spacing.to_string()),
+        ];
+
+        if let Some(c) = &style.border_color {
+            attrs.push(("COLOR", c.clone()))
+        }
+        if let Some(c) = &style.background_color {
             attrs.push(("BGCOLOR", c.clone()))
         }
+        // Weak entities are drawn with a double border, by wrapping their
+        // table in another one.
+        if e.weak {
+            self.open_tag_attrs("TABLE", &[
+                ("BORDER", "1".to_owned()),
+                ("CELLBORDER", "0".to_owned()),
+                ("CELLPADDING", "2".to_owned()),
+                ("CELLSPACING", "0".to_owned()),
+            ])?;
+            write!(self.w, "<TR><TD>")?;
+        }
         self.open_tag_attrs("TABLE", &attrs)?;
 
+        // Entities with any typed attribute get a second column for types,
+        // and likewise for constraints and descriptions if they're shown.
+        let columns = Columns {
+            keys: self.options.keys.badges()
+                && e.attribs.iter().any(|a| !key_text(a, e.is_primary_key(a)).is_empty()),
+            types: e.attribs.iter().any(|a| a.datatype.is_some()),
+            constraints: self.options.constraints
+                && e.attribs.iter().any(|a| !constraint_names(a, e.is_primary_key(a)).is_empty()),
+            descriptions: self.options.descriptions && e.attribs.iter().any(|a| a.description.is_some()),
+        };
+        let mut cell = String::new();
+        if columns.count() > 1 {
+            cell.push_str(&format!(" COLSPAN=\"{}\"", columns.count()));
+        }
+        // The table background already covers the header unless it's
+        // given its own.
+        if let Some(c) = header.background_color.as_ref().filter(|c| Some(*c) != e.options.background_color.as_ref()) {
+            cell.push_str(&format!(" BGCOLOR=\"{}\"", escape_html(c)));
+        }
+        let face = if header.font == style.font { String::new() } else { format!(" FACE=\"{}\"", escape_html(&header.font)) };
+        let color = if header.color == style.color { String::new() } else { format!(" COLOR=\"{}\"", escape_html(&header.color)) };
         write!(
             self.w,
-            "\n    <TR><TD><B><FONT POINT-SIZE=\"{size}\">{name}</FONT></B></TD></TR>\n",
+            "\n    <TR><TD{cell}><B><FONT{face} POINT-SIZE=\"{size}\"{color}>{name}</FONT></B></TD></TR>\n",
+            cell=cell,
+            face=face,
             size=e.header_options.size,
-            name=e.name,
+            color=color,
+            name=escape_html(header.label.as_deref().unwrap_or(&e.name)),
         )?;
 
         for a in &e.attribs {
-            self.render_attribute(a)?;
+            self.render_attribute(a, e.is_primary_key(a), attached(e, a, ported), columns)?;
+        }
+        if self.options.indexes {
+            for index in &e.indexes {
+                self.render_index(index, columns)?;
+            }
         }
 
-        write!(self.w, r#"  </TABLE>
-</FONT>
->];
-"#)?;
+        write!(self.w, "  </TABLE>")?;
+        if e.weak {
+            write!(self.w, "</TD></TR></TABLE>")?;
+        }
+        write!(self.w, "\n</FONT>\n>];\n")?;
 
         Ok(())
     }
@@ -158,60 +1057,423 @@ impl<W: Write> Renderer<W> {
     }
 
     fn attributes(&mut self, name: &str, opts: &Vec<(&str, String)>) -> Result<()> {
-        write!(self.w, "    {} [\n", name)?;
+        let indent = &self.options.indent;
+        writeln!(self.w, "{}{} [", indent, name)?;
         for (key, value) in opts {
-            write!(self.w, "        {}={},\n", key, value)?;
+            writeln!(self.w, "{}{}{}={},", indent, indent, key, value)?;
         }
-        write!(self.w, "    ];\n")
+        writeln!(self.w, "{}];", indent)
     }
 
     fn graph_footer(&mut self) -> Result<()> {
-        write!(self.w, "}}\n")
+        writeln!(self.w, "}}")
     }
+}
 
+/// Converts a title label into HTML-like label markup. Lines are left
+/// aligned, text between `**` is bold and between single `*` italic, with
+/// `\*` for a literal asterisk. Anything else is escaped.
+///
+/// Tags are always closed in the order they were opened, so where bold and
+/// italic text overlap, the inner tag is closed and opened again around the
+/// end of the outer one.
+pub(crate) fn title_markup(label: &str) -> String {
+    /// Opens `tag`, or closes it if it's already open.
+    fn toggle(out: &mut String, open: &mut Vec<&'static str>, tag: &'static str) {
+        match open.iter().position(|t| *t == tag) {
+            Some(i) => {
+                let inner = open.split_off(i + 1);
+                for t in inner.iter().rev().chain([&tag]) {
+                    out.push_str(&format!("</{}>", t));
+                }
+                open.pop();
+                for t in &inner {
+                    out.push_str(&format!("<{}>", t));
+                }
+                open.extend(inner);
+            },
+            None => {
+                out.push_str(&format!("<{}>", tag));
+                open.push(tag);
+            },
+        }
+    }
 
+    let mut out = String::new();
+    let mut open = Vec::new();
+    let mut chars = label.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'*') => out.push(chars.next().expect("peeked")),
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                toggle(&mut out, &mut open, "B");
+            },
+            '*' => toggle(&mut out, &mut open, "I"),
+            '\n' => out.push_str("<BR ALIGN=\"LEFT\"/>"),
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    while let Some(t) = open.pop() {
+        out.push_str(&format!("</{}>", t));
+    }
+    out
 }
 
+/// Summarises an entity for its tooltip, e.g. `orders: 4 attributes,
+/// primary key (id)`.
+fn entity_summary(e: &ast::Entity) -> String {
+    let mut s = format!("{}: {} attribute{}", e.name, e.attribs.len(), if e.attribs.len() == 1 { "" } else { "s" });
+    let key: Vec<&str> = e.attribs.iter().filter(|a| e.is_primary_key(a)).map(|a| a.field.as_ref()).collect();
+    if !key.is_empty() {
+        s.push_str(&format!(", primary key ({})", key.join(", ")));
+    }
+    s
+}
 
+/// Summarises an attribute for its tooltip, e.g. `user_id: int (FK,
+/// nullable)`.
+fn attribute_summary(a: &ast::Attribute, pk: bool) -> String {
+    let mut s = a.field.to_string();
+    if let Some(t) = &a.datatype {
+        s.push_str(&format!(": {}", t));
+    }
+    let mut notes = constraint_names(a, pk);
+    if a.nullable {
+        notes.push("nullable");
+    }
+    if !notes.is_empty() {
+        s.push_str(&format!(" ({})", notes.join(", ")));
+    }
+    s
+}
 
+/// Summarises a relationship for its tooltip, e.g. `person 1--0..N
+/// dependent: has`.
+fn relationship_summary(r: &ast::Relation) -> String {
+    let mut s = match r.kind {
+        ast::RelationKind::Association => format!("{} {}--{} {}", r.entity1, r.card1, r.card2, r.entity2),
+        ast::RelationKind::Inheritance => format!("{} is a {}", r.entity2, r.entity1),
+    };
+    if let Some(label) = r.options.label.as_ref().or(r.name.as_ref()) {
+        s.push_str(&format!(": {}", label));
+    }
+    s
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::parser::parse_erd;
-    use std::str::from_utf8;
-    use pretty_assertions::assert_eq;
+/// Returns `PK`, `FK` or `PK FK` for an attribute which is part of a key.
+fn key_text(a: &ast::Attribute, pk: bool) -> &'static str {
+    match (pk, a.fk) {
+        (true, true) => "PK FK",
+        (true, false) => "PK",
+        (false, true) => "FK",
+        (false, false) => "",
+    }
+}
 
-    #[test]
-    fn empty_graph() {
-        let erd = ast::Erd::default();
-        let mut buf = Vec::new();
-        let mut renderer = Renderer::new(&mut buf);
-        renderer.render_erd(&erd).unwrap();
-        assert_eq!(from_utf8(&buf).unwrap(), r#"graph {
-    graph [
-        rankdir=LR,
-        splines=spline,
-    ];
-    node [
-        label="\N",
-        shape=plaintext,
-    ];
-    edge [
-        color=gray50,
-        minlen=2,
-        style=dashed,
-    ];
+/// Returns the names of the keys an attribute is part of, e.g. `PK` and
+/// `FK` for a foreign key in a composite primary key.
+pub(crate) fn constraint_names(a: &ast::Attribute, key: bool) -> Vec<&'static str> {
+    [(key, "PK"), (a.fk, "FK"), (a.unique, "unique")].iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect()
 }
-"#);
-    }
 
-    #[test]
-    fn title_and_entity() {
-        let s = r#"
-title {label: "Foo"}
+/// Returns graph attributes for the title's font and color, and the
+/// background color, of which the graph directive's takes precedence.
+pub(crate) fn graph_style_attrs(erd: &ast::Erd, options: &RenderOptions) -> Vec<(&'static str, String)> {
+    let mut attrs = Vec::new();
+    if let Some(font) = &erd.title_options.font {
+        attrs.push(("fontname", quote(font)));
+    }
+    if let Some(color) = &erd.title_options.color {
+        attrs.push(("fontcolor", quote(color)));
+    }
+    if let Some(color) = erd.graph_options.background_color.as_ref().or(options.background_color.as_ref()) {
+        attrs.push(("bgcolor", quote(color)));
+    }
+    attrs
+}
 
-[thing]
+/// Returns the graph attributes controlling layout. A graph directive in the
+/// diagram takes precedence over the defaults it's rendered with.
+pub(crate) fn layout_attrs(graph: &ast::GraphOptions, options: &RenderOptions) -> Vec<(&'static str, String)> {
+    let mut attrs = vec![
+        ("rankdir", graph.rankdir.clone().unwrap_or_else(|| options.rankdir.clone())),
+        ("splines", graph.splines.clone().unwrap_or_else(|| options.splines.clone())),
+    ];
+    if let Some(layout) = graph.layout.as_ref().or(options.layout.as_ref()) {
+        attrs.push(("layout", layout.clone()));
+        // Engines other than dot place nodes freely, and may put them on
+        // top of each other unless told not to.
+        if layout != "dot" {
+            attrs.push(("overlap", "false".to_owned()));
+        }
+    }
+    attrs
+}
+
+/// Returns whether any of the `ported` relationships attaches to `e` at
+/// attribute `a`.
+fn attached(e: &ast::Entity, a: &ast::Attribute, ported: &[&ast::Relation]) -> bool {
+    ported.iter().any(|r| {
+        (r.entity1 == e.name && r.options.tail_port.as_deref() == Some(a.field.as_str()))
+            || (r.entity2 == e.name && r.options.head_port.as_deref() == Some(a.field.as_str()))
+    })
+}
+
+/// Returns `e` with only the attributes `filter` shows. Showing keys keeps
+/// any other attribute an edge attaches to as well.
+pub(crate) fn filter_attributes<'a>(e: &'a ast::Entity, filter: ast::AttributeFilter, ported: &[&ast::Relation]) -> Cow<'a, ast::Entity> {
+    match filter {
+        ast::AttributeFilter::All => Cow::Borrowed(e),
+        ast::AttributeFilter::Keys => {
+            let mut shown = e.clone();
+            shown.attribs.retain(|a| e.is_primary_key(a) || a.fk || attached(e, a, ported));
+            Cow::Owned(shown)
+        },
+        ast::AttributeFilter::Hidden => Cow::Owned(ast::Entity { attribs: Vec::new(), ..e.clone() }),
+    }
+}
+
+/// Returns `r` attached at the columns of the foreign key joining its
+/// entities, so its edge meets the rows rather than the tables. Only
+/// associations without ports of their own are attached, and only when a
+/// single foreign key joins the two entities, as otherwise which one the
+/// relationship stands for is ambiguous.
+fn column_ports<'a>(erd: &ast::Erd, r: &'a ast::Relation) -> Cow<'a, ast::Relation> {
+    if r.kind != ast::RelationKind::Association || r.options.head_port.is_some() || r.options.tail_port.is_some() {
+        return Cow::Borrowed(r);
+    }
+    let (entity1, entity2) = match (erd.entity(&r.entity1), erd.entity(&r.entity2)) {
+        (Some(e1), Some(e2)) => (e1, e2),
+        _ => return Cow::Borrowed(r),
+    };
+    // Pairs of (tail, head) fields, with references from the second entity
+    // to the first, then the other way round unless it's a self-loop.
+    let references = |from: &ast::Entity, to: &ast::Entity| -> Vec<(String, String)> {
+        from.attribs.iter()
+            .filter_map(|a| {
+                let target = a.references.as_ref()?;
+                let exists = target.entity == to.name && to.attribs.iter().any(|b| b.field == target.field);
+                exists.then(|| (target.field.clone(), a.field.to_string()))
+            })
+            .collect()
+    };
+    let mut ports = references(entity2, entity1);
+    if entity1.name != entity2.name {
+        ports.extend(references(entity1, entity2).into_iter().map(|(to, from)| (from, to)));
+    }
+    match ports.as_slice() {
+        [(tail, head)] => {
+            let mut r = r.clone();
+            r.options.tail_port = Some(tail.clone());
+            r.options.head_port = Some(head.clone());
+            Cow::Owned(r)
+        },
+        _ => Cow::Borrowed(r),
+    }
+}
+
+/// Returns the edge attributes for a relationship's own line style, length
+/// and width, which otherwise come from the defaults for all edges.
+fn edge_style_attrs(options: &ast::RelationshipOptions) -> Vec<(&'static str, String)> {
+    let mut attrs = Vec::new();
+    if let Some(style) = &options.style {
+        attrs.push(("style", style.clone()));
+    }
+    if let Some(minlen) = options.minlen {
+        attrs.push(("minlen", minlen.to_string()));
+    }
+    if let Some(width) = &options.penwidth {
+        attrs.push(("penwidth", width.clone()));
+    }
+    attrs
+}
+
+/// Returns the edge attributes showing `card` at the head or tail of an
+/// edge. Crow's foot arrowheads can't show numbers other than 0 and 1, so
+/// explicit ranges are labelled as well.
+fn cardinality_attrs(card: ast::Cardinality, head: bool, notation: ast::Notation) -> Vec<(&'static str, String)> {
+    let (label, arrow) = if head { ("headlabel", "arrowhead") } else { ("taillabel", "arrowtail") };
+    let label = (label, quote(&card.to_string()));
+    match notation {
+        ast::Notation::Labels => vec![label],
+        ast::Notation::CrowsFoot => {
+            let min = match card {
+                ast::Cardinality::ZeroOne | ast::Cardinality::ZeroPlus => 0,
+                ast::Cardinality::One | ast::Cardinality::OnePlus => 1,
+                ast::Cardinality::Range { min, .. } => min,
+            };
+            // Arrowhead shapes are listed starting from the entity.
+            let shape = format!(
+                "{}{}",
+                if card.is_many() { "crow" } else { "tee" },
+                if min == 0 { "odot" } else { "tee" },
+            );
+            match card {
+                ast::Cardinality::Range { .. } => vec![(arrow, shape), label],
+                _ => vec![(arrow, shape)],
+            }
+        },
+    }
+}
+
+/// Returns a port name as a DOT ID, quoted unless it's a plain identifier.
+fn port_id(port: &str) -> String {
+    if port.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !port.starts_with(|c: char| c.is_ascii_digit()) {
+        port.to_owned()
+    } else {
+        quote(port)
+    }
+}
+
+/// Returns `s` as a quoted DOT string.
+pub(crate) fn quote(s: &str) -> String {
+    format!("\"{}\"", escape_string(s))
+}
+
+/// Escapes `s` for use inside a quoted DOT string, where `"` would end the
+/// string and `\` starts an escape sequence.
+fn escape_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes `s` for use as text or an attribute value in an HTML-like label.
+pub(crate) fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn summary_text(erd: &ast::Erd, summary: &Summary) -> String {
+    let mut parts = vec![format!(
+        "{} entities, {} relationships",
+        erd.entities.len(),
+        erd.relationships.len(),
+    )];
+
+    if let Some(t) = summary.generated {
+        parts.push(format!("generated {}", format_timestamp(t)));
+    }
+
+    if let Some(source) = &summary.source {
+        parts.push(format!("from {}", source));
+    }
+
+    parts.join(", ")
+}
+
+/// Formats a timestamp as `YYYY-MM-DD HH:MM:SS UTC`.
+fn format_timestamp(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Civil-from-days conversion, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60,
+    )
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErdError;
+    use crate::parser::{parse_erd, parse_erd_with, ParseOptions};
+    use std::str::from_utf8;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn deterministic_output() {
+        let text = r##"title {label: "T", color: "red", size: "20", font: "Arial"}
+header {bgcolor: "#ddd", color: "blue", font: "Courier", size: "18", border: "1"}
+entity {bgcolor: "#eee", color: "green", border: "2", font: "Times", label: "x"}
+relationship {color: "gray", font: "Arial", size: "9", label: "r"}
+[a] {border-color: "red", size: "10", label: "A", color: "black"}
+*id {type: "int", color: "red", bgcolor: "#fff", font: "Mono", border: "1", label: "ID"}
+[b]
+*id
++a_id -> a.id
+note "n" {attached: "b", bgcolor: "pink"}
+"##;
+        let render = || {
+            let mut out = String::new();
+            Renderer::new(Fmt(&mut out)).render_erd(&parse_erd(text).unwrap()).unwrap();
+            out
+        };
+        let first = render();
+        for _ in 0..10 {
+            assert_eq!(render(), first);
+        }
+    }
+
+    #[test]
+    fn fmt_output() {
+        let erd = parse_erd(include_str!("../examples/nfldb.er")).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let mut out = String::new();
+        Renderer::new(Fmt(&mut out)).render_erd(&erd).unwrap();
+        assert_eq!(out, from_utf8(&buf).unwrap());
+    }
+
+    #[test]
+    fn empty_graph() {
+        let erd = ast::Erd::default();
+        let mut buf = Vec::new();
+        let mut renderer = Renderer::new(&mut buf);
+        renderer.render_erd(&erd).unwrap();
+        assert_eq!(from_utf8(&buf).unwrap(), r#"graph {
+    graph [
+        rankdir=LR,
+        splines=spline,
+    ];
+    node [
+        label="\N",
+        shape=plaintext,
+    ];
+    edge [
+        color="gray50",
+        minlen=2,
+        style="dashed",
+    ];
+}
+"#);
+    }
+
+    #[test]
+    fn title_and_entity() {
+        let s = r#"
+title {label: "Foo"}
+
+[thing]
 "#;
         let erd = parse_erd(s).unwrap();
         let mut buf = Vec::new();
@@ -230,9 +1492,9 @@ title {label: "Foo"}
         shape=plaintext,
     ];
     edge [
-        color=gray50,
+        color="gray50",
         minlen=2,
-        style=dashed,
+        style="dashed",
     ];
     "thing" [
         label=<
@@ -246,6 +1508,32 @@ title {label: "Foo"}
 "##);
     }
 
+    #[test]
+    fn edge_defaults_quoted() {
+        let options = RenderOptions { edge_color: "#333".to_owned(), edge_style: "dotted".to_owned(), ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&parse_erd("[a]\n").unwrap()).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains("    edge [\n        color=\"#333\",\n        minlen=2,\n        style=\"dotted\",\n"), "{}", out);
+    }
+
+    #[test]
+    fn title_markup() {
+        assert_eq!(super::title_markup("Plain"), "Plain");
+        assert_eq!(
+            super::title_markup("**Orders** schema\n*draft* <v2> & \\*notes\\*"),
+            "<B>Orders</B> schema<BR ALIGN=\"LEFT\"/><I>draft</I> &lt;v2&gt; &amp; *notes*",
+        );
+        assert_eq!(super::title_markup("**unclosed *too"), "<B>unclosed <I>too</I></B>");
+        assert_eq!(super::title_markup("**a *b** c*"), "<B>a <I>b</I></B><I> c</I>");
+        assert_eq!(super::title_markup("*a **b* c**"), "<I>a <B>b</B></I><B> c</B>");
+
+        let erd = parse_erd("title {label: \"A\\n**B**\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains(r#"label=<<FONT POINT-SIZE="30">A<BR ALIGN="LEFT"/><B>B</B></FONT>>,"#));
+    }
+
     #[test]
     fn simple() {
         let s = include_str!("../examples/simple.er");
@@ -263,20 +1551,20 @@ title {label: "Foo"}
         shape=plaintext,
     ];
     edge [
-        color=gray50,
+        color="gray50",
         minlen=2,
-        style=dashed,
+        style="dashed",
     ];
     "Person" [
         label=<
 <FONT FACE="Helvetica">
   <TABLE BORDER="0" CELLBORDER="1" CELLPADDING="4" CELLSPACING="0">
     <TR><TD><B><FONT POINT-SIZE="16">Person</FONT></B></TD></TR>
-    <TR><TD ALIGN="LEFT">name</TD></TR>
+    <TR><TD ALIGN="LEFT"><U>name</U></TD></TR>
     <TR><TD ALIGN="LEFT">height</TD></TR>
     <TR><TD ALIGN="LEFT">weight</TD></TR>
     <TR><TD ALIGN="LEFT">birth date</TD></TR>
-    <TR><TD ALIGN="LEFT">birth_place_id</TD></TR>
+    <TR><TD ALIGN="LEFT"><I>birth_place_id</I></TD></TR>
   </TABLE>
 </FONT>
 >];
@@ -285,7 +1573,7 @@ title {label: "Foo"}
 <FONT FACE="Helvetica">
   <TABLE BORDER="0" CELLBORDER="1" CELLPADDING="4" CELLSPACING="0">
     <TR><TD><B><FONT POINT-SIZE="16">Birth Place</FONT></B></TD></TR>
-    <TR><TD ALIGN="LEFT">id</TD></TR>
+    <TR><TD ALIGN="LEFT"><U>id</U></TD></TR>
     <TR><TD ALIGN="LEFT">birth city</TD></TR>
     <TR><TD ALIGN="LEFT">birth state</TD></TR>
     <TR><TD ALIGN="LEFT">birth country</TD></TR>
@@ -298,6 +1586,860 @@ title {label: "Foo"}
  
     }
 
+    #[test]
+    fn title_with_summary() {
+        let s = r#"
+title {label: "Foo"}
+
+[a]
+[b]
+a 1--* b
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        let mut renderer = Renderer::new(&mut buf);
+        renderer.set_summary(Summary {
+            source: Some("foo.er".to_owned()),
+            generated: Some(UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000)),
+        });
+        renderer.render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"label=<<FONT POINT-SIZE="30">Foo</FONT><BR ALIGN="LEFT"/><FONT POINT-SIZE="15">2 entities, 1 relationships, generated 2020-09-13 12:26:40 UTC, from foo.er</FONT>>,"#));
+    }
+
+    #[test]
+    fn summary_without_title() {
+        let mut buf = Vec::new();
+        let mut renderer = Renderer::new(&mut buf);
+        renderer.set_summary(Summary::default());
+        renderer.render_erd(&ast::Erd::default()).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"label=<<FONT POINT-SIZE="15">0 entities, 0 relationships</FONT>>,"#));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01 00:00:00 UTC");
+        let t = UNIX_EPOCH + std::time::Duration::from_secs(951_782_400);
+        assert_eq!(format_timestamp(t), "2000-02-29 00:00:00 UTC");
+    }
+
+    #[test]
+    fn backends() {
+        let mut backends = Backends::default();
+        assert_eq!(backends.names(), vec!["dot", "er", "chen", "uml"]);
+        assert!(backends.get("svg").is_none());
+
+        let erd = parse_erd("[a]\n*id\n").unwrap();
+        let mut buf = Vec::new();
+        backends.get("er").unwrap().render(&erd, &mut buf).unwrap();
+        assert_eq!(from_utf8(&buf).unwrap(), "[a]\n  *id\n\n");
+
+        backends.register(Box::new(Dot {
+            options: RenderOptions { summary: Some(Summary::default()), ..Default::default() },
+        }));
+        assert_eq!(backends.names(), vec!["er", "chen", "uml", "dot"]);
+        let mut buf = Vec::new();
+        backends.get("dot").unwrap().render(&erd, &mut buf).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains("1 entities, 0 relationships"));
+    }
+
+    #[test]
+    fn title_style() {
+        let erd = parse_erd("title {label: \"Schema\", font: \"Georgia\", color: \"navy\"}\n[a]\n").unwrap();
+        let options = RenderOptions { background_color: Some("#fdfdf6".to_owned()), ..Default::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options.clone()).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().starts_with(r##"graph {
+    graph [
+        label=<<FONT POINT-SIZE="30">Schema</FONT>>,
+        labeljust=l,
+        labelloc=t,
+        fontname="Georgia",
+        fontcolor="navy",
+        bgcolor="#fdfdf6",
+        rankdir=LR,
+"##), "{}", from_utf8(&buf).unwrap());
+
+        let erd = parse_erd("graph {bgcolor: \"black\"}\n[a]\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains("        bgcolor=\"black\",\n"));
+        assert!(format::format_erd(&erd).starts_with("graph {bgcolor: \"black\"}\n"));
+    }
+
+    #[test]
+    fn graph_directive() {
+        let erd = parse_erd("graph {rankdir: \"TB\"}\n[a]\n").unwrap();
+        let options = RenderOptions { rankdir: "RL".to_owned(), splines: "ortho".to_owned(), ..Default::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().starts_with("graph {\n    graph [\n        rankdir=TB,\n        splines=ortho,\n    ];\n"));
+        assert!(format::format_erd(&erd).starts_with("graph {rankdir: \"TB\"}\n\n[a]\n"));
+
+        let erd = parse_erd("graph {layout: \"fdp\", splines: \"curved\"}\n[a]\n").unwrap();
+        let options = RenderOptions { layout: Some("neato".to_owned()), ..Default::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains("        splines=curved,\n        layout=fdp,\n        overlap=false,\n"));
+    }
+
+    #[test]
+    fn layout_engine() {
+        let attrs = |layout: Option<&str>| {
+            let options = RenderOptions { layout: layout.map(str::to_owned), ..Default::default() };
+            layout_attrs(&ast::GraphOptions::default(), &options)
+        };
+        assert_eq!(attrs(None).len(), 2);
+        assert_eq!(attrs(Some("dot"))[2..], [("layout", "dot".to_owned())]);
+        assert_eq!(attrs(Some("sfdp"))[2..], [("layout", "sfdp".to_owned()), ("overlap", "false".to_owned())]);
+
+        assert_eq!(splines_for_edge_type("curved"), Some("curved"));
+        assert_eq!(splines_for_edge_type("line"), Some("line"));
+        let erd = parse_erd("[a]\n").unwrap();
+        let options = RenderOptions { splines: "line".to_owned(), layout: Some("circo".to_owned()), ..Default::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains("        splines=line,\n        layout=circo,\n        overlap=false,\n"));
+    }
+
+    #[test]
+    fn render_options() {
+        let erd = parse_erd("[a]\n[b] {font: \"Courier\"}\na 1--1 b\n").unwrap();
+        let mut buf = Vec::new();
+        let options = RenderOptions {
+            rankdir: "TB".to_owned(),
+            splines: "ortho".to_owned(),
+            layout: None,
+            background_color: None,
+            edge_color: "black".to_owned(),
+            edge_style: "solid".to_owned(),
+            edge_font_color: None,
+            edge_minlen: 1,
+            font: "Times".to_owned(),
+            indent: "  ".to_owned(),
+            summary: None,
+            indexes: false,
+            constraints: false,
+            nullable: true,
+            descriptions: false,
+            tooltips: false,
+            relationship_attributes: RelationshipAttributes::Label,
+            keys: KeyStyle::Markup,
+            attributes: ast::AttributeFilter::All,
+            row_template: RowTemplate::default(),
+            notation: ast::Notation::Labels,
+            legend: false,
+            schema_clusters: false,
+            cluster_style: "rounded".to_owned(),
+            cluster_color: "gray70".to_owned(),
+            cluster_background: None,
+        };
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert_eq!(from_utf8(&buf).unwrap(), r#"graph {
+  graph [
+    rankdir=TB,
+    splines=ortho,
+  ];
+  node [
+    label="\N",
+    shape=plaintext,
+  ];
+  edge [
+    color="black",
+    minlen=1,
+    style="solid",
+  ];
+  "a" [
+    label=<
+<FONT FACE="Times">
+  <TABLE BORDER="0" CELLBORDER="1" CELLPADDING="4" CELLSPACING="0">
+    <TR><TD><B><FONT POINT-SIZE="16">a</FONT></B></TD></TR>
+  </TABLE>
+</FONT>
+>];
+  "b" [
+    label=<
+<FONT FACE="Courier">
+  <TABLE BORDER="0" CELLBORDER="1" CELLPADDING="4" CELLSPACING="0">
+    <TR><TD><B><FONT POINT-SIZE="16">b</FONT></B></TD></TR>
+  </TABLE>
+</FONT>
+>];
+  "a" -- "b" [ headlabel="1", taillabel="1" ];
+}
+"#);
+    }
+
+    #[test]
+    fn edge_types() {
+        assert_eq!(splines_for_edge_type("poly"), Some("polyline"));
+        assert_eq!(splines_for_edge_type("noedge"), Some("none"));
+        assert_eq!(splines_for_edge_type("spline"), Some(RenderOptions::default().splines.as_str()));
+        assert_eq!(splines_for_edge_type("curved"), Some("curved"));
+        assert_eq!(splines_for_edge_type("wiggly"), None);
+    }
+
+    #[test]
+    fn relationship_options() {
+        let s = r##"
+[a]
+[b]
+a 1--* b {label: "owns", color: "#ff0000", size: "10", font: "Courier"}
+rel a_b: a 1--1 b
+a (0,5)--(1,*) b
+"##;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains(
+            r##"    "a" -- "b" [ headlabel="0..N", taillabel="1", label="owns", color="#ff0000", fontsize=10, fontname="Courier" ];"##
+        ));
+        assert!(from_utf8(&buf).unwrap().contains(r#"    "a" -- "b" [ headlabel="1", taillabel="1", id="a_b" ];"#));
+        assert!(from_utf8(&buf).unwrap().contains(r#"    "a" -- "b" [ headlabel="(1,*)", taillabel="(0,5)" ];"#));
+    }
+
+    #[test]
+    fn entity_font_options() {
+        let s = r##"
+[a] {font: "Courier", size: "12", color: "#333333"}
+*id
+header {color: "blue", font: "Times"}
+[b] {color: "red"}
+*id
+"##;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r##"<FONT FACE="Courier" POINT-SIZE="12" COLOR="#333333">"##));
+        assert!(out.contains(r#"<B><FONT POINT-SIZE="12">a</FONT></B>"#));
+        assert!(out.contains(r#"<FONT FACE="Helvetica" COLOR="red">"#));
+        assert!(out.contains(r#"<B><FONT FACE="Times" POINT-SIZE="16" COLOR="blue">b</FONT></B>"#));
+    }
+
+    #[test]
+    fn entity_border() {
+        let erd = parse_erd("[a] {border: \"2\", border-color: \"red\"}\n[b]\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TABLE BORDER="2" CELLBORDER="1" CELLPADDING="4" CELLSPACING="0" COLOR="red">"#));
+        assert!(out.contains(r#"<TABLE BORDER="0" CELLBORDER="1" CELLPADDING="4" CELLSPACING="0">"#));
+    }
+
+    #[test]
+    fn header_style() {
+        let s = r##"
+header {color: "white", bgcolor: "#333333"}
+[a] {bgcolor: "#eeeeee"}
+[b] {label: "Bee"}
+"##;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r##"<TR><TD BGCOLOR="#333333"><B><FONT POINT-SIZE="16" COLOR="white">a</FONT></B></TD></TR>"##));
+        assert!(out.contains(r##"<TR><TD BGCOLOR="#333333"><B><FONT POINT-SIZE="16" COLOR="white">Bee</FONT></B></TD></TR>"##));
+    }
+
+    #[test]
+    fn attribute_style() {
+        let s = r##"
+[a]
+*id {type: "int", bgcolor: "yellow", color: "red", font: "Courier"}
+name {type: "text", bgcolor: "#eeeeee"}
+total {text-alignment: "right"}
+legacy {border: "3", border-color: "red"}
+"##;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(concat!(
+            r#"<TR><TD ALIGN="LEFT" BGCOLOR="yellow"><FONT FACE="Courier" COLOR="red"><U>id</U></FONT></TD>"#,
+            r#"<TD ALIGN="LEFT" BGCOLOR="yellow"><FONT FACE="Courier" COLOR="red">int</FONT></TD></TR>"#,
+        )));
+        assert!(out.contains(r##"<TR><TD ALIGN="LEFT" BGCOLOR="#eeeeee">name</TD><TD ALIGN="LEFT" BGCOLOR="#eeeeee">text</TD></TR>"##));
+        assert!(out.contains(r#"<TR><TD ALIGN="RIGHT">total</TD><TD ALIGN="RIGHT"></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT" BORDER="3" COLOR="red">legacy</TD><TD ALIGN="LEFT" BORDER="3" COLOR="red"></TD></TR>"#));
+    }
+
+    #[test]
+    fn row_template() {
+        let erd = parse_erd("[a]\n*id {type: \"int\", label: \"ID\"}\n+b_id\nname\n").unwrap();
+        let a = &erd.entities[0].attribs;
+        let default = RowTemplate::default();
+        assert_eq!(default.expand(&a[0], true, "<U>id</U>"), "<U>id</U> [ID]");
+        assert_eq!(default.expand(&a[2], false, "name"), "name");
+
+        let template: RowTemplate = "{key }{field}{: type} {{x}}".parse().unwrap();
+        assert_eq!(template.expand(&a[0], true, "<U>id</U>"), "PK id: int {x}");
+        assert_eq!(template.expand(&a[1], false, "<I>b_id</I>"), "FK b_id {x}");
+
+        let mut buf = Vec::new();
+        let options = RenderOptions { row_template: template, ..Default::default() };
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains(r#"<TR><TD ALIGN="LEFT">name {x}</TD><TD ALIGN="LEFT"></TD></TR>"#));
+
+        for (template, err) in [
+            ("{size}", "unknown placeholder {size}, expected one of name, field, key, type or label"),
+            ("{name", "unclosed { in row template"),
+            ("name}", "unmatched } in row template, write }} for a literal brace"),
+            ("{ - }", "no placeholder in { - }"),
+        ] {
+            assert_eq!(template.parse::<RowTemplate>(), Err(err.to_owned()));
+        }
+    }
+
+    #[test]
+    fn escaping() {
+        let s = r#"
+[`R&D "Dept"`] {label: "<R&D>"}
+*`a\b` {type: "map<k, v>", label: "x & y"}
+[b]
+`R&D "Dept"` 1--* b {label: "\"quoted\""}
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"    "R&D \"Dept\"" ["#), "{}", out);
+        assert!(out.contains("<FONT POINT-SIZE=\"16\">&lt;R&amp;D&gt;</FONT>"));
+        assert!(out.contains(r#"<TD ALIGN="LEFT"><U>a\b</U> [x &amp; y]</TD><TD ALIGN="LEFT">map&lt;k, v&gt;</TD>"#));
+        assert!(out.contains(r#"    "R&D \"Dept\"" -- "b" [ headlabel="0..N", taillabel="1", label="\"quoted\"" ];"#));
+
+        assert_eq!(escape_html(r#"<a href="x">&</a>"#), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+        assert_eq!(quote(r#"a\"b"#), r#""a\\\"b""#);
+    }
+
+    #[test]
+    fn crows_foot() {
+        let s = r#"
+[a]
+[b]
+a 1--* b
+a ?--+ b {label: "x"}
+a (0,5)--1 b {notation: "labels"}
+a (2,5)--1 b
+"#;
+        let erd = parse_erd(s).unwrap();
+        let options = RenderOptions { notation: ast::Notation::CrowsFoot, ..Default::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options.clone()).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"    "a" -- "b" [ arrowhead=crowodot, arrowtail=teetee, dir=both ];"#), "{}", out);
+        assert!(out.contains(r#"    "a" -- "b" [ arrowhead=crowtee, arrowtail=teeodot, dir=both, label="x" ];"#));
+        assert!(out.contains(r#"    "a" -- "b" [ headlabel="1", taillabel="(0,5)" ];"#));
+        assert!(out.contains(r#"    "a" -- "b" [ arrowhead=teetee, arrowtail=crowtee, taillabel="(2,5)", dir=both ];"#));
+
+        let erd = parse_erd("relationship {notation: \"crows-foot\"}\n[a]\n[b]\na 1--* b {attributes: \"x\"}\n").unwrap();
+        let options = RenderOptions { relationship_attributes: RelationshipAttributes::Entity, ..Default::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"    "a" -- "a--b#0" [ arrowtail=teetee, dir=back ];"#), "{}", out);
+        assert!(out.contains(r#"    "a--b#0" -- "b" [ arrowhead=crowodot, dir=forward ];"#));
+
+        assert_eq!(parse_erd("[a]\na 1--1 a {notation: \"chen\"}\n").unwrap_err(), ErdError::InvalidOptionValue {
+            key: "notation".to_owned(),
+            value: "chen".to_owned(),
+            expected: "labels or crows-foot",
+        });
+    }
+
+    #[test]
+    fn edge_style() {
+        let s = r#"
+relationship {penwidth: "1.5"}
+[a]
+[[b]]
+*+a_id
+a 1==* b {style: "dotted", minlen: "3"}
+a ^-- b {style: "bold"}
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"    "a" -- "b" [ headlabel="0..N", taillabel="1", style=dotted, minlen=3, penwidth=1.5 ];"#), "{}", out);
+        assert!(out.contains(r#"    "a" -- "b" [ dir=back, arrowtail=empty, style=bold, penwidth=1.5 ];"#), "{}", out);
+
+        assert_eq!(parse_erd("[a]\na 1--1 a {style: \"wavy\"}\n").unwrap_err(), ErdError::InvalidOptionValue {
+            key: "style".to_owned(),
+            value: "wavy".to_owned(),
+            expected: "solid, dashed, dotted or bold",
+        });
+        assert!(parse_erd("[a]\na 1--1 a {penwidth: \"-1\"}\n").is_err());
+    }
+
+    #[test]
+    fn relationship_attributes() {
+        let erd = parse_erd("[user]\n[group]\nuser *--1 group {label: \"member\", attributes: \"joined_at, role\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains(
+            r#"    "user" -- "group" [ headlabel="1", taillabel="0..N", label="member\njoined_at\nrole" ];"#
+        ));
+
+        let options = RenderOptions { relationship_attributes: RelationshipAttributes::Entity, ..Default::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options.clone()).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"    "user--group#0" [
+        label=<
+<FONT FACE="Helvetica">
+  <TABLE BORDER="0" CELLBORDER="1" CELLPADDING="4" CELLSPACING="0" STYLE="rounded">
+    <TR><TD><I>member</I></TD></TR>
+    <TR><TD ALIGN="LEFT">joined_at</TD></TR>
+    <TR><TD ALIGN="LEFT">role</TD></TR>
+  </TABLE>
+</FONT>
+>];
+    "user" -- "user--group#0" [ taillabel="0..N" ];
+    "user--group#0" -- "group" [ headlabel="1" ];
+"#), "{}", out);
+
+        let erd = parse_erd("[a]\n[b]\na 1--* b {attributes: \"x\", color: \"red\", size: \"9\", font: \"Courier\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains(
+            r#"    "a" -- "a--b#0" [ taillabel="1", color="red", fontsize=9, fontname="Courier" ];"#
+        ));
+    }
+
+    #[test]
+    fn nary_relationships() {
+        let s = r#"
+[supplier]
+*id
+[part]
+*id
+[project]
+*id
+supplies(supplier, part, project) {color: "blue"}
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.ends_with(r#"    "supplies" [ shape=diamond, fontname="Helvetica", color="blue" ];
+    "supplier" -- "supplies" [ color="blue" ];
+    "part" -- "supplies" [ color="blue" ];
+    "project" -- "supplies" [ color="blue" ];
+}
+"#));
+        assert_eq!(format::format_erd(&erd).lines().last(), Some(r#"supplies(supplier, part, project) {color: "blue"}"#));
+    }
+
+    #[test]
+    fn enums() {
+        let s = r#"
+enum status {values: "active, archived"}
+[account]
+*id
+state {type: "status"}
+[user]
+*id
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.ends_with(r#"    "status" [ label=<<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4"><TR><TD><I>enum</I> <B>status</B></TD></TR><TR><TD ALIGN="LEFT">active</TD></TR><TR><TD ALIGN="LEFT">archived</TD></TR></TABLE>>, fontname="Helvetica", fontsize=10 ];
+    "account" -- "status" [ style=dotted ];
+}
+"#));
+        assert!(format::format_erd(&erd).starts_with("enum status {values: \"active, archived\"}\n\n[account]\n"));
+    }
+
+    #[test]
+    fn references() {
+        let s = r#"
+[customers]
+*id
+[orders]
+*id
++customer_id -> customers.id
+[invoices]
+*+'order id' -> orders.id {unique: "true"}
++customer_id -> customers.id
+customers 1--* invoices
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT" PORT="id"><U>id</U></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT" PORT="customer_id"><I>customer_id</I></TD></TR>"#));
+        // Declared relationships are attached at the foreign key too.
+        assert!(!out.contains(r#"<TR><TD ALIGN="LEFT"><I>customer_id</I></TD></TR>"#));
+        assert!(out.ends_with(r#"    "customers" -- "invoices" [ headlabel="0..N", taillabel="1", tailport=id, headport=customer_id ];
+    "customers" -- "orders" [ headlabel="0..N", taillabel="1", tailport=id, headport=customer_id ];
+    "orders" -- "invoices" [ headlabel="{0,1}", taillabel="1", style=solid, tailport=id, headport="order id" ];
+}
+"#));
+        assert!(format::format_erd(&erd).contains("  +customer_id -> customers.id\n\n[invoices]\n  *+\"order id\" -> orders.id {unique: \"true\"}\n"));
+    }
+
+    #[test]
+    fn column_ports() {
+        let s = r#"
+[users]
+*id
+[posts]
+*id
++author_id -> users.id
++editor_id -> users.id
+[comments]
+*id
++post_id -> posts.id
++user_id -> users.id
+comments *--1 posts
+users 1--* posts
+users 1--* comments {head-port: "w"}
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"    "comments" -- "posts" [ headlabel="1", taillabel="0..N", tailport=post_id, headport=id ];"#), "{}", out);
+        // Either foreign key could be meant.
+        assert!(out.contains(r#"    "users" -- "posts" [ headlabel="0..N", taillabel="1" ];"#));
+        assert!(out.contains(r#"    "users" -- "comments" [ headlabel="0..N", taillabel="1", headport=w ];"#));
+        assert!(out.contains(r#"<TD ALIGN="LEFT" PORT="post_id"><I>post_id</I></TD>"#));
+        assert!(out.contains(r#"<TD ALIGN="LEFT"><I>user_id</I></TD>"#));
+    }
+
+    #[test]
+    fn clusters() {
+        let s = r#"
+[customers] {group: "sales"}
+*id
+[billing.invoices]
+*id
+[orders] {group: "sales", description: "Placed orders"}
+*id
+[products]
+"#;
+        let erd = parse_erd_with(s, &ParseOptions { ident_chars: ".".to_owned(), ..Default::default() }).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"    subgraph "cluster_sales" {
+        label="sales";
+        style="rounded";
+        color="gray70";
+        "customers" [
+            label=<"#), "{}", out);
+        assert!(out.contains(r#"</FONT>
+>];
+        "orders" [
+            tooltip="Placed orders",
+            label=<"#));
+        assert!(out.contains("</FONT>\n>];\n    }\n    \"billing.invoices\" [\n"));
+        assert!(!out.contains("cluster_billing"));
+
+        let options = RenderOptions {
+            schema_clusters: true,
+            cluster_background: Some("#f8f8f8".to_owned()),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains("    subgraph \"cluster_billing\" {\n        label=\"billing\";\n        style=\"rounded\";\n        color=\"gray70\";\n        bgcolor=\"#f8f8f8\";\n        \"billing.invoices\" [\n"), "{}", out);
+        assert!(format::format_erd(&erd).starts_with("[customers] {group: \"sales\"}\n"));
+    }
+
+    #[test]
+    fn legend() {
+        let erd = parse_erd("[a]\n*id\n[b]\n+a_id\na 1--* b\n").unwrap();
+        let options = RenderOptions { legend: true, ..Default::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options.clone()).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.ends_with(r#"    "erd-legend" [
+        label=<
+<FONT FACE="Helvetica">
+  <TABLE BORDER="0" CELLBORDER="1" CELLPADDING="4" CELLSPACING="0">
+    <TR><TD COLSPAN="2"><B>Legend</B></TD></TR>
+    <TR><TD><U>id</U></TD><TD ALIGN="LEFT">primary key</TD></TR>
+    <TR><TD><I>id</I></TD><TD ALIGN="LEFT">foreign key</TD></TR>
+    <TR><TD><B>id</B></TD><TD ALIGN="LEFT">unique</TD></TR>
+    <TR><TD>1</TD><TD ALIGN="LEFT">exactly one</TD></TR>
+    <TR><TD>{0,1}</TD><TD ALIGN="LEFT">zero or one</TD></TR>
+    <TR><TD>0..N</TD><TD ALIGN="LEFT">zero or more</TD></TR>
+    <TR><TD>1..N</TD><TD ALIGN="LEFT">one or more</TD></TR>
+    <TR><TD>(m,n)</TD><TD ALIGN="LEFT">from m to n</TD></TR>
+  </TABLE>
+</FONT>
+>];
+    { rank=sink; "erd-legend"; }
+}
+"#), "{}", out);
+
+        let erd = parse_erd("[a]\n[b]\na 1--* b {notation: \"crows-foot\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD>crow's foot</TD><TD ALIGN="LEFT">many</TD></TR>"#), "{}", out);
+        assert!(!out.contains("exactly one"));
+    }
+
+    #[test]
+    fn notes() {
+        let s = "[orders]\n*id\nnote \"Never \\\"deleted\\\"\" {attached: \"orders\"}\nnote \"Draft\" {bgcolor: \"pink\"}\n";
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.ends_with(r#"    "note:0" [ shape=note, style=filled, fillcolor="lightyellow", fontname="Helvetica", label="Never \"deleted\"" ];
+    "note:0" -- "orders" [ style=dotted ];
+    "note:1" [ shape=note, style=filled, fillcolor="pink", fontname="Helvetica", label="Draft" ];
+}
+"#));
+        assert!(format::format_erd(&erd).ends_with("note \"Never \\\"deleted\\\"\" {attached: \"orders\"}\nnote \"Draft\" {bgcolor: \"pink\"}\n"));
+    }
+
+    #[test]
+    fn weak_entities() {
+        let s = r#"
+[order]
+*id
+[[order_item]]
+*line
+order 1--* order_item {identifying: "true"}
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert_eq!(out.matches(r#"<TABLE BORDER="1" CELLBORDER="0" CELLPADDING="2" CELLSPACING="0"><TR><TD><TABLE"#).count(), 1);
+        assert!(out.contains("  </TABLE></TD></TR></TABLE>\n</FONT>\n>];\n"));
+        assert!(out.contains(r#"    "order" -- "order_item" [ headlabel="0..N", taillabel="1", style=solid ];"#));
+
+        let formatted = format::format_erd(&erd);
+        assert!(formatted.contains("[[order_item]]\n"));
+        assert!(formatted.ends_with("order 1==* order_item\n"));
+    }
+
+    #[test]
+    fn inheritance() {
+        let erd = parse_erd("[person]\n*id\n[employee]\n*id\nperson ^-- employee {label: \"is a\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains(
+            r#"    "person" -- "employee" [ dir=back, arrowtail=empty, style=solid, label="is a" ];"#
+        ));
+        assert_eq!(format::format_erd(&erd).lines().last(), Some(r#"person ^-- employee {label: "is a"}"#));
+    }
+
+    #[test]
+    fn self_loops() {
+        let s = r#"
+[employee]
+*id
++manager_id
+[team]
+*id
+employee 1--* employee {label: "manages"}
+employee ?--1 employee {tail-port: "n", head-port: "s"}
+employee *--1 team {head-port: "w"}
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(
+            r#"    "employee" -- "employee" [ headlabel="0..N", taillabel="1", label="manages", tailport=ne, headport=se ];"#
+        ));
+        assert!(out.contains(r#"    "employee" -- "employee" [ headlabel="1", taillabel="{0,1}", tailport=n, headport=s ];"#));
+        assert!(out.contains(r#"    "employee" -- "team" [ headlabel="1", taillabel="0..N", headport=w ];"#));
+
+        assert_eq!(format::format_erd(&erd).lines().nth(8), Some("employee ?--1 employee {tail-port: \"n\", head-port: \"s\"}"));
+        assert!(parse_erd("[a]\na 1--1 a {head-port: \"up\"}\n").is_err());
+    }
+
+    #[test]
+    fn attribute_types() {
+        let erd = parse_erd("[a]\n*id {type: \"int\"}\nname\n[b]\n*id\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD COLSPAN="2"><B><FONT POINT-SIZE="16">a</FONT></B></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><U>id</U></TD><TD ALIGN="LEFT">int</TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT">name</TD><TD ALIGN="LEFT"></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD><B><FONT POINT-SIZE="16">b</FONT></B></TD></TR>"#));
+    }
+
+    #[test]
+    fn constraints() {
+        let s = "[a]\n*id {type: \"int\"}\n+b_id {type: \"int\", unique: \"true\"}\nname {type: \"text\"}\n[b]\nx\n";
+        let erd = parse_erd(s).unwrap();
+        let options = RenderOptions { constraints: true, ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD COLSPAN="3"><B><FONT POINT-SIZE="16">a</FONT></B></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><U>id</U></TD><TD ALIGN="LEFT">int</TD><TD ALIGN="LEFT">PK</TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><I><B>b_id</B></I></TD><TD ALIGN="LEFT">int</TD><TD ALIGN="LEFT">FK, unique</TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT">name</TD><TD ALIGN="LEFT">text</TD><TD ALIGN="LEFT"></TD></TR>"#));
+        // Nothing to list.
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT">x</TD></TR>"#));
+    }
+
+    #[test]
+    fn nullable() {
+        let erd = parse_erd("[a]\n*id\n[b]\n+a_id -> a.id {nullable: \"true\"}\nnote {nullable: \"false\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT" PORT="a_id"><I>a_id</I> <FONT COLOR="gray50"><I>NULL</I></FONT></TD></TR>"#), "{}", out);
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT">note</TD></TR>"#));
+        assert!(out.contains(r#"    "a" -- "b" [ headlabel="0..N", taillabel="{0,1}", tailport=id, headport=a_id ];"#));
+
+        let options = RenderOptions { nullable: false, ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert!(!from_utf8(&buf).unwrap().contains("NULL"));
+        assert!(format::format_erd(&erd).contains("  +a_id -> a.id {nullable: \"true\"}\n  note\n"));
+        assert!(parse_erd("[a]\nx {nullable: \"maybe\"}\n").is_err());
+    }
+
+    #[test]
+    fn key_badges() {
+        let erd = parse_erd("[a]\n*id {type: \"int\"}\n*+b_id\nname\n[b]\nx\n").unwrap();
+        let options = RenderOptions { keys: KeyStyle::Badges, ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD COLSPAN="3"><B><FONT POINT-SIZE="16">a</FONT></B></TD></TR>"#), "{}", out);
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><B>PK</B></TD><TD ALIGN="LEFT">id</TD><TD ALIGN="LEFT">int</TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><B>PK FK</B></TD><TD ALIGN="LEFT">b_id</TD><TD ALIGN="LEFT"></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"></TD><TD ALIGN="LEFT">name</TD><TD ALIGN="LEFT"></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT">x</TD></TR>"#));
+
+        let options = RenderOptions { keys: KeyStyle::Both, legend: true, ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><B>PK FK</B></TD><TD ALIGN="LEFT"><I><U>b_id</U></I></TD><TD ALIGN="LEFT"></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD><B>PK</B></TD><TD ALIGN="LEFT">primary key</TD></TR>"#));
+    }
+
+    #[test]
+    fn unique_attributes() {
+        let erd = parse_erd("[a]\n*id\nemail {unique: \"true\"}\n+code {unique: \"true\", label: \"c\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><B>email</B></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><I><B>code</B></I> [c]</TD></TR>"#));
+    }
+
+    #[test]
+    fn composite_primary_key() {
+        let erd = parse_erd("[a] {pk: \"order_id, line_no\"}\norder_id {label: \"o\"}\nline_no {label: \"l\"}\nqty {label: \"q\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><U>order_id</U> [o]</TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><U>line_no</U> [l]</TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT">qty [q]</TD></TR>"#));
+    }
+
+    #[test]
+    fn indexes() {
+        let erd = parse_erd("[a]\n*id\nname {type: \"text\"}\nindex {columns: \"name, id\", unique: \"true\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        assert!(!from_utf8(&buf).unwrap().contains("index"));
+
+        let options = RenderOptions { indexes: true, ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        assert!(from_utf8(&buf).unwrap().contains(
+            r#"<TR><TD ALIGN="LEFT" COLSPAN="2"><I>unique index (name, id)</I></TD></TR>"#
+        ));
+    }
+
+    #[test]
+    fn descriptions() {
+        let s = "[a] {description: \"An 'a'\"}\n*id {description: \"Key\"}\nname {type: \"text\"}\n[b]\nx\n";
+        let erd = parse_erd(s).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains("    \"a\" [\n        tooltip=\"An 'a'\",\n        label=<"));
+        assert!(!out.contains("Key"));
+
+        let options = RenderOptions { descriptions: true, ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD COLSPAN="3"><B><FONT POINT-SIZE="16">a</FONT></B></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT"><U>id</U></TD><TD ALIGN="LEFT"></TD><TD ALIGN="LEFT">Key</TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD ALIGN="LEFT">x</TD></TR>"#));
+    }
+
+    #[test]
+    fn tooltips() {
+        let s = "[a] {description: \"An 'a'\"}\n*id {description: \"Key\"}\n\n[b]\n*id {type: \"int\"}\n+a_id {nullable: \"true\"}\na 1--* b {label: \"has\"}\n";
+        let erd = parse_erd(s).unwrap();
+        let options = RenderOptions { tooltips: true, ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains("    \"a\" [\n        tooltip=\"An 'a'\",\n        label=<"), "{}", out);
+        assert!(out.contains("    \"b\" [\n        tooltip=\"b: 2 attributes, primary key (id)\",\n        label=<"));
+        assert!(out.contains(r#"<TD ALIGN="LEFT" TOOLTIP="Key"><U>id</U></TD>"#));
+        assert!(out.contains(r#"<TD ALIGN="LEFT" TOOLTIP="id: int (PK)"><U>id</U></TD>"#));
+        assert!(out.contains(r#"<TD ALIGN="LEFT" TOOLTIP="a_id (FK, nullable)"><I>a_id</I>"#));
+        assert!(out.contains(r#"tooltip="a 1--0..N b: has""#));
+    }
+
+    #[test]
+    fn url() {
+        let erd = parse_erd("[a] {href: \"https://example.com/a?x=1&y=2\"}\nid\n[b]\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains("    \"a\" [\n        URL=\"https://example.com/a?x=1&y=2\",\n        label=<"), "{}", out);
+        assert!(out.contains("    \"b\" [\n        label=<"));
+    }
+
+    #[test]
+    fn attribute_filter() {
+        let s = "[a]\n*id\nname\n[b]\n*id\nemail {unique: \"true\"}\n+a_id -> a.id\nnote\n";
+        let erd = parse_erd(s).unwrap();
+        let options = RenderOptions { attributes: ast::AttributeFilter::Keys, ..RenderOptions::default() };
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TD ALIGN="LEFT" PORT="id"><U>id</U></TD>"#), "{}", out);
+        assert!(out.contains(r#"<TD ALIGN="LEFT" PORT="a_id"><I>a_id</I></TD>"#));
+        assert!(!out.contains("name") && !out.contains("email") && !out.contains("note"));
+
+        let erd = parse_erd(&format!("graph {{attributes: \"none\"}}\n{}", s)).unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(!out.contains("<TD ALIGN"));
+        assert!(!out.contains("port="));
+        assert!(out.contains(r#""a" -- "b" [ "#));
+    }
+
+    #[test]
+    fn header_colors() {
+        let erd = parse_erd("header {bgcolor: \"black\", color: \"white\"}\n[a]\n[b] {bgcolor: \"black\"}\n").unwrap();
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = from_utf8(&buf).unwrap();
+        assert!(out.contains(r#"<TR><TD BGCOLOR="black"><B><FONT POINT-SIZE="16" COLOR="white">a</FONT></B></TD></TR>"#));
+        assert!(out.contains(r#"<TR><TD><B><FONT POINT-SIZE="16" COLOR="white">b</FONT></B></TD></TR>"#));
+    }
+
     #[test]
     fn test_empty_graph_with_opts() {
         let mut buf = Vec::new();
@@ -0,0 +1,195 @@
+use std::io::Read;
+use erd::{ast::GlobalOption, chen, parser, render, uml, Severity};
+use tiny_http::{Header, Method, Response};
+use crate::exit::{Failure, Verbosity};
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY: u64 = 1024 * 1024;
+
+/// HTTP server rendering `.er` source posted to `/render`.
+///
+/// The output format, edge type and layout direction can be set per request
+/// with the `format`, `edge`, `rankdir` and `layout` query parameters, e.g.
+/// `POST /render?format=dot&rankdir=TB`.
+pub struct Server {
+    /// Render options used unless overridden by the request.
+    pub options: render::RenderOptions,
+    /// Default options for the posted source, which its own override.
+    pub defaults: Vec<GlobalOption>,
+    /// Characters allowed in unquoted identifiers in the posted source.
+    pub ident_chars: String,
+    pub verbosity: Verbosity,
+}
+
+/// Response to a request, before it's sent.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Reply {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Reply {
+    fn ok(content_type: &'static str, body: String) -> Self {
+        Self { status: 200, content_type, body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        let mut body = message.into();
+        body.push('\n');
+        Self { status, content_type: "text/plain; charset=utf-8", body }
+    }
+}
+
+impl Server {
+    /// Listens on the given address, handling requests one at a time until
+    /// the process is stopped.
+    pub fn run(&self, addr: &str) -> Result<(), Failure> {
+        let server = tiny_http::Server::http(addr)
+            .map_err(|err| Failure::Io(format!("Failed to listen on {}: {}", addr, err)))?;
+        if self.verbosity > Verbosity::Quiet {
+            eprintln!("Listening on http://{}", addr);
+        }
+
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            let reply = match request.as_reader().take(MAX_BODY + 1).read_to_string(&mut body) {
+                Ok(n) if n as u64 > MAX_BODY => Reply::error(413, format!("Request body is over {} bytes", MAX_BODY)),
+                Ok(_) => self.handle(request.method(), request.url(), &body),
+                Err(err) => Reply::error(400, format!("Failed to read request body: {}", err)),
+            };
+            if self.verbosity == Verbosity::Verbose {
+                eprintln!("{} {} {}", request.method(), request.url(), reply.status);
+            }
+
+            let header = Header::from_bytes("Content-Type", reply.content_type).expect("valid header");
+            let response = Response::from_string(reply.body)
+                .with_status_code(reply.status)
+                .with_header(header);
+            if let Err(err) = request.respond(response) {
+                eprintln!("Failed to send response: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a single request.
+    pub fn handle(&self, method: &Method, url: &str, body: &str) -> Reply {
+        let (path, query) = match url.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (url, ""),
+        };
+        if path != "/render" {
+            return Reply::error(404, format!("Not found: {}", path));
+        }
+        if *method != Method::Post {
+            return Reply::error(405, "Use POST to render .er source sent as the request body");
+        }
+
+        let mut options = self.options.clone();
+        let mut format = "dot";
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match key {
+                "format" => format = value,
+                "edge" => match render::splines_for_edge_type(value) {
+                    Some(splines) => options.splines = splines.to_owned(),
+                    None => return Reply::error(400, format!("Unknown edge type: {}", value)),
+                },
+                "rankdir" if render::RANKDIRS.contains(&value) => options.rankdir = value.to_owned(),
+                "rankdir" => return Reply::error(400, format!("Unknown rankdir: {}", value)),
+                "layout" if render::LAYOUTS.contains(&value) => options.layout = Some(value.to_owned()),
+                "layout" => return Reply::error(400, format!("Unknown layout: {}", value)),
+                _ => return Reply::error(400, format!("Unknown parameter: {}", key)),
+            }
+        }
+
+        let mut backends = render::Backends::default();
+        backends.register(Box::new(chen::Chen { options: options.clone() }));
+        backends.register(Box::new(uml::Uml { options: options.clone() }));
+        backends.register(Box::new(render::Dot { options }));
+        let backend = match backends.get(format) {
+            Some(b) => b,
+            None => return Reply::error(400, format!("Unknown output format: {}", format)),
+        };
+
+        // Without a path, includes aren't resolved, so requests can't read
+        // files from the server.
+        let parse_options = parser::ParseOptions {
+            defaults: self.defaults.clone(),
+            path: None,
+            ident_chars: self.ident_chars.clone(),
+            ..Default::default()
+        };
+        let erd = match parser::parse_erd_with(body, &parse_options) {
+            Ok(erd) => erd,
+            Err(err) => return Reply::error(400, format!("Failed to parse input: {}", err)),
+        };
+        let errors: Vec<_> = erd.validate().into_iter()
+            .filter(|d| d.severity >= Severity::Error)
+            .map(|d| d.to_string())
+            .collect();
+        if !errors.is_empty() {
+            return Reply::error(422, errors.join("\n"));
+        }
+
+        let mut output = Vec::new();
+        if let Err(err) = backend.render(&erd, &mut output) {
+            return Reply::error(500, format!("Failed to render: {}", err));
+        }
+        let content_type = match format {
+            "dot" => "text/vnd.graphviz; charset=utf-8",
+            _ => "text/plain; charset=utf-8",
+        };
+        Reply::ok(content_type, String::from_utf8_lossy(&output).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server {
+        Server {
+            options: render::RenderOptions::default(),
+            defaults: Vec::new(),
+            ident_chars: String::new(),
+            verbosity: Verbosity::Quiet,
+        }
+    }
+
+    #[test]
+    fn render() {
+        let reply = server().handle(&Method::Post, "/render?rankdir=TB", "[a]\n*id\n");
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.content_type, "text/vnd.graphviz; charset=utf-8");
+        assert!(reply.body.contains("rankdir=TB"));
+
+        let reply = server().handle(&Method::Post, "/render?layout=neato", "[a]\n*id\n");
+        assert!(reply.body.contains("        layout=neato,\n        overlap=false,\n"), "{}", reply.body);
+
+        let reply = server().handle(&Method::Post, "/render?format=er", "[a]\n*id\n");
+        assert_eq!(reply, Reply::ok("text/plain; charset=utf-8", "[a]\n  *id\n\n".to_owned()));
+    }
+
+    #[test]
+    fn ident_chars() {
+        let s = Server { ident_chars: ".".to_owned(), ..server() };
+        let reply = s.handle(&Method::Post, "/render?format=er", "[auth.users]\n*id\n");
+        assert_eq!(reply, Reply::ok("text/plain; charset=utf-8", "[\"auth.users\"]\n  *id\n\n".to_owned()));
+        assert_eq!(server().handle(&Method::Post, "/render", "[auth.users]\n*id\n").status, 400);
+    }
+
+    #[test]
+    fn errors() {
+        let s = server();
+        let status = |method, url, body| s.handle(&method, url, body).status;
+        assert_eq!(status(Method::Post, "/", "[a]"), 404);
+        assert_eq!(status(Method::Get, "/render", ""), 405);
+        assert_eq!(status(Method::Post, "/render?format=png", "[a]"), 400);
+        assert_eq!(status(Method::Post, "/render?edge=wavy", "[a]"), 400);
+        assert_eq!(status(Method::Post, "/render?colour=red", "[a]"), 400);
+        assert_eq!(status(Method::Post, "/render", "[a"), 400);
+        assert_eq!(status(Method::Post, "/render", "[a]\nx\nx\n"), 422);
+    }
+}
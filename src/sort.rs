@@ -0,0 +1,64 @@
+use crate::ast::{Entity, Erd};
+
+/// Order to sort an entity's attributes into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttributeOrder {
+    /// By field name.
+    Alphabetical,
+    /// Primary keys, then foreign keys, then everything else, otherwise
+    /// keeping the order they were declared in.
+    KeysFirst,
+}
+
+impl Erd {
+    /// Sorts entities by name. Relationships are left in declaration order.
+    pub fn sort_entities(&mut self) {
+        self.entities.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Sorts the attributes of every entity.
+    pub fn sort_attributes(&mut self, order: AttributeOrder) {
+        for e in &mut self.entities {
+            e.sort_attributes(order);
+        }
+    }
+}
+
+impl Entity {
+    pub fn sort_attributes(&mut self, order: AttributeOrder) {
+        match order {
+            AttributeOrder::Alphabetical => self.attribs.sort_by(|a, b| a.field.cmp(&b.field)),
+            AttributeOrder::KeysFirst => {
+                let primary_key = &self.primary_key;
+                self.attribs.sort_by_key(|a| (!(a.pk || primary_key.iter().any(|k| *k == a.field)), !a.fk))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_erd;
+    use super::*;
+
+    fn fields(erd: &Erd) -> Vec<Vec<&str>> {
+        erd.entities.iter()
+            .map(|e| e.attribs.iter().map(|a| a.field.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn sort() {
+        let s = "[b]\nname\n+team\n*id\nage\n*+pk_fk\n[a] {pk: \"y\"}\nz\ny\n";
+
+        let mut erd = parse_erd(s).unwrap();
+        erd.sort_attributes(AttributeOrder::KeysFirst);
+        assert_eq!(fields(&erd), vec![vec!["pk_fk", "id", "team", "name", "age"], vec!["y", "z"]]);
+
+        let mut erd = parse_erd(s).unwrap();
+        erd.sort_attributes(AttributeOrder::Alphabetical);
+        erd.sort_entities();
+        assert_eq!(erd.entities[0].name, "a");
+        assert_eq!(fields(&erd), vec![vec!["y", "z"], vec!["age", "id", "name", "pk_fk", "team"]]);
+    }
+}
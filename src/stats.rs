@@ -0,0 +1,133 @@
+use std::{cmp::Reverse, fmt};
+use crate::ast::{Cardinality, Erd, RelationKind};
+use crate::format::{cardinality_symbol, quote_ident};
+
+/// How many of the largest entities are listed.
+const LARGEST: usize = 5;
+
+/// Summary of a diagram's size and shape.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub entities: usize,
+    pub attributes: usize,
+    pub primary_keys: usize,
+    pub foreign_keys: usize,
+    pub relationships: usize,
+    /// Number of relationships with each pair of cardinalities, most common
+    /// first. Inheritance relationships aren't included.
+    pub cardinalities: Vec<((Cardinality, Cardinality), usize)>,
+    /// Entities which aren't part of any relationship.
+    pub isolated: Vec<String>,
+    /// Names and attribute counts of the entities with the most attributes,
+    /// largest first.
+    pub largest: Vec<(String, usize)>,
+}
+
+impl Erd {
+    /// Counts the diagram's entities, attributes and relationships.
+    pub fn stats(&self) -> Stats {
+        let attribs = || self.entities.iter().flat_map(|e| &e.attribs);
+
+        let mut cardinalities: Vec<((Cardinality, Cardinality), usize)> = Vec::new();
+        for r in self.relationships.iter().filter(|r| r.kind == RelationKind::Association) {
+            let pair = (r.card1, r.card2);
+            match cardinalities.iter_mut().find(|(p, _)| *p == pair) {
+                Some((_, n)) => *n += 1,
+                None => cardinalities.push((pair, 1)),
+            }
+        }
+        // Stable, so equally common pairs stay in the order first seen.
+        cardinalities.sort_by_key(|(_, n)| Reverse(*n));
+
+        let mut largest: Vec<_> = self.entities.iter()
+            .map(|e| (e.name.to_string(), e.attribs.len()))
+            .collect();
+        largest.sort_by_key(|(_, n)| Reverse(*n));
+        largest.truncate(LARGEST);
+
+        Stats {
+            entities: self.entities.len(),
+            attributes: attribs().count(),
+            primary_keys: self.entities.iter()
+                .map(|e| e.attribs.iter().filter(|a| e.is_primary_key(a)).count())
+                .sum(),
+            foreign_keys: attribs().filter(|a| a.fk).count(),
+            relationships: self.relationships.len(),
+            cardinalities,
+            isolated: self.entities.iter()
+                .filter(|e| self.relationships_for(&e.name).next().is_none())
+                .filter(|e| !self.nary_relationships.iter().any(|r| r.entities.contains(&e.name)))
+                .map(|e| e.name.to_string())
+                .collect(),
+            largest,
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Entities: {}", self.entities)?;
+        writeln!(
+            f,
+            "Attributes: {} ({} primary keys, {} foreign keys)",
+            self.attributes, self.primary_keys, self.foreign_keys,
+        )?;
+        writeln!(f, "Relationships: {}", self.relationships)?;
+        for ((card1, card2), n) in &self.cardinalities {
+            writeln!(f, "    {}--{}  {}", cardinality_symbol(*card1), cardinality_symbol(*card2), n)?;
+        }
+
+        let isolated: Vec<_> = self.isolated.iter().map(|e| quote_ident(e)).collect();
+        writeln!(
+            f,
+            "Entities without relationships: {}",
+            if isolated.is_empty() { "none".to_owned() } else { isolated.join(", ") },
+        )?;
+
+        writeln!(f, "Largest entities:")?;
+        for (entity, n) in &self.largest {
+            writeln!(f, "    {}  {}", quote_ident(entity), n)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use crate::parser::parse_erd;
+
+    #[test]
+    fn stats() {
+        let erd = parse_erd(r#"
+[a]
+*id
+name
+[b]
+*id
++a_id
+[c] {pk: "x"}
+[d] {pk: "x, y"}
+x
+y
+z
+a 1--* b
+b 1--* a
+a ?--1 b
+a ^-- b
+"#).unwrap();
+
+        assert_eq!(erd.stats().to_string(), r#"Entities: 4
+Attributes: 7 (4 primary keys, 1 foreign keys)
+Relationships: 4
+    1--*  2
+    ?--1  1
+Entities without relationships: c, d
+Largest entities:
+    d  3
+    a  2
+    b  2
+    c  0
+"#);
+    }
+}
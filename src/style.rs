@@ -0,0 +1,160 @@
+use crate::ast::{Attribute, Entity, Relation};
+use crate::render::RenderOptions;
+
+/// Font size used where neither the diagram nor its directives set one.
+pub const DEFAULT_FONT_SIZE: u8 = 14;
+/// Text color used where neither the diagram nor its directives set one.
+pub const DEFAULT_COLOR: &str = "black";
+/// Background of notes which don't set one.
+pub const NOTE_COLOR: &str = "lightyellow";
+
+/// Final styling of a diagram element, after global directives, the
+/// element's own options, inherited values and defaults have been combined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedStyle {
+    /// Extra label text, if any.
+    pub label: Option<String>,
+    pub font: String,
+    pub font_size: u8,
+    pub color: String,
+    pub background_color: Option<String>,
+    pub border: u8,
+    pub border_color: Option<String>,
+}
+
+/// Computes resolved styles for the elements of a diagram.
+pub struct StyleResolver<'a> {
+    options: &'a RenderOptions,
+}
+
+impl<'a> StyleResolver<'a> {
+    pub fn new(options: &'a RenderOptions) -> Self {
+        Self { options }
+    }
+
+    /// Style of an entity's table as a whole.
+    pub fn entity(&self, e: &Entity) -> ResolvedStyle {
+        let o = &e.options;
+        ResolvedStyle {
+            label: o.label.clone(),
+            font: o.font.clone().unwrap_or_else(|| self.options.font.clone()),
+            font_size: o.size.unwrap_or(DEFAULT_FONT_SIZE),
+            color: o.color.clone().unwrap_or_else(|| DEFAULT_COLOR.to_owned()),
+            background_color: o.background_color.clone(),
+            border: o.border,
+            border_color: o.border_color.clone(),
+        }
+    }
+
+    /// Style of an entity's header row, inheriting its font and color from
+    /// the entity.
+    pub fn header(&self, e: &Entity) -> ResolvedStyle {
+        let entity = self.entity(e);
+        let o = &e.header_options;
+        ResolvedStyle {
+            label: o.label.clone(),
+            font: o.font.clone().unwrap_or(entity.font),
+            font_size: o.size,
+            color: o.color.clone().unwrap_or(entity.color),
+            background_color: o.background_color.clone().or_else(|| e.options.background_color.clone()),
+            border: o.border,
+            border_color: o.border_color.clone(),
+        }
+    }
+
+    /// Style of an attribute row, inheriting anything it doesn't set from
+    /// its entity.
+    pub fn attribute(&self, e: &Entity, a: &Attribute) -> ResolvedStyle {
+        let entity = self.entity(e);
+        let o = &a.options;
+        ResolvedStyle {
+            label: o.label.clone(),
+            font: o.font.clone().unwrap_or(entity.font),
+            font_size: entity.font_size,
+            color: o.color.clone().unwrap_or(entity.color),
+            background_color: o.background_color.clone().or(entity.background_color),
+            border: o.border.unwrap_or(e.options.cell_border),
+            border_color: o.border_color.clone().or(entity.border_color),
+        }
+    }
+
+    /// Style of a relationship's edge. Edges have no background or border.
+    pub fn relationship(&self, r: &Relation) -> ResolvedStyle {
+        let o = &r.options;
+        ResolvedStyle {
+            label: o.label.clone(),
+            font: o.font.clone().unwrap_or_else(|| self.options.font.clone()),
+            font_size: o.size.unwrap_or(DEFAULT_FONT_SIZE),
+            color: o.color.clone().unwrap_or_else(|| self.options.edge_color.clone()),
+            background_color: None,
+            border: 0,
+            border_color: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_erd;
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let erd = parse_erd("[a]\n*id\na 1--1 a\n").unwrap();
+        let opts = RenderOptions::default();
+        let r = StyleResolver::new(&opts);
+        let e = &erd.entities[0];
+
+        let expected = ResolvedStyle {
+            label: None,
+            font: "Helvetica".to_owned(),
+            font_size: 14,
+            color: "black".to_owned(),
+            background_color: None,
+            border: 0,
+            border_color: None,
+        };
+        assert_eq!(r.entity(e), expected);
+        assert_eq!(r.header(e), ResolvedStyle { font_size: 16, ..expected.clone() });
+        assert_eq!(r.attribute(e, &e.attribs[0]), ResolvedStyle { border: 1, ..expected.clone() });
+        assert_eq!(r.relationship(&erd.relationships[0]), ResolvedStyle { color: "gray50".to_owned(), ..expected });
+    }
+
+    #[test]
+    fn directives_and_inheritance() {
+        let s = r##"
+entity {bgcolor: "#eeeeee", font: "Courier"}
+header {color: "white", bgcolor: "#333333"}
+relationship {color: "blue"}
+[a] {color: "#111111"}
+*id {font: "Times", border: "2"}
+name {bgcolor: "yellow"}
+a 1--1 a {label: "self"}
+"##;
+        let erd = parse_erd(s).unwrap();
+        let opts = RenderOptions { font: "Arial".to_owned(), ..Default::default() };
+        let r = StyleResolver::new(&opts);
+        let e = &erd.entities[0];
+
+        let entity = r.entity(e);
+        assert_eq!(entity.font, "Courier");
+        assert_eq!(entity.color, "#111111");
+        assert_eq!(entity.background_color.as_deref(), Some("#eeeeee"));
+
+        let header = r.header(e);
+        assert_eq!(header.color, "white");
+        assert_eq!(header.background_color.as_deref(), Some("#333333"));
+        assert_eq!(header.font, "Courier");
+
+        let id = r.attribute(e, &e.attribs[0]);
+        assert_eq!((id.font.as_str(), id.color.as_str(), id.border), ("Times", "#111111", 2));
+        assert_eq!(id.background_color.as_deref(), Some("#eeeeee"));
+
+        let name = r.attribute(e, &e.attribs[1]);
+        assert_eq!(name.background_color.as_deref(), Some("yellow"));
+        assert_eq!(name.font, "Courier");
+
+        let rel = r.relationship(&erd.relationships[0]);
+        assert_eq!((rel.label.as_deref(), rel.color.as_str()), (Some("self"), "blue"));
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use crate::ast::{Erd, Name};
+
+impl Erd {
+    /// Returns the part of the diagram within `depth` relationships of the
+    /// named entity, or `None` if there's no such entity.
+    ///
+    /// Relationships are followed in either direction. Entities keep their
+    /// declaration order, and only relationships between kept entities are
+    /// included. An n-ary relationship counts as one step to each of its
+    /// entities. Enums are kept if a kept entity's attribute has them as its
+    /// type, and notes if they're attached to a kept entity or to none. The
+    /// title is kept as is.
+    pub fn neighborhood(&self, name: &str, depth: usize) -> Option<Erd> {
+        self.entity(name)?;
+
+        let mut kept: HashSet<&str> = HashSet::new();
+        kept.insert(name);
+        let mut frontier = vec![name];
+        for _ in 0..depth {
+            let mut next = Vec::new();
+            for n in frontier {
+                let others = self.relationships_for(n)
+                    .flat_map(|r| vec![r.entity1.as_str(), r.entity2.as_str()])
+                    .chain(self.nary_relationships.iter()
+                        .filter(|r| r.entities.iter().any(|e| e == n))
+                        .flat_map(|r| r.entities.iter().map(Name::as_str)));
+                for other in others {
+                    if kept.insert(other) {
+                        next.push(other);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+
+        let entities: Vec<_> = self.entities.iter()
+            .filter(|e| kept.contains(e.name.as_str()))
+            .cloned()
+            .collect();
+        let enums = self.enums.iter()
+            .filter(|t| entities.iter().flat_map(|e| &e.attribs).any(|a| a.datatype.as_ref() == Some(&t.name)))
+            .cloned()
+            .collect();
+        Some(Erd {
+            entities,
+            relationships: self.relationships.iter()
+                .filter(|r| kept.contains(r.entity1.as_str()) && kept.contains(r.entity2.as_str()))
+                .cloned()
+                .collect(),
+            nary_relationships: self.nary_relationships.iter()
+                .filter(|r| r.entities.iter().all(|e| kept.contains(e.as_str())))
+                .cloned()
+                .collect(),
+            enums,
+            notes: self.notes.iter()
+                .filter(|n| n.attached.as_ref().is_none_or(|e| kept.contains(e.as_str())))
+                .cloned()
+                .collect(),
+            title_options: self.title_options.clone(),
+            graph_options: self.graph_options.clone(),
+            includes: self.includes.clone(),
+            variables: self.variables.clone(),
+            ignored_options: self.ignored_options.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_erd;
+
+    #[test]
+    fn neighborhood() {
+        let erd = parse_erd(r#"
+[a]
+[b]
+[c]
+[d]
+[e]
+a 1--* b
+c 1--* b
+c 1--1 d
+e 1--1 e
+r(d, e, e)
+"#).unwrap();
+
+        let names = |depth| {
+            let n = erd.neighborhood("b", depth).unwrap();
+            let entities: Vec<_> = n.entities.iter().map(|e| e.name.to_string()).collect();
+            (entities, n.relationships.len())
+        };
+        assert_eq!(names(0), (vec!["b".to_owned()], 0));
+        assert_eq!(names(1), (vec!["a".to_owned(), "b".to_owned(), "c".to_owned()], 2));
+        assert_eq!(names(2), (vec!["a".to_owned(), "b".to_owned(), "c".to_owned(), "d".to_owned()], 3));
+        assert_eq!(names(10).0.len(), 5);
+
+        let n = erd.neighborhood("d", 1).unwrap();
+        assert_eq!(n.entities.len(), 3);
+        assert_eq!(n.nary_relationships.len(), 1);
+
+        assert!(erd.neighborhood("nope", 1).is_none());
+    }
+}
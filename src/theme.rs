@@ -0,0 +1,197 @@
+use crate::ast::{GlobalOption, GlobalOptionType, OptionMap, Span, OPT_BACKGROUND_COLOR, OPT_BORDER_COLOR, OPT_COLOR};
+use crate::render::RenderOptions;
+
+/// A named set of coordinated colors and fonts, made up of render defaults
+/// and default options for the input, which its own options override.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub font: &'static str,
+    pub edge_color: &'static str,
+    pub edge_style: &'static str,
+    /// Color of the cardinalities and labels along edges, if not black.
+    pub edge_font_color: Option<&'static str>,
+    /// Color of the diagram's background, if not white.
+    pub background: Option<&'static str>,
+    pub title: &'static [(&'static str, &'static str)],
+    pub header: &'static [(&'static str, &'static str)],
+    pub entity: &'static [(&'static str, &'static str)],
+}
+
+/// Built-in themes. The first is the default, matching the original `erd`.
+pub const THEMES: &[Theme] = &[
+    Theme {
+        name: "classic",
+        font: "Helvetica",
+        edge_color: "gray50",
+        edge_style: "dashed",
+        edge_font_color: None,
+        background: None,
+        title: &[],
+        header: &[],
+        entity: &[],
+    },
+    Theme {
+        name: "monochrome",
+        font: "Helvetica",
+        edge_color: "black",
+        edge_style: "solid",
+        edge_font_color: None,
+        background: None,
+        title: &[],
+        header: &[(OPT_BACKGROUND_COLOR, "#d9d9d9")],
+        entity: &[(OPT_BACKGROUND_COLOR, "#f5f5f5")],
+    },
+    Theme {
+        name: "pastel",
+        font: "Helvetica",
+        edge_color: "#8e7cc3",
+        edge_style: "solid",
+        edge_font_color: None,
+        background: None,
+        title: &[],
+        header: &[(OPT_BACKGROUND_COLOR, "#cfe2f3"), (OPT_COLOR, "#3d5a80")],
+        entity: &[(OPT_BACKGROUND_COLOR, "#fdf6e3")],
+    },
+    Theme {
+        name: "high-contrast",
+        font: "Helvetica-Bold",
+        edge_color: "black",
+        edge_style: "solid",
+        edge_font_color: None,
+        background: None,
+        title: &[],
+        header: &[(OPT_BACKGROUND_COLOR, "black"), (OPT_COLOR, "white")],
+        entity: &[(OPT_BACKGROUND_COLOR, "white")],
+    },
+    Theme {
+        name: "dark",
+        font: "Helvetica",
+        edge_color: "#8b949e",
+        edge_style: "dashed",
+        edge_font_color: Some("#c9d1d9"),
+        background: Some("#0d1117"),
+        title: &[(OPT_COLOR, "#e6edf3")],
+        header: &[(OPT_BACKGROUND_COLOR, "#30363d"), (OPT_COLOR, "#e6edf3")],
+        entity: &[(OPT_BACKGROUND_COLOR, "#161b22"), (OPT_COLOR, "#c9d1d9"), (OPT_BORDER_COLOR, "#6e7681")],
+    },
+];
+
+impl Theme {
+    /// Returns the built-in theme with the given name.
+    pub fn by_name(name: &str) -> Option<&'static Theme> {
+        THEMES.iter().find(|t| t.name == name)
+    }
+
+    /// Overrides render defaults with the theme's fonts and edge styling.
+    pub fn apply(&self, options: &mut RenderOptions) {
+        options.font = self.font.to_owned();
+        options.edge_color = self.edge_color.to_owned();
+        options.edge_style = self.edge_style.to_owned();
+        options.edge_font_color = self.edge_font_color.map(str::to_owned);
+        options.background_color = self.background.map(str::to_owned);
+    }
+
+    /// Returns default options setting the theme's colors, to be given as
+    /// [`ParseOptions::defaults`](crate::parser::ParseOptions::defaults).
+    pub fn defaults(&self) -> Vec<GlobalOption> {
+        [
+            (GlobalOptionType::Title, self.title),
+            (GlobalOptionType::Header, self.header),
+            (GlobalOptionType::Entity, self.entity),
+        ]
+            .iter()
+            .filter(|(_, options)| !options.is_empty())
+            .map(|(option_type, options)| GlobalOption {
+                option_type: *option_type,
+                options: options.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<OptionMap>(),
+                span: Span::default(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_erd_with, ParseOptions};
+    use crate::render::Renderer;
+    use super::*;
+
+    #[test]
+    fn classic_is_default() {
+        let mut options = RenderOptions::default();
+        THEMES[0].apply(&mut options);
+        assert_eq!(options, RenderOptions::default());
+        assert!(THEMES[0].defaults().is_empty());
+    }
+
+    #[test]
+    fn themes() {
+        assert!(Theme::by_name("neon").is_none());
+
+        for theme in THEMES {
+            let options = ParseOptions { defaults: theme.defaults(), ..Default::default() };
+            assert!(parse_erd_with("title {label: \"t\"}\n[a]\nx\n", &options).is_ok(), "{}", theme.name);
+        }
+
+        let theme = Theme::by_name("high-contrast").unwrap();
+        let options = ParseOptions { defaults: theme.defaults(), ..Default::default() };
+        let erd = parse_erd_with("[a]\nx\n", &options).unwrap();
+        assert_eq!(erd.entities[0].header_options.background_color.as_deref(), Some("black"));
+        assert_eq!(erd.entities[0].header_options.color.as_deref(), Some("white"));
+        assert_eq!(erd.entities[0].options.background_color.as_deref(), Some("white"));
+    }
+
+    #[test]
+    fn theme_colors_quoted() {
+        let erd = parse_erd_with("[a]\nx\n", &ParseOptions::default()).unwrap();
+        for theme in THEMES {
+            let mut options = RenderOptions::default();
+            theme.apply(&mut options);
+            let mut buf = Vec::new();
+            Renderer::with_options(&mut buf, options).render_erd(&erd).unwrap();
+            let out = std::str::from_utf8(&buf).unwrap();
+            let expected = format!("        color=\"{}\",\n", theme.edge_color);
+            assert!(out.contains(&expected), "{}: {}", theme.name, out);
+        }
+    }
+
+    #[test]
+    fn own_options_beat_theme() {
+        let theme = Theme::by_name("pastel").unwrap();
+        let options = ParseOptions { defaults: theme.defaults(), ..Default::default() };
+        let erd = parse_erd_with("[a] {bgcolor: \"red\"}\nx\n[b]\nx\n", &options).unwrap();
+        assert_eq!(erd.entities[0].options.background_color.as_deref(), Some("red"));
+        assert_eq!(erd.entities[0].header_options.background_color.as_deref(), Some("red"));
+        assert_eq!(erd.entities[0].header_options.color.as_deref(), Some("#3d5a80"));
+        assert_eq!(erd.entities[1].options.background_color.as_deref(), Some("#fdf6e3"));
+        assert_eq!(erd.entities[1].header_options.background_color.as_deref(), Some("#cfe2f3"));
+
+        let mut buf = Vec::new();
+        Renderer::new(&mut buf).render_erd(&erd).unwrap();
+        let out = std::str::from_utf8(&buf).unwrap();
+        let a = &out[out.find("\"a\" [").unwrap()..out.find("\"b\" [").unwrap()];
+        assert!(a.contains(r#"BGCOLOR="red""#), "{}", a);
+        assert!(!a.contains("#fdf6e3") && !a.contains("#cfe2f3"));
+    }
+
+    #[test]
+    fn dark() {
+        let theme = Theme::by_name("dark").unwrap();
+        let mut render_options = RenderOptions::default();
+        theme.apply(&mut render_options);
+        assert_eq!(render_options.background_color.as_deref(), Some("#0d1117"));
+
+        let options = ParseOptions { defaults: theme.defaults(), ..Default::default() };
+        let erd = parse_erd_with("title {label: \"t\"}\n[a]\nx\na 1--* a\n", &options).unwrap();
+        assert_eq!(erd.title_options.color.as_deref(), Some("#e6edf3"));
+        let mut buf = Vec::new();
+        Renderer::with_options(&mut buf, render_options).render_erd(&erd).unwrap();
+        let out = std::str::from_utf8(&buf).unwrap();
+        assert!(out.contains("        fontcolor=\"#e6edf3\",\n        bgcolor=\"#0d1117\",\n"), "{}", out);
+        assert!(out.contains("        fontcolor=\"#c9d1d9\",\n    ];"));
+        assert!(out.contains(r##"<FONT FACE="Helvetica" COLOR="#c9d1d9">"##));
+    }
+}
@@ -0,0 +1,309 @@
+//! Rendering of diagrams as UML class diagrams: each entity is a class box
+//! with compartments for its name, key and other attributes, and
+//! relationships are associations with multiplicities at each end.
+
+use std::io::{self, Result};
+use crate::ast;
+use crate::render::{constraint_names, escape_html, filter_attributes, graph_style_attrs, layout_attrs, quote, title_markup, Backend, Output, RenderOptions};
+use crate::style::NOTE_COLOR;
+
+/// Backend producing Graphviz DOT output drawn as a UML class diagram.
+#[derive(Clone, Debug, Default)]
+pub struct Uml {
+    pub options: RenderOptions,
+}
+
+impl Backend for Uml {
+    fn name(&self) -> &'static str {
+        "uml"
+    }
+
+    fn render(&self, erd: &ast::Erd, w: &mut dyn io::Write) -> Result<()> {
+        UmlRenderer::with_options(w, self.options.clone()).render_erd(erd)
+    }
+}
+
+/// Writes a diagram as DOT in the style of a UML class diagram.
+///
+/// Identifying relationships are drawn as compositions, with a filled
+/// diamond at the owning entity, and inheritance as generalisation, with a
+/// hollow triangle at the more general entity. Enums are drawn as
+/// `«enumeration»` classes.
+pub struct UmlRenderer<W: Output> {
+    w: W,
+    options: RenderOptions,
+}
+
+impl<W: Output> UmlRenderer<W> {
+    pub fn new(w: W) -> Self {
+        Self::with_options(w, RenderOptions::default())
+    }
+
+    pub fn with_options(w: W, options: RenderOptions) -> Self {
+        Self { w, options }
+    }
+
+    pub fn render_erd(&mut self, erd: &ast::Erd) -> Result<()> {
+        let indent = self.options.indent.clone();
+        writeln!(self.w, "graph {{")?;
+        writeln!(self.w, "{}graph [", indent)?;
+        if let Some(label) = &erd.title_options.label {
+            writeln!(
+                self.w,
+                "{i}{i}label=<<FONT POINT-SIZE=\"{}\">{}</FONT>>,\n{i}{i}labeljust=l,\n{i}{i}labelloc=t,",
+                erd.title_options.size,
+                title_markup(label),
+                i=indent,
+            )?;
+        }
+        let attrs = graph_style_attrs(erd, &self.options).into_iter()
+            .chain(layout_attrs(&erd.graph_options, &self.options));
+        for (k, v) in attrs {
+            writeln!(self.w, "{i}{i}{}={},", k, v, i=indent)?;
+        }
+        writeln!(self.w, "{}];", indent)?;
+        writeln!(self.w, "{i}node [\n{i}{i}fontname={},\n{i}{i}shape=plaintext,\n{i}];", quote(&self.options.font), i=indent)?;
+        writeln!(
+            self.w,
+            "{i}edge [\n{i}{i}color={},\n{i}{i}fontname={},",
+            quote(&self.options.edge_color),
+            quote(&self.options.font),
+            i=indent,
+        )?;
+        if let Some(color) = &self.options.edge_font_color {
+            writeln!(self.w, "{i}{i}fontcolor={},", quote(color), i=indent)?;
+        }
+        writeln!(self.w, "{}];", indent)?;
+
+        let filter = erd.graph_options.attributes.unwrap_or(self.options.attributes);
+        for e in &erd.entities {
+            self.entity(&filter_attributes(e, filter, &[]))?;
+        }
+        for t in &erd.enums {
+            self.enumeration(t)?;
+        }
+        for r in erd.relationships.iter().chain(&erd.implied_relationships()) {
+            self.relationship(r)?;
+        }
+        for r in &erd.nary_relationships {
+            self.nary_relationship(r)?;
+        }
+        for (i, n) in erd.notes.iter().enumerate() {
+            self.note(n, i)?;
+        }
+
+        writeln!(self.w, "}}")
+    }
+
+    /// Draws an entity as a class with three compartments: its name, its
+    /// primary key attributes, and the rest of its attributes.
+    fn entity(&mut self, e: &ast::Entity) -> Result<()> {
+        let (keys, others): (Vec<_>, Vec<_>) = e.attribs.iter().partition(|a| e.is_primary_key(a));
+        let name = escape_html(e.header_options.label.as_deref().unwrap_or(&e.name));
+        self.class(&e.name, &format!("<B>{}</B>", name), &[
+            keys.iter().map(|a| attribute_line(a, true)).collect(),
+            others.iter().map(|a| attribute_line(a, false)).collect(),
+        ])
+    }
+
+    fn enumeration(&mut self, t: &ast::Enum) -> Result<()> {
+        let name = format!("«enumeration»<BR/><B>{}</B>", escape_html(&t.name));
+        self.class(&t.name, &name, &[t.values.iter().map(|v| escape_html(v)).collect()])
+    }
+
+    /// Writes a class box with the given name and compartments, each
+    /// holding lines of already escaped text.
+    fn class(&mut self, id: &str, name: &str, compartments: &[Vec<String>]) -> Result<()> {
+        let indent = &self.options.indent;
+        writeln!(self.w, "{}{} [", indent, quote(id))?;
+        writeln!(self.w, r#"{i}{i}label=<<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4">"#, i=indent)?;
+        writeln!(self.w, "{i}{i}<TR><TD>{}</TD></TR>", name, i=indent)?;
+        for lines in compartments {
+            let text: String = lines.iter().map(|l| format!(r#"{}<BR ALIGN="LEFT"/>"#, l)).collect();
+            writeln!(self.w, r#"{i}{i}<TR><TD ALIGN="LEFT">{}</TD></TR>"#, text, i=indent)?;
+        }
+        writeln!(self.w, "{i}{i}</TABLE>>\n{i}];", i=indent)
+    }
+
+    fn relationship(&mut self, r: &ast::Relation) -> Result<()> {
+        let mut attrs = match r.kind {
+            ast::RelationKind::Association => {
+                let mut attrs = vec![
+                    ("headlabel", quote(&multiplicity(r.card2))),
+                    ("taillabel", quote(&multiplicity(r.card1))),
+                ];
+                if r.identifying {
+                    attrs.push(("dir", "back".to_owned()));
+                    attrs.push(("arrowtail", "diamond".to_owned()));
+                }
+                attrs
+            },
+            ast::RelationKind::Inheritance => vec![
+                ("dir", "back".to_owned()),
+                ("arrowtail", "empty".to_owned()),
+            ],
+        };
+        // Attributes of the relationship are listed below its label, as
+        // there's no association class to hold them.
+        let lines: Vec<&str> = r.options.label.iter()
+            .map(String::as_str)
+            .chain(r.attribs.iter().map(|a| a.field.as_str()))
+            .collect();
+        if !lines.is_empty() {
+            attrs.push(("label", quote(&lines.join("\n")).replace('\n', "\\n")));
+        }
+        if let Some(color) = &r.options.color {
+            attrs.push(("color", quote(color)));
+        }
+        let attrs: Vec<String> = attrs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        writeln!(self.w, "{}{} -- {} [ {} ];",
+            self.options.indent, quote(&r.entity1), quote(&r.entity2), attrs.join(", "))
+    }
+
+    /// Draws an n-ary relationship as UML does n-ary associations, as a
+    /// diamond with a line to each entity.
+    fn nary_relationship(&mut self, r: &ast::NaryRelation) -> Result<()> {
+        let indent = &self.options.indent;
+        let id = quote(&r.name);
+        let label = r.options.label.as_deref().unwrap_or(&r.name);
+        writeln!(self.w, "{}{} [ shape=diamond, label=\"\", xlabel={} ];", indent, id, quote(label))?;
+        for e in &r.entities {
+            writeln!(self.w, "{}{} -- {};", indent, quote(e), id)?;
+        }
+        Ok(())
+    }
+
+    /// Draws a note with a dashed line to the entity it's attached to, if
+    /// any.
+    fn note(&mut self, n: &ast::Note, index: usize) -> Result<()> {
+        let indent = &self.options.indent;
+        let id = quote(&format!("note:{}", index));
+        writeln!(
+            self.w,
+            "{}{} [ shape=note, style=filled, fillcolor={}, label={} ];",
+            indent,
+            id,
+            quote(n.background_color.as_deref().unwrap_or(NOTE_COLOR)),
+            quote(&n.text).replace('\n', "\\n"),
+        )?;
+        if let Some(entity) = &n.attached {
+            writeln!(self.w, "{}{} -- {} [ style=dashed ];", indent, id, quote(entity))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns an attribute as a line of a class compartment, e.g.
+/// `customer_id: int {FK}`.
+fn attribute_line(a: &ast::Attribute, key: bool) -> String {
+    let mut line = escape_html(&a.field);
+    if let Some(t) = &a.datatype {
+        line.push_str(&format!(": {}", escape_html(t)));
+    }
+    // Attributes which may be null have at most one value.
+    if a.nullable {
+        line.push_str(" [0..1]");
+    }
+    let constraints = constraint_names(a, key);
+    if !constraints.is_empty() {
+        line.push_str(&format!(" {{{}}}", constraints.join(", ")));
+    }
+    line
+}
+
+/// Returns a cardinality as a UML multiplicity, e.g. `0..*`.
+fn multiplicity(card: ast::Cardinality) -> String {
+    match card {
+        ast::Cardinality::ZeroOne => "0..1".to_owned(),
+        ast::Cardinality::One => "1".to_owned(),
+        ast::Cardinality::ZeroPlus => "0..*".to_owned(),
+        ast::Cardinality::OnePlus => "1..*".to_owned(),
+        ast::Cardinality::Range { min, max: Some(max) } if min == max => min.to_string(),
+        ast::Cardinality::Range { min, max: Some(max) } => format!("{}..{}", min, max),
+        ast::Cardinality::Range { min, max: None } => format!("{}..*", min),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_erd;
+    use crate::render::Fmt;
+    use pretty_assertions::assert_eq;
+    use super::*;
+
+    #[test]
+    fn uml() {
+        let s = r#"
+enum status {values: "open, closed"}
+[customer]
+*id {type: "int"}
+email {unique: "true"}
+[order]
+*id
++customer_id -> customer.id {nullable: "true"}
+state {type: "status"}
+[[line]]
+*+order_id
+*n
+[vip]
+customer ^-- vip
+order 1==+ line
+customer (0,5)--(2,*) order {label: "places"}
+"#;
+        let erd = parse_erd(s).unwrap();
+        let mut out = String::new();
+        UmlRenderer::new(Fmt(&mut out)).render_erd(&erd).unwrap();
+        assert_eq!(out, r#"graph {
+    graph [
+        rankdir=LR,
+        splines=spline,
+    ];
+    node [
+        fontname="Helvetica",
+        shape=plaintext,
+    ];
+    edge [
+        color="gray50",
+        fontname="Helvetica",
+    ];
+    "customer" [
+        label=<<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4">
+        <TR><TD><B>customer</B></TD></TR>
+        <TR><TD ALIGN="LEFT">id: int {PK}<BR ALIGN="LEFT"/></TD></TR>
+        <TR><TD ALIGN="LEFT">email {unique}<BR ALIGN="LEFT"/></TD></TR>
+        </TABLE>>
+    ];
+    "order" [
+        label=<<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4">
+        <TR><TD><B>order</B></TD></TR>
+        <TR><TD ALIGN="LEFT">id {PK}<BR ALIGN="LEFT"/></TD></TR>
+        <TR><TD ALIGN="LEFT">customer_id [0..1] {FK}<BR ALIGN="LEFT"/>state: status<BR ALIGN="LEFT"/></TD></TR>
+        </TABLE>>
+    ];
+    "line" [
+        label=<<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4">
+        <TR><TD><B>line</B></TD></TR>
+        <TR><TD ALIGN="LEFT">order_id {PK, FK}<BR ALIGN="LEFT"/>n {PK}<BR ALIGN="LEFT"/></TD></TR>
+        <TR><TD ALIGN="LEFT"></TD></TR>
+        </TABLE>>
+    ];
+    "vip" [
+        label=<<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4">
+        <TR><TD><B>vip</B></TD></TR>
+        <TR><TD ALIGN="LEFT"></TD></TR>
+        <TR><TD ALIGN="LEFT"></TD></TR>
+        </TABLE>>
+    ];
+    "status" [
+        label=<<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4">
+        <TR><TD>«enumeration»<BR/><B>status</B></TD></TR>
+        <TR><TD ALIGN="LEFT">open<BR ALIGN="LEFT"/>closed<BR ALIGN="LEFT"/></TD></TR>
+        </TABLE>>
+    ];
+    "customer" -- "vip" [ dir=back, arrowtail=empty ];
+    "order" -- "line" [ headlabel="1..*", taillabel="1", dir=back, arrowtail=diamond ];
+    "customer" -- "order" [ headlabel="2..*", taillabel="0..5", label="places" ];
+}
+"#);
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashSet;
+use crate::ast::{Erd, Entity};
+use crate::error::{Diagnostic, DiagnosticKind};
+
+/// Settings which affect how strictly a diagram is checked.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidateOptions {
+    /// Report relationships with undeclared entities as warnings rather
+    /// than errors.
+    pub allow_unknown_entities: bool,
+}
+
+impl Erd {
+    /// Checks the diagram for semantic problems which don't prevent parsing,
+    /// returning a diagnostic for each problem found.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        self.validate_with(&ValidateOptions::default())
+    }
+
+    /// As [`validate`](Self::validate), using the given options.
+    pub fn validate_with(&self, options: &ValidateOptions) -> Vec<Diagnostic> {
+        let mut diags = Vec::new();
+        let unknown_entity = |entity: &str| {
+            let kind = DiagnosticKind::UnknownEntity {
+                entity: entity.to_owned(),
+                suggestion: self.closest_entity(entity).map(str::to_owned),
+            };
+            if options.allow_unknown_entities {
+                Diagnostic::warning(kind)
+            } else {
+                Diagnostic::error(kind)
+            }
+        };
+
+        for o in &self.ignored_options {
+            diags.push(Diagnostic::warning(DiagnosticKind::IgnoredOption {
+                scope: o.scope,
+                key: o.key.clone(),
+            }).at(o.span));
+        }
+
+        let mut names = HashSet::new();
+        for (i, e) in self.entities.iter().enumerate() {
+            if !names.insert(e.name.as_str()) {
+                let first = self.entities[..i].iter().find(|f| f.name == e.name).expect("seen before");
+                diags.push(Diagnostic::warning(DiagnosticKind::DuplicateEntity {
+                    entity: e.name.to_string(),
+                    first_line: first.span.line,
+                }).at(e.span));
+            }
+        }
+        let mut relationship_names = HashSet::new();
+        for r in &self.relationships {
+            if let Some(name) = &r.name {
+                if !relationship_names.insert(name.as_str()) {
+                    diags.push(Diagnostic::error(DiagnosticKind::DuplicateRelationship {
+                        name: name.clone(),
+                    }).at(r.span));
+                }
+            }
+            for name in &[&r.entity1, &r.entity2] {
+                if !names.contains(name.as_str()) {
+                    diags.push(unknown_entity(name).at(r.span));
+                }
+            }
+        }
+
+        for r in &self.nary_relationships {
+            if !relationship_names.insert(r.name.as_str()) {
+                diags.push(Diagnostic::error(DiagnosticKind::DuplicateRelationship {
+                    name: r.name.clone(),
+                }).at(r.span));
+            }
+            if names.contains(r.name.as_str()) {
+                diags.push(Diagnostic::error(DiagnosticKind::RelationshipNameConflict {
+                    name: r.name.clone(),
+                }).at(r.span));
+            }
+            for name in &r.entities {
+                if !names.contains(name.as_str()) {
+                    diags.push(unknown_entity(name).at(r.span));
+                }
+            }
+        }
+
+        let mut enum_names = HashSet::new();
+        for t in &self.enums {
+            if !enum_names.insert(t.name.as_str()) {
+                diags.push(Diagnostic::error(DiagnosticKind::DuplicateEnum {
+                    name: t.name.clone(),
+                }).at(t.span));
+            }
+            if names.contains(t.name.as_str()) {
+                diags.push(Diagnostic::error(DiagnosticKind::EnumNameConflict {
+                    name: t.name.clone(),
+                }).at(t.span));
+            }
+        }
+
+        for n in &self.notes {
+            if let Some(entity) = n.attached.as_ref().filter(|e| !names.contains(e.as_str())) {
+                diags.push(Diagnostic::warning(DiagnosticKind::UnknownNoteEntity {
+                    entity: entity.clone(),
+                }).at(n.span));
+            }
+        }
+
+        for e in &self.entities {
+            for a in &e.attribs {
+                let target = match &a.references {
+                    Some(target) => target,
+                    None => continue,
+                };
+                if self.entity(&target.entity).and_then(|t| t.attribute(&target.field)).is_none() {
+                    let kind = DiagnosticKind::UnknownReference {
+                        entity: e.name.to_string(),
+                        attribute: a.field.to_string(),
+                        target: format!("{}.{}", target.entity, target.field),
+                    };
+                    let diag = if options.allow_unknown_entities { Diagnostic::warning(kind) } else { Diagnostic::error(kind) };
+                    diags.push(diag.at(a.span));
+                }
+            }
+        }
+
+        for e in &self.entities {
+            validate_entity(e, &mut diags);
+            if e.weak && !self.relationships_for(&e.name).any(|r| r.identifying) {
+                diags.push(Diagnostic::warning(DiagnosticKind::UnidentifiedWeakEntity {
+                    entity: e.name.to_string(),
+                }).at(e.span));
+            }
+        }
+
+        diags
+    }
+
+    /// Returns the declared entity name most similar to `name`, if any is
+    /// close enough to be a likely misspelling.
+    fn closest_entity(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        let max = name.chars().count() / 3;
+        self.entities.iter()
+            .map(|e| (edit_distance(&name, &e.name.to_lowercase()), e.name.as_str()))
+            .filter(|(d, _)| *d <= max)
+            .min_by_key(|(d, _)| *d)
+            .map(|(_, e)| e)
+    }
+}
+
+/// Returns the number of single character insertions, deletions and
+/// substitutions needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            row.push(substitute.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+fn validate_entity(e: &Entity, diags: &mut Vec<Diagnostic>) {
+    if e.attribs.is_empty() {
+        diags.push(Diagnostic::warning(DiagnosticKind::EmptyEntity { entity: e.name.to_string() }).at(e.span));
+    }
+
+    for field in &e.primary_key {
+        if e.attribute(field).is_none() {
+            diags.push(Diagnostic::warning(DiagnosticKind::UnknownKeyAttribute {
+                entity: e.name.to_string(),
+                attribute: field.clone(),
+            }).at(e.span));
+        }
+    }
+
+    for index in &e.indexes {
+        for column in &index.columns {
+            if e.attribute(column).is_none() {
+                diags.push(Diagnostic::warning(DiagnosticKind::UnknownIndexColumn {
+                    entity: e.name.to_string(),
+                    column: column.clone(),
+                }).at(index.span));
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for a in &e.attribs {
+        if !seen.insert(a.field.as_str()) {
+            diags.push(Diagnostic::error(DiagnosticKind::DuplicateAttribute {
+                entity: e.name.to_string(),
+                attribute: a.field.to_string(),
+            }).at(a.span));
+        }
+    }
+
+    if e.options.border_color.is_some() && e.options.border == 0 {
+        diags.push(Diagnostic::warning(DiagnosticKind::ConflictingOptions {
+            element: format!("entity '{}'", e.name),
+            message: "border-color has no effect with a border of 0".to_owned(),
+        }).at(e.span));
+    }
+
+    for a in &e.attribs {
+        if a.options.border_color.is_some() && a.options.border == Some(0) {
+            diags.push(Diagnostic::warning(DiagnosticKind::ConflictingOptions {
+                element: format!("attribute '{}.{}'", e.name, a.field),
+                message: "border-color has no effect with a border of 0".to_owned(),
+            }).at(a.span));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_erd, parse_erd_with, ParseOptions};
+    use crate::error::Severity;
+    use super::*;
+
+    #[test]
+    fn valid() {
+        let erd = parse_erd(include_str!("../examples/nfldb.er")).unwrap();
+        assert_eq!(erd.validate(), vec![]);
+    }
+
+    #[test]
+    fn problems() {
+        let s = r#"
+[a] {border-color: "red"}
+*id
+*id
+name {border: "0", border-color: "blue"}
+index {columns: "name, email"}
+[[b]] {pk: "id"}
+rel x: a 1--* b
+rel x: a 1--* c
+x(a, b, d)
+b(a, b)
+"#;
+        let erd = parse_erd(s).unwrap();
+        let diags = erd.validate();
+        assert_eq!(diags, vec![
+            Diagnostic::error(DiagnosticKind::DuplicateRelationship { name: "x".to_owned() }),
+            Diagnostic::error(DiagnosticKind::UnknownEntity { entity: "c".to_owned(), suggestion: None }),
+            Diagnostic::error(DiagnosticKind::DuplicateRelationship { name: "x".to_owned() }),
+            Diagnostic::error(DiagnosticKind::UnknownEntity { entity: "d".to_owned(), suggestion: None }),
+            Diagnostic::error(DiagnosticKind::RelationshipNameConflict { name: "b".to_owned() }),
+            Diagnostic::warning(DiagnosticKind::UnknownIndexColumn {
+                entity: "a".to_owned(),
+                column: "email".to_owned(),
+            }),
+            Diagnostic::error(DiagnosticKind::DuplicateAttribute {
+                entity: "a".to_owned(),
+                attribute: "id".to_owned(),
+            }),
+            Diagnostic::warning(DiagnosticKind::ConflictingOptions {
+                element: "entity 'a'".to_owned(),
+                message: "border-color has no effect with a border of 0".to_owned(),
+            }),
+            Diagnostic::warning(DiagnosticKind::ConflictingOptions {
+                element: "attribute 'a.name'".to_owned(),
+                message: "border-color has no effect with a border of 0".to_owned(),
+            }),
+            Diagnostic::warning(DiagnosticKind::EmptyEntity { entity: "b".to_owned() }),
+            Diagnostic::warning(DiagnosticKind::UnknownKeyAttribute {
+                entity: "b".to_owned(),
+                attribute: "id".to_owned(),
+            }),
+            Diagnostic::warning(DiagnosticKind::UnidentifiedWeakEntity { entity: "b".to_owned() }),
+        ]);
+        assert_eq!(diags[6].severity, Severity::Error);
+        assert_eq!(diags[6].to_string(), "error: entity 'a' declares attribute 'id' more than once");
+        let lines: Vec<_> = diags.iter().map(|d| d.span.line).collect();
+        assert_eq!(lines, vec![9, 9, 10, 10, 11, 6, 4, 2, 5, 7, 7, 7]);
+    }
+
+    #[test]
+    fn unknown_entities() {
+        let erd = parse_erd("[user]\nid\n[Order]\nid\nusr 1--* order\nuser 1--* invoice\n").unwrap();
+        let diags = erd.validate();
+        let messages: Vec<_> = diags.iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "error: relationship refers to undeclared entity 'usr' (did you mean 'user'?)",
+            "error: relationship refers to undeclared entity 'order' (did you mean 'Order'?)",
+            "error: relationship refers to undeclared entity 'invoice'",
+        ]);
+
+        let options = ValidateOptions { allow_unknown_entities: true };
+        assert!(erd.validate_with(&options).iter().all(|d| d.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn duplicate_entities() {
+        let erd = parse_erd("[a]\nx\n[b]\ny\n[a]\nz\n").unwrap();
+        let diags = erd.validate();
+        assert_eq!(diags, vec![
+            Diagnostic::warning(DiagnosticKind::DuplicateEntity { entity: "a".to_owned(), first_line: 1 }),
+        ]);
+        assert_eq!(diags[0].span.line, 5);
+        assert_eq!(diags[0].to_string(), "warning: entity 'a' was already declared on line 1");
+    }
+
+    #[test]
+    fn enums() {
+        let erd = parse_erd("enum a {values: \"x\"}\nenum b {values: \"x\"}\nenum b {values: \"y\"}\n[a]\nid\n").unwrap();
+        let messages: Vec<_> = erd.validate().iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "error: enum 'a' has the same name as an entity",
+            "error: more than one enum is named 'b'",
+        ]);
+    }
+
+    #[test]
+    fn references() {
+        let erd = parse_erd("[a]\n*id\n[b]\n+a_id -> a.id\n+c_id -> c.id\n+a_key -> a.key\n").unwrap();
+        let messages: Vec<_> = erd.validate().iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "error: attribute 'b.c_id' refers to undeclared field 'c.id'",
+            "error: attribute 'b.a_key' refers to undeclared field 'a.key'",
+        ]);
+    }
+
+    #[test]
+    fn notes() {
+        let erd = parse_erd("[a]\nid\nnote \"x\" {attached: \"a\"}\nnote \"y\" {attached: \"b\"}\nnote \"z\"\n").unwrap();
+        let messages: Vec<_> = erd.validate().iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec!["warning: note is attached to undeclared entity 'b'"]);
+    }
+
+    #[test]
+    fn ignored_options() {
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        let erd = parse_erd_with("[a]
+x {glow: \"1\"}
+", &options).unwrap();
+        let diags = erd.validate();
+        assert_eq!(diags, vec![
+            Diagnostic::warning(DiagnosticKind::IgnoredOption { scope: "attribute", key: "glow".to_owned() }),
+        ]);
+        assert_eq!(diags[0].span.line, 2);
+        assert_eq!(diags[0].to_string(), "warning: ignored unknown attribute option: glow");
+    }
+}
@@ -0,0 +1,60 @@
+use std::{fs, thread, time::{Duration, SystemTime}};
+
+/// How often watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Calls `f` each time any of the given files is modified, created or
+/// removed. Never returns.
+pub fn watch<F: FnMut()>(paths: &[String], mut f: F) -> ! {
+    let mut last = modified(paths);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        poll(paths, &mut last, &mut f);
+    }
+}
+
+/// Checks the given files once, calling `f` if any have changed since their
+/// modification times were last recorded in `last`. Returns whether they had.
+fn poll<F: FnMut()>(paths: &[String], last: &mut Vec<Option<SystemTime>>, f: &mut F) -> bool {
+    let current = modified(paths);
+    if current == *last {
+        return false;
+    }
+    *last = current;
+    f();
+    true
+}
+
+fn modified(paths: &[String]) -> Vec<Option<SystemTime>> {
+    paths.iter()
+        .map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use super::*;
+
+    #[test]
+    fn poll_changes() {
+        let path = std::env::temp_dir().join(format!("erd-watch-{}.er", std::process::id()));
+        fs::write(&path, "[Person]").unwrap();
+        let paths = vec![path.to_string_lossy().into_owned()];
+        let mut last = modified(&paths);
+        let mut runs = 0;
+
+        assert!(!poll(&paths, &mut last, &mut || runs += 1));
+        assert_eq!(runs, 0);
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(poll(&paths, &mut last, &mut || runs += 1));
+        assert!(!poll(&paths, &mut last, &mut || runs += 1));
+        assert_eq!(runs, 1);
+
+        fs::remove_file(&path).unwrap();
+        assert!(poll(&paths, &mut last, &mut || runs += 1));
+        assert_eq!(runs, 2);
+    }
+}